@@ -0,0 +1,259 @@
+//! run many conversions listed in a TOML or JSON manifest on many threads,
+//! reporting each job's result without letting one bad job stop the rest
+use crate::{run_convert, ConvertConfig, FilesRead, OutputFormat};
+use crate::languages::atom_format::{AtomFormat, DontCare, TextConfig};
+use crate::languages::seq_value_to_st_value_and_valid_strings::StreamConfig;
+use crate::formats::readmem::{LaneOrder, Radix, ReadmemConfig};
+use crate::formats::vcd::VcdConfig;
+use crate::formats::parse_json_or_toml;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::panic;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Instant;
+
+/// A list of conversions. Relative paths are relative to the manifest's directory.
+///
+/// In TOML, each job is a `[[jobs]]` table:
+///
+/// ```toml
+/// threads = 4
+///
+/// [[jobs]]
+/// name = "conv2d_in"
+/// values = "conv2d/in_values.pb"
+/// type = "conv2d/in_type.pb"
+/// format = "csv"
+/// out = "conv2d/in.csv"
+/// dont_care = "x"
+/// ```
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    /// The number of jobs to run at once. If missing, one per available thread.
+    #[serde(default)]
+    pub threads: Option<usize>,
+    pub jobs: Vec<ConvertJob>
+}
+
+/// A conversion in a manifest, with the options of `convert` on the command line
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConvertJob {
+    /// The name of the job in the report. If missing, the job's values file.
+    #[serde(default)]
+    pub name: Option<String>,
+    pub values: String,
+    /// If missing, the values must be a container
+    #[serde(rename = "type", default)]
+    pub st_type: Option<String>,
+    #[serde(default = "default_format")]
+    pub format: String,
+    pub out: String,
+    #[serde(default)]
+    pub valids_out: Option<String>,
+    #[serde(default)]
+    pub interleave: bool,
+    /// Convert the values as a Seq of frames, one after another
+    #[serde(default)]
+    pub frames: bool,
+    #[serde(default)]
+    pub latency: u32,
+    #[serde(default)]
+    pub frame_gap: u32,
+    /// Split tuple atoms into one stream per field
+    #[serde(default)]
+    pub split_tuples: bool,
+    /// The threads to convert this job on. If missing, 1, since the jobs already run in parallel.
+    #[serde(default)]
+    pub threads: Option<usize>,
+    #[serde(default = "default_atom_format")]
+    pub atom_format: String,
+    #[serde(default)]
+    pub dont_care: Option<String>,
+    #[serde(default = "default_radix")]
+    pub radix: String,
+    #[serde(default = "default_lane_order")]
+    pub lane_order: String
+}
+
+fn default_format() -> String { String::from("text") }
+fn default_atom_format() -> String { String::from("rust") }
+fn default_radix() -> String { String::from("hex") }
+fn default_lane_order() -> String { String::from("lsb") }
+
+impl ConvertJob {
+    /// Get the job's name in the report
+    pub fn name(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.values)
+    }
+
+    /// Get the conversion's options, with relative paths resolved against `dir`
+    pub fn to_convert_config(&self, dir: &Path) -> Result<ConvertConfig, Box<dyn Error>> {
+        let unknown = |option: &str, name: &str, names: &[&str]| -> Box<dyn Error> {
+            format!("unknown {} {}, expected one of {}", option, name, names.join(", ")).into()
+        };
+        let resolve = |path: &str| dir.join(path).to_string_lossy().to_string();
+        let dont_care = match &self.dont_care {
+            Some(name) => Some(DontCare::from_name(name)
+                .ok_or_else(|| unknown("dont_care", name, &DontCare::NAMES))?),
+            None => None
+        };
+        Ok(ConvertConfig {
+            sequence_values_proto_path: resolve(&self.values),
+            space_time_type_proto_path: self.st_type.as_deref().map(resolve),
+            output_format: OutputFormat::from_name(&self.format)
+                .ok_or_else(|| unknown("format", &self.format, &OutputFormat::NAMES))?,
+            output_values_path: resolve(&self.out),
+            output_valids_path: self.valids_out.as_deref().map(resolve),
+            interleave: self.interleave,
+            stream: if self.frames {
+                Some(StreamConfig { latency: self.latency, frame_gap: self.frame_gap })
+            } else {
+                None
+            },
+            split_tuples: self.split_tuples,
+            threads: Some(self.threads.unwrap_or(1)),
+            text: TextConfig {
+                atom_format: AtomFormat::from_name(&self.atom_format)
+                    .ok_or_else(|| unknown("atom_format", &self.atom_format, &AtomFormat::NAMES))?,
+                dont_care
+            },
+            readmem: ReadmemConfig {
+                radix: Radix::from_name(&self.radix)
+                    .ok_or_else(|| unknown("radix", &self.radix, &Radix::NAMES))?,
+                lane_order: LaneOrder::from_name(&self.lane_order)
+                    .ok_or_else(|| unknown("lane_order", &self.lane_order, &LaneOrder::NAMES))?,
+                dont_care: dont_care.is_some()
+            },
+            vcd: VcdConfig { dont_care: dont_care.is_some(), ..VcdConfig::default() }
+        })
+    }
+}
+
+/// Parse a manifest. Files ending in `.toml` are TOML, files ending in `.json` are JSON,
+/// and other files are JSON if they start with `{` and TOML otherwise.
+///
+/// # Examples
+///
+/// ```
+/// use aetherling::batch::parse_manifest;
+/// let manifest = parse_manifest(r#"{"jobs": [{"values": "v.pb", "type": "t.pb", "out": "o.csv",
+///                                             "format": "csv"}]}"#, "jobs.json").unwrap();
+///
+/// assert_eq!(manifest.jobs[0].format, "csv");
+/// assert_eq!(manifest.jobs[0].name(), "v.pb");
+/// ```
+pub fn parse_manifest(text: &str, path: &str) -> Result<Manifest, Box<dyn Error>> {
+    parse_json_or_toml(text, path, "manifest")
+}
+
+/// The result of one job
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct JobReport {
+    pub name: String,
+    pub ok: bool,
+    /// Why the job failed
+    pub error: Option<String>,
+    pub seconds: f64
+}
+
+/// Run a manifest's jobs, `threads` at a time, and report each job's result
+/// in the manifest's order. A job that fails, even by panicking, doesn't stop the others.
+pub fn run_manifest(manifest: &Manifest, dir: &Path, threads: usize) -> Vec<JobReport> {
+    let next_job = AtomicUsize::new(0);
+    let reports: Mutex<Vec<Option<JobReport>>> = Mutex::new(vec![None; manifest.jobs.len()]);
+    thread::scope(|scope| {
+        for _ in 0..std::cmp::max(1, threads) {
+            scope.spawn(|| loop {
+                let idx = next_job.fetch_add(1, Ordering::SeqCst);
+                let job = match manifest.jobs.get(idx) {
+                    Some(job) => job,
+                    None => break
+                };
+                let report = run_job(job, dir);
+                reports.lock().unwrap()[idx] = Some(report);
+            });
+        }
+    });
+    reports.into_inner().unwrap().into_iter().map(|r| r.expect("every job runs")).collect()
+}
+
+fn run_job(job: &ConvertJob, dir: &Path) -> JobReport {
+    let start = Instant::now();
+    // a job that hits a bug, such as an arithmetic overflow, panics,
+    // so catch panics to keep them in this job
+    let result = panic::catch_unwind(|| {
+        job.to_convert_config(dir)
+            .and_then(|conf| run_convert(conf, false, &mut FilesRead::default()))
+            .map_err(|e| e.to_string())
+    }).unwrap_or_else(|panic| Err(match panic.downcast_ref::<&str>() {
+        Some(msg) => format!("panicked: {}", msg),
+        None => match panic.downcast_ref::<String>() {
+            Some(msg) => format!("panicked: {}", msg),
+            None => String::from("panicked")
+        }
+    }));
+    JobReport {
+        name: job.name().to_string(),
+        ok: result.is_ok(),
+        error: result.err(),
+        seconds: start.elapsed().as_secs_f64()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::languages::space_time::serialize::save_type;
+    use crate::languages::space_time::types::Type;
+    use crate::languages::sequence::serialize_values::save_value;
+    use crate::tests::in_temp_dir;
+    use std::fs;
+
+    #[test]
+    fn test_toml_manifest_defaults() {
+        let manifest = parse_manifest("[[jobs]]\nvalues = \"v.pb\"\ntype = \"t.pb\"\nout = \"o.txt\"\n\
+                                       valids_out = \"valids.txt\"\n", "jobs.toml").unwrap();
+        assert_eq!(manifest.threads, None);
+        let conf = manifest.jobs[0].to_convert_config(Path::new("runs")).unwrap();
+        assert_eq!(conf.output_format, OutputFormat::Text);
+        assert_eq!(conf.output_valids_path, Some(Path::new("runs").join("valids.txt").to_string_lossy().to_string()));
+    }
+
+    #[test]
+    fn test_unknown_option_names() {
+        let manifest = parse_manifest("[[jobs]]\nvalues = \"v.pb\"\ntype = \"t.pb\"\nout = \"o\"\nformat = \"png\"\n",
+                                      "jobs.toml").unwrap();
+        assert_eq!(manifest.jobs[0].to_convert_config(Path::new(".")).unwrap_err().to_string(),
+                   "unknown format png, expected one of text, csv, vcd, readmem, npy");
+    }
+
+    #[test]
+    fn test_bad_jobs_dont_stop_good_jobs() {
+        let st_type = save_type(&Type::TSeq {n: 2, i: 0, elem_type: Box::from(Type::UInt8)});
+        let values = save_value(&vec!(1, 2));
+        let files: [(&str, &[u8]); 5] = [("t.pb", &st_type), ("v.pb", &values), ("bad.pb", &[0xff, 0xff, 0xff]),
+                                         ("one.json", b"[1]"), ("huge.txt", b"TSeq 1 4294967295 UInt8")];
+        let manifest = parse_manifest(r#"{"jobs": [
+            {"name": "good", "values": "v.pb", "type": "t.pb", "format": "csv", "out": "good.csv"},
+            {"name": "missing", "values": "nope.pb", "type": "t.pb", "format": "csv", "out": "missing.csv"},
+            {"name": "corrupt", "values": "bad.pb", "type": "t.pb", "format": "csv", "out": "corrupt.csv"},
+            {"name": "huge", "values": "one.json", "type": "huge.txt", "format": "csv", "out": "huge.csv"},
+            {"name": "good2", "values": "v.pb", "type": "t.pb", "format": "vcd", "out": "good.vcd"}
+        ]}"#, "jobs.json").unwrap();
+        let (reports, good_csv) = in_temp_dir("batch", &files, |dir| {
+            (run_manifest(&manifest, dir, 2), fs::read_to_string(dir.join("good.csv")).unwrap())
+        });
+        let oks: Vec<(&str, bool)> = reports.iter().map(|r| (r.name.as_str(), r.ok)).collect();
+        assert_eq!(oks, vec!(("good", true), ("missing", false), ("corrupt", false), ("huge", false),
+                            ("good2", true)));
+        assert!(reports[1].error.as_ref().unwrap().starts_with("couldn't read"));
+        assert!(reports[2].error.as_ref().unwrap().starts_with("bad protobuf value"));
+        assert!(reports[3].error.as_ref().unwrap().ends_with("has too many clocks or lanes"));
+        assert_eq!(good_csv, "clock,valid,lane_0\n0,true,1\n1,true,2\n");
+    }
+}
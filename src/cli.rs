@@ -0,0 +1,611 @@
+//! parse the `aetherling` binary's command line into a `Config`
+use crate::{BatchConfig, CheckConfig, Command, Config, ConvertConfig, ExportConfig, ExportSink,
+            GenConfig, GenValuesConfig, Harness, ImageExport, ImportConfig, ImportSource,
+            InspectConfig, OutputFormat, PortFiles, RoundtripConfig};
+use crate::languages::atom_format::{AtomFormat, DontCare, TextConfig};
+use crate::languages::type_files::TypeLanguage;
+use crate::languages::sequence::gen_values::Pattern;
+use crate::formats::readmem::{LaneOrder, Radix, ReadmemConfig};
+use crate::formats::vcd::VcdConfig;
+use crate::formats::fault::FaultConfig;
+use crate::formats::testbench::TestbenchConfig;
+use crate::formats::pnm::{ImageLayout, RenderConfig, SampleMapping};
+use crate::formats::y4m::{ChromaFormat, ChromaLayout, VideoConfig};
+use crate::formats::wav::AudioConfig;
+use crate::languages::seq_value_to_st_value_and_valid_strings::StreamConfig;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use std::ffi::OsString;
+
+const EXIT_CODES: &str = "EXIT CODES:
+    0    the command ran and its checks passed
+    1    the command ran and a check failed
+    2    the command couldn't run, such as for bad arguments or a missing file";
+
+/// The `aetherling` binary's command line interface
+pub fn app() -> App<'static, 'static> {
+    let values_arg = Arg::with_name("values").long("values").value_name("FILE").takes_value(true)
+        .help("A protobuf Sequence value");
+    let type_arg = Arg::with_name("type").long("type").value_name("FILE").takes_value(true)
+        .help("A protobuf Space-Time type");
+    let port_arg = |name: &'static str, help: &'static str| Arg::with_name(name).long(name)
+        .value_name("NAME=VALUES:TYPE").takes_value(true).multiple(true).number_of_values(1)
+        .help(help);
+
+    App::new("aetherling")
+        .about("Convert Aetherling Sequence values to Space-Time values and test circuits with them")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .setting(AppSettings::VersionlessSubcommands)
+        .after_help(EXIT_CODES)
+        .arg(Arg::with_name("verbose").short("v").long("verbose").global(true)
+            .help("Print what the command did to stderr"))
+        .subcommand(SubCommand::with_name("convert")
+            .about("Convert a Sequence value to a Space-Time value and write it in a file format")
+            .arg(values_arg.clone().required(true)
+                .help("A Sequence value as protobuf, JSON, text such as `[(1, True)]`, or a container \
+                       starting with a `#aetherling TYPE` line, or - for stdin"))
+            .arg(type_arg.clone()
+                .help("A Space-Time or Sequence type as protobuf, JSON, or text, or - for stdin. \
+                       Defaults to the values' container header."))
+            .arg(Arg::with_name("format").short("f").long("format").takes_value(true)
+                .possible_values(&OutputFormat::NAMES).default_value("text")
+                .help("The output file format"))
+            .arg(Arg::with_name("out").short("o").long("out").value_name("FILE").takes_value(true)
+                .required(true).help("The values file, or - for stdout"))
+            .arg(Arg::with_name("valids-out").long("valids-out").value_name("FILE").takes_value(true)
+                .help("The valids file, for the text, readmem, and npy formats, or - for stdout"))
+            .arg(Arg::with_name("interleave").long("interleave").conflicts_with("valids-out")
+                .help("Write the valids in the values file. The text format prints one clock per line."))
+            .arg(Arg::with_name("frames").long("frames").requires("type")
+                .help("The values are a Seq of frames, such as an imported video. \
+                       Convert each frame with the type, one after another."))
+            .arg(Arg::with_name("latency").long("latency").takes_value(true).requires("frames")
+                .help("The invalid clocks before the first frame"))
+            .arg(Arg::with_name("frame-gap").long("frame-gap").takes_value(true).requires("frames")
+                .help("The invalid clocks between frames"))
+            .arg(Arg::with_name("threads").short("j").long("threads").takes_value(true)
+                .help("The threads to convert on. Defaults to one per available thread."))
+            .arg(Arg::with_name("split-tuples").long("split-tuples")
+                .help("Split tuple atoms into one stream per field, such as lane_left_right. \
+                       The text, readmem, and npy formats write each field to a file named with its suffix, \
+                       such as out_left.hex."))
+            .arg(Arg::with_name("atom-format").long("atom-format").takes_value(true)
+                .possible_values(&AtomFormat::NAMES).default_value("rust")
+                .help("How to print atoms in the text and csv formats"))
+            .arg(Arg::with_name("dont-care").long("dont-care").takes_value(true)
+                .possible_values(&DontCare::NAMES)
+                .help("Print this marker for the atoms of invalid clocks. \
+                       The vcd and readmem formats print x for any marker."))
+            .arg(Arg::with_name("radix").long("radix").takes_value(true)
+                .possible_values(&Radix::NAMES).default_value("hex")
+                .help("The digits of the readmem format"))
+            .arg(Arg::with_name("lane-order").long("lane-order").takes_value(true)
+                .possible_values(&LaneOrder::NAMES).default_value("lsb")
+                .help("Whether lane 0 is in the least or most significant bits of readmem words")))
+        .subcommand(SubCommand::with_name("check")
+            .about("Compare a simulation's output CSV table against an expected CSV table \
+                    on the expected valid clocks")
+            .arg(Arg::with_name("expected").long("expected").value_name("FILE").takes_value(true)
+                .required(true).help("The expected table, from `convert --format csv`"))
+            .arg(Arg::with_name("actual").long("actual").value_name("FILE").takes_value(true)
+                .required(true).help("The simulation's table")))
+        .subcommand(SubCommand::with_name("inspect")
+            .about("Print the normalized form, widths, clocks, and port layout of a type")
+            .arg(Arg::with_name("type").long("type").value_name("FILE").takes_value(true).required(true)
+                .help("A Space-Time or Sequence type, as protobuf, JSON, or text such as `TSeq 2 1 UInt8`"))
+            .arg(Arg::with_name("seq").long("seq").help("Read a protobuf type as a Sequence type"))
+            .arg(Arg::with_name("json").long("json").help("Print everything as JSON")))
+        .subcommand(SubCommand::with_name("gen")
+            .about("Generate a test harness that drives a circuit's inputs and checks its outputs")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(harness_subcommand("fault", "A Python test with the fault library")
+                .arg(Arg::with_name("module").long("module").takes_value(true).default_value("circuit")
+                    .help("The Python module to import the circuit from"))
+                .arg(Arg::with_name("target").long("target").takes_value(true).default_value("verilator")
+                    .help("The fault simulator target"))
+                .arg(Arg::with_name("out").short("o").long("out").value_name("FILE").takes_value(true)
+                    .required(true).help("The test file"))
+                .arg(port_arg("input", "An input port, with its value and type files"))
+                .arg(port_arg("output", "An output port, with its value and type files")))
+            .subcommand(harness_subcommand("testbench", "A self-checking SystemVerilog testbench")
+                .arg(Arg::with_name("module-name").long("module-name").takes_value(true).default_value("tb")
+                    .help("The name of the testbench module"))
+                .arg(Arg::with_name("out").short("o").long("out").value_name("DIR").takes_value(true)
+                    .required(true).help("The directory for the testbench and its memory files"))
+                .arg(port_arg("input", "An input port, with its value and type files"))
+                .arg(port_arg("output", "An output port, with its value and type files")))
+            .subcommand(SubCommand::with_name("values")
+                .about("A Sequence value of a type, with every atom in its type's range")
+                .arg(Arg::with_name("type").long("type").value_name("FILE").takes_value(true).required(true)
+                    .help("A Sequence or Space-Time type, as protobuf, JSON, or text, or - for stdin"))
+                .arg(Arg::with_name("seq").long("seq").help("Read a protobuf type as a Sequence type"))
+                .arg(Arg::with_name("pattern").short("p").long("pattern").takes_value(true)
+                    .possible_values(&PATTERNS).default_value("random")
+                    .help("How to choose the atoms"))
+                .arg(Arg::with_name("seed").long("seed").takes_value(true).default_value("0")
+                    .help("The random pattern's seed"))
+                .arg(Arg::with_name("start").long("start").takes_value(true).default_value("0")
+                    .allow_hyphen_values(true).help("The ramp pattern's first atom"))
+                .arg(Arg::with_name("step").long("step").takes_value(true).default_value("1")
+                    .allow_hyphen_values(true).help("The ramp pattern's step between atoms"))
+                .arg(Arg::with_name("value").long("value").takes_value(true)
+                    .allow_hyphen_values(true).required_if("pattern", "constant")
+                    .help("The constant pattern's atom"))
+                .arg(Arg::with_name("container").long("container")
+                    .help("Start the protobuf with a `#aetherling TYPE` header"))
+                .arg(Arg::with_name("out").short("o").long("out").value_name("FILE").takes_value(true)
+                    .required(true).help("The protobuf value file, or - for stdout"))))
+        .subcommand(SubCommand::with_name("roundtrip")
+            .about("Load and save a Sequence value and a Space-Time type, and check they're unchanged")
+            .arg(values_arg.required_unless("type"))
+            .arg(type_arg))
+        .subcommand(SubCommand::with_name("import")
+            .about("Read a media file as a Sequence value")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(import_subcommand("image", "A PGM or PPM image, as P2, P3, P5, or P6, \
+                                                    with RGB pixels as ATuple r (ATuple g b)")
+                .arg(Arg::with_name("layout").long("layout").takes_value(true)
+                    .possible_values(&ImageLayout::NAMES).default_value("rows")
+                    .help("Nest the pixels as Seq height (Seq width pixel), or flatten them to \
+                           Seq (height * width) pixel")))
+            .subcommand(import_subcommand("video", "A Y4M video, as a Seq of frames")
+                .arg(Arg::with_name("chroma").long("chroma").takes_value(true)
+                    .possible_values(&ChromaLayout::NAMES).default_value("luma")
+                    .help("Read the luma plane, the chroma planes as ATuple u v pixels, or every \
+                           pixel as ATuple y (ATuple u v)"))
+                .arg(Arg::with_name("layout").long("layout").takes_value(true)
+                    .possible_values(&ImageLayout::NAMES).default_value("rows")
+                    .help("Nest each frame's pixels as Seq height (Seq width pixel), or flatten them to \
+                           Seq (height * width) pixel")))
+            .subcommand(import_subcommand("audio", "A PCM WAV file, as a Seq of samples. \
+                                                    Stereo samples are ATuple left right."))
+            .subcommand(import_subcommand("raw", "A raw buffer, such as a .bin capture, as a layout descriptor says")
+                .arg(Arg::with_name("descriptor").long("descriptor").value_name("FILE").takes_value(true)
+                    .required(true).help("A TOML or JSON layout with the width, height, channels, bits, signed, \
+                                          endian, stride, planar, and frames of the buffer"))
+                .arg(Arg::with_name("layout").long("layout").takes_value(true)
+                    .possible_values(&ImageLayout::NAMES).default_value("rows")
+                    .help("Nest each frame's pixels as Seq height (Seq width pixel), or flatten them to \
+                           Seq (height * width) pixel"))))
+        .subcommand(SubCommand::with_name("export")
+            .about("Write a Sequence value, such as a circuit's output, as a media file")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(export_subcommand("image", "A PGM or PPM image, with ATuple r (ATuple g b) atoms \
+                                                    as RGB pixels and other atoms as gray pixels")
+                .arg(Arg::with_name("width").long("width").takes_value(true)
+                    .help("The image's width. Defaults to W of a Seq H (Seq W pixel) type."))
+                .arg(Arg::with_name("height").long("height").takes_value(true)
+                    .help("The image's height. Defaults to H of a Seq H (Seq W pixel) type."))
+                .arg(Arg::with_name("max-val").long("max-val").takes_value(true).default_value("255")
+                    .help("The largest sample. Above 255 writes a 16-bit image."))
+                .arg(Arg::with_name("mapping").long("mapping").takes_value(true)
+                    .possible_values(&SampleMapping::NAMES).default_value("clamp")
+                    .help("Clamp atoms to 0 through the max value, or scale the smallest and largest atoms to them"))
+                .arg(Arg::with_name("ascii").long("ascii").help("Write a P2 or P3 image"))
+                .arg(Arg::with_name("reference").long("reference").value_name("FILE").takes_value(true)
+                    .requires("diff-out").help("A reference value or PGM/PPM image to compare against"))
+                .arg(Arg::with_name("diff-out").long("diff-out").value_name("FILE").takes_value(true)
+                    .requires("reference").help("The image of the difference from the reference")))
+            .subcommand(export_subcommand("video", "A Y4M video, from a Seq of frames")
+                .arg(Arg::with_name("width").long("width").takes_value(true)
+                    .help("The video's width. Defaults to W of a Seq F (Seq H (Seq W pixel)) type."))
+                .arg(Arg::with_name("height").long("height").takes_value(true)
+                    .help("The video's height. Defaults to H of a Seq F (Seq H (Seq W pixel)) type."))
+                .arg(Arg::with_name("chroma").long("chroma").takes_value(true)
+                    .possible_values(&ChromaLayout::NAMES).default_value("luma")
+                    .help("Whether each frame's pixels are luma, ATuple u v chroma, or ATuple y (ATuple u v). \
+                           Missing planes are mid-gray."))
+                .arg(Arg::with_name("chroma-format").long("chroma-format").takes_value(true)
+                    .possible_values(&ChromaFormat::NAMES).default_value("420")
+                    .help("How the video's chroma planes are subsampled"))
+                .arg(Arg::with_name("bit-depth").long("bit-depth").takes_value(true).default_value("8")
+                    .help("The bits of each sample, 8 or from 9 to 16")))
+            .subcommand(export_subcommand("audio", "A PCM WAV file, from a Seq of samples or of ATuple left right")
+                .arg(Arg::with_name("sample-rate").long("sample-rate").takes_value(true).default_value("44100")
+                    .help("The samples per second in each channel"))
+                .arg(Arg::with_name("bits").long("bits").takes_value(true)
+                    .possible_values(&["8", "16", "32"]).default_value("16")
+                    .help("The bits of each sample. 8-bit samples are unsigned."))
+                .arg(Arg::with_name("channels").long("channels").takes_value(true).possible_values(&["1", "2"])
+                    .help("Mono or stereo. Defaults to stereo if the samples are tuples.")))
+            .subcommand(export_subcommand("raw", "A raw buffer, laid out as a layout descriptor says")
+                .arg(Arg::with_name("descriptor").long("descriptor").value_name("FILE").takes_value(true)
+                    .required(true).help("A TOML or JSON layout with the width, height, channels, bits, signed, \
+                                          endian, stride, planar, and frames of the buffer"))))
+        .subcommand(SubCommand::with_name("batch")
+            .about("Run the conversions in a TOML or JSON manifest in parallel. \
+                    A failed job doesn't stop the others, but fails the batch.")
+            .arg(Arg::with_name("manifest").long("manifest").value_name("FILE").takes_value(true)
+                .required(true).help("The manifest, with paths relative to its directory"))
+            .arg(Arg::with_name("threads").short("j").long("threads").takes_value(true)
+                .help("The number of jobs to run at once. Defaults to the manifest's, \
+                       or the number of available threads."))
+            .arg(Arg::with_name("report").long("report").value_name("FILE").takes_value(true)
+                .help("Write every job's result as JSON")))
+}
+
+/// The names of the `gen values` patterns
+const PATTERNS: [&str; 5] = ["random", "ramp", "constant", "checkerboard", "index"];
+
+/// The options shared by all the media file readers
+fn import_subcommand(name: &'static str, about: &'static str) -> App<'static, 'static> {
+    SubCommand::with_name(name).about(about)
+        .arg(Arg::with_name("in").short("i").long("in").value_name("FILE").takes_value(true).required(true)
+            .help("The media file, or - for stdin"))
+        .arg(Arg::with_name("out").short("o").long("out").value_name("FILE").takes_value(true)
+            .required(true).help("The protobuf value file, or - for stdout"))
+        .arg(Arg::with_name("container").long("container")
+            .help("Start the protobuf with a `#aetherling TYPE` header"))
+        .arg(Arg::with_name("type-out").long("type-out").value_name("FILE").takes_value(true)
+            .help("Write the value's Sequence type as text"))
+}
+
+/// The options shared by all the media file writers
+fn export_subcommand(name: &'static str, about: &'static str) -> App<'static, 'static> {
+    SubCommand::with_name(name).about(about)
+        .arg(Arg::with_name("values").long("values").value_name("FILE").takes_value(true).required(true)
+            .help("A Sequence value as protobuf, JSON, text, or a container, or - for stdin"))
+        .arg(Arg::with_name("type").long("type").value_name("FILE").takes_value(true)
+            .help("The value's Sequence or Space-Time type. Defaults to the values' container header."))
+        .arg(Arg::with_name("out").short("o").long("out").value_name("FILE").takes_value(true)
+            .required(true).help("The media file, or - for stdout"))
+}
+
+/// The options shared by all the test harnesses
+fn harness_subcommand(name: &'static str, about: &'static str) -> App<'static, 'static> {
+    SubCommand::with_name(name).about(about)
+        .arg(Arg::with_name("circuit").long("circuit").takes_value(true).default_value("Top")
+            .help("The name of the circuit under test"))
+        .arg(Arg::with_name("clock").long("clock").takes_value(true).default_value("CLK")
+            .help("The circuit's clock port"))
+        .arg(Arg::with_name("valid-in").long("valid-in").takes_value(true).default_value("valid_up")
+            .help("The circuit's input valid port, or `none`"))
+        .arg(Arg::with_name("valid-out").long("valid-out").takes_value(true).default_value("valid_down")
+            .help("The circuit's output valid port, or `none`"))
+        .arg(Arg::with_name("latency").long("latency").takes_value(true).default_value("0")
+            .help("The clocks between the first input and the first output"))
+}
+
+/// Parse a command line, including the binary's name, into a `Config`
+///
+/// # Examples
+///
+/// ```
+/// use aetherling::cli::parse_args;
+/// use aetherling::{Command, OutputFormat};
+/// let conf = parse_args(vec!("aetherling", "convert", "--values", "v.pb", "--type", "t.pb",
+///                            "--format", "csv", "-o", "out.csv")).unwrap();
+///
+/// match conf.command {
+///     Command::Convert(convert_conf) => assert_eq!(convert_conf.output_format, OutputFormat::Csv),
+///     _ => panic!("expected convert")
+/// }
+/// ```
+pub fn parse_args<I, T>(args: I) -> Result<Config, clap::Error>
+    where I: IntoIterator<Item=T>, T: Into<OsString> + Clone {
+    let matches = app().get_matches_from_safe(args)?;
+    let command = match matches.subcommand() {
+        ("convert", Some(m)) => Command::Convert(parse_convert(m)?),
+        ("check", Some(m)) => Command::Check(CheckConfig {
+            expected_csv_path: value(m, "expected"),
+            actual_csv_path: value(m, "actual")
+        }),
+        ("inspect", Some(m)) => Command::Inspect(InspectConfig {
+            type_path: value(m, "type"),
+            protobuf_language: if m.is_present("seq") { TypeLanguage::Seq } else { TypeLanguage::SpaceTime },
+            json: m.is_present("json")
+        }),
+        ("gen", Some(m)) => match m.subcommand() {
+            ("values", Some(vm)) => Command::GenValues(parse_gen_values(vm)?),
+            _ => Command::Gen(parse_gen(m)?)
+        },
+        ("roundtrip", Some(m)) => Command::Roundtrip(RoundtripConfig {
+            sequence_values_proto_path: m.value_of("values").map(String::from),
+            space_time_type_proto_path: m.value_of("type").map(String::from)
+        }),
+        ("import", Some(m)) => Command::Import(parse_import(m)?),
+        ("export", Some(m)) => Command::Export(parse_export(m)?),
+        ("batch", Some(m)) => Command::Batch(BatchConfig {
+            manifest_path: value(m, "manifest"),
+            threads: match m.value_of("threads") {
+                Some(_) => Some(value_t(m, "threads")? as usize),
+                None => None
+            },
+            report_path: m.value_of("report").map(String::from)
+        }),
+        _ => unreachable!("clap requires a subcommand")
+    };
+    Ok(Config { command, verbose: matches.is_present("verbose") })
+}
+
+fn value(m: &ArgMatches, name: &str) -> String {
+    m.value_of(name).map(String::from).unwrap_or_default()
+}
+
+fn parse_convert(m: &ArgMatches) -> Result<ConvertConfig, clap::Error> {
+    // clap only allows the possible values, so the names all parse
+    let dont_care = m.value_of("dont-care").and_then(DontCare::from_name);
+    let output_format = OutputFormat::from_name(&value(m, "format")).unwrap();
+    let interleave = m.is_present("interleave");
+    if interleave && !matches!(output_format, OutputFormat::Text | OutputFormat::Csv | OutputFormat::Vcd) {
+        return Err(clap::Error::with_description(
+            &format!("the {} format can't interleave the valids", value(m, "format")),
+            clap::ErrorKind::ArgumentConflict));
+    }
+    if output_format.has_valids_file() && !interleave && !m.is_present("valids-out") {
+        return Err(clap::Error::with_description(
+            &format!("the {} format needs --valids-out or --interleave", value(m, "format")),
+            clap::ErrorKind::MissingRequiredArgument));
+    }
+    Ok(ConvertConfig {
+        sequence_values_proto_path: value(m, "values"),
+        space_time_type_proto_path: m.value_of("type").map(String::from),
+        output_format,
+        output_values_path: value(m, "out"),
+        output_valids_path: m.value_of("valids-out").map(String::from),
+        interleave,
+        stream: if m.is_present("frames") {
+            Some(StreamConfig {
+                latency: if m.is_present("latency") { value_t(m, "latency")? } else { 0 },
+                frame_gap: if m.is_present("frame-gap") { value_t(m, "frame-gap")? } else { 0 }
+            })
+        } else {
+            None
+        },
+        split_tuples: m.is_present("split-tuples"),
+        threads: match m.value_of("threads") {
+            Some(_) => Some(value_t(m, "threads")? as usize),
+            None => None
+        },
+        text: TextConfig { atom_format: AtomFormat::from_name(&value(m, "atom-format")).unwrap(), dont_care },
+        readmem: ReadmemConfig {
+            radix: Radix::from_name(&value(m, "radix")).unwrap(),
+            lane_order: LaneOrder::from_name(&value(m, "lane-order")).unwrap(),
+            dont_care: dont_care.is_some()
+        },
+        vcd: VcdConfig { dont_care: dont_care.is_some(), ..VcdConfig::default() }
+    })
+}
+
+fn parse_gen(m: &ArgMatches) -> Result<GenConfig, clap::Error> {
+    let (kind, hm) = match m.subcommand() {
+        (kind, Some(hm)) => (kind, hm),
+        _ => unreachable!("clap requires a subcommand")
+    };
+    let optional_port = |name: &str| match value(hm, name).as_str() {
+        "none" => None,
+        port => Some(String::from(port))
+    };
+    let output_latency = value_t(hm, "latency")?;
+    let harness = match kind {
+        "fault" => Harness::Fault(FaultConfig {
+            circuit_module: value(hm, "module"),
+            circuit_name: value(hm, "circuit"),
+            clock_name: value(hm, "clock"),
+            valid_in_name: optional_port("valid-in"),
+            valid_out_name: optional_port("valid-out"),
+            output_latency,
+            target: value(hm, "target")
+        }),
+        _ => Harness::Testbench(TestbenchConfig {
+            module_name: value(hm, "module-name"),
+            dut_name: value(hm, "circuit"),
+            clock_name: value(hm, "clock"),
+            valid_in_name: optional_port("valid-in"),
+            valid_out_name: optional_port("valid-out"),
+            output_latency,
+            ..TestbenchConfig::default()
+        })
+    };
+    Ok(GenConfig {
+        harness,
+        inputs: port_files(hm, "input")?,
+        outputs: port_files(hm, "output")?,
+        output_path: value(hm, "out")
+    })
+}
+
+fn parse_import(m: &ArgMatches) -> Result<ImportConfig, clap::Error> {
+    let (kind, im) = match m.subcommand() {
+        (kind, Some(im)) => (kind, im),
+        _ => unreachable!("clap requires a subcommand")
+    };
+    // clap only allows the possible values, so the names all parse
+    let layout = || ImageLayout::from_name(&value(im, "layout")).unwrap();
+    let source = match kind {
+        "video" => ImportSource::Video(ChromaLayout::from_name(&value(im, "chroma")).unwrap(), layout()),
+        "audio" => ImportSource::Audio,
+        "raw" => ImportSource::Raw { descriptor_path: value(im, "descriptor"), layout: layout() },
+        _ => ImportSource::Image(layout())
+    };
+    Ok(ImportConfig {
+        source,
+        input_path: value(im, "in"),
+        output_path: value(im, "out"),
+        container: im.is_present("container"),
+        type_output_path: im.value_of("type-out").map(String::from)
+    })
+}
+
+fn parse_export(m: &ArgMatches) -> Result<ExportConfig, clap::Error> {
+    let (kind, em) = match m.subcommand() {
+        (kind, Some(em)) => (kind, em),
+        _ => unreachable!("clap requires a subcommand")
+    };
+    let optional_u32 = |name: &str| if em.is_present(name) { value_t(em, name) } else { Ok(0) };
+    let sink = if kind == "raw" {
+        ExportSink::Raw { descriptor_path: value(em, "descriptor") }
+    } else if kind == "audio" {
+        ExportSink::Audio(AudioConfig {
+            sample_rate: value_t(em, "sample-rate")?,
+            // clap only allows 1 or 2 channels and 8, 16, or 32 bits, so they fit
+            channels: if em.is_present("channels") { value_t(em, "channels")? as u16 } else { 0 },
+            bits: value_t(em, "bits")? as u16
+        })
+    } else if kind == "video" {
+        ExportSink::Video(VideoConfig {
+            width: optional_u32("width")?,
+            height: optional_u32("height")?,
+            chroma_format: ChromaFormat::from_name(&value(em, "chroma-format")).unwrap(),
+            bit_depth: value_t(em, "bit-depth")?,
+            chroma_layout: ChromaLayout::from_name(&value(em, "chroma")).unwrap(),
+            ..VideoConfig::default()
+        })
+    } else {
+        ExportSink::Image(ImageExport {
+            render: RenderConfig {
+                width: optional_u32("width")?,
+                height: optional_u32("height")?,
+                max_val: value_t(em, "max-val")?,
+                mapping: SampleMapping::from_name(&value(em, "mapping")).unwrap()
+            },
+            ascii: em.is_present("ascii"),
+            reference_path: em.value_of("reference").map(String::from),
+            diff_output_path: em.value_of("diff-out").map(String::from)
+        })
+    };
+    Ok(ExportConfig {
+        sink,
+        values_path: value(em, "values"),
+        type_path: em.value_of("type").map(String::from),
+        output_path: value(em, "out")
+    })
+}
+
+fn parse_gen_values(m: &ArgMatches) -> Result<GenValuesConfig, clap::Error> {
+    let integer = |name: &str| value(m, name).parse::<i64>().map_err(|_| clap::Error::value_validation_auto(
+        format!("--{} must be an integer", name)));
+    let pattern = match value(m, "pattern").as_str() {
+        "random" => Pattern::Random {
+            seed: value(m, "seed").parse().map_err(|_| clap::Error::value_validation_auto(
+                String::from("--seed must be a non-negative integer")))?
+        },
+        "ramp" => Pattern::Ramp { start: integer("start")?, step: integer("step")? },
+        "constant" => Pattern::Constant(integer("value")?),
+        "checkerboard" => Pattern::Checkerboard,
+        _ => Pattern::Index
+    };
+    Ok(GenValuesConfig {
+        type_path: value(m, "type"),
+        protobuf_language: if m.is_present("seq") { TypeLanguage::Seq } else { TypeLanguage::SpaceTime },
+        pattern,
+        container: m.is_present("container"),
+        output_path: value(m, "out")
+    })
+}
+
+fn value_t(m: &ArgMatches, name: &str) -> Result<u32, clap::Error> {
+    value(m, name).parse().map_err(|_| clap::Error::value_validation_auto(
+        format!("--{} must be a non-negative integer", name)))
+}
+
+/// Parse `NAME=VALUES:TYPE` port arguments
+fn port_files(m: &ArgMatches, name: &str) -> Result<Vec<PortFiles>, clap::Error> {
+    m.values_of(name).map_or(Ok(Vec::new()), |ports| ports.map(|port| {
+        let parsed = port.split_once('=').and_then(|(port_name, files)| {
+            files.rsplit_once(':').map(|(values, st_type)| PortFiles {
+                name: String::from(port_name),
+                sequence_values_proto_path: String::from(values),
+                space_time_type_proto_path: String::from(st_type)
+            })
+        });
+        parsed.ok_or_else(|| clap::Error::value_validation_auto(
+            format!("--{} {} isn't NAME=VALUES:TYPE", name, port)))
+    }).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert_needs_valids_file_for_text() {
+        let err = parse_args(vec!("aetherling", "convert", "--values", "v.pb", "--type", "t.pb",
+                                  "-o", "vals.txt")).unwrap_err();
+        assert_eq!(err.kind, clap::ErrorKind::MissingRequiredArgument);
+    }
+
+    #[test]
+    fn test_convert_interleave() {
+        let conf = parse_args(vec!("aetherling", "convert", "--values", "-", "-o", "-", "--interleave")).unwrap();
+        match conf.command {
+            Command::Convert(convert_conf) => {
+                assert!(convert_conf.interleave);
+                assert_eq!(convert_conf.space_time_type_proto_path, None);
+            }
+            _ => panic!("expected convert")
+        }
+        let err = parse_args(vec!("aetherling", "convert", "--values", "v.pb", "-f", "npy",
+                                  "-o", "vals.npy", "--interleave")).unwrap_err();
+        assert_eq!(err.kind, clap::ErrorKind::ArgumentConflict);
+    }
+
+    #[test]
+    fn test_convert_frames() {
+        let conf = parse_args(vec!("aetherling", "convert", "--values", "frames.pb", "--type", "frame_t.pb",
+                                   "-o", "out.txt", "--valids-out", "valids.txt", "--frames",
+                                   "--frame-gap", "2")).unwrap();
+        match conf.command {
+            Command::Convert(convert_conf) =>
+                assert_eq!(convert_conf.stream, Some(StreamConfig {latency: 0, frame_gap: 2})),
+            _ => panic!("expected convert")
+        }
+        let err = parse_args(vec!("aetherling", "convert", "--values", "frames.pb", "-o", "-",
+                                  "--interleave", "--frames")).unwrap_err();
+        assert_eq!(err.kind, clap::ErrorKind::MissingRequiredArgument);
+    }
+
+    #[test]
+    fn test_gen_fault_ports() {
+        let conf = parse_args(vec!("aetherling", "-v", "gen", "fault", "-o", "test.py", "--valid-in", "none",
+                                   "--input", "I0=in0.pb:in0_t.pb", "--input", "I1=in1.pb:in1_t.pb",
+                                   "--output", "O=out.pb:out_t.pb", "--latency", "3")).unwrap();
+        assert!(conf.verbose);
+        match conf.command {
+            Command::Gen(gen_conf) => {
+                assert_eq!(gen_conf.inputs.len(), 2);
+                assert_eq!(gen_conf.inputs[1].sequence_values_proto_path, "in1.pb");
+                assert_eq!(gen_conf.outputs[0].space_time_type_proto_path, "out_t.pb");
+                match gen_conf.harness {
+                    Harness::Fault(fault_conf) => {
+                        assert_eq!(fault_conf.output_latency, 3);
+                        assert_eq!(fault_conf.valid_in_name, None);
+                    }
+                    _ => panic!("expected a fault harness")
+                }
+            }
+            _ => panic!("expected gen")
+        }
+    }
+
+    #[test]
+    fn test_gen_values_pattern() {
+        let conf = parse_args(vec!("aetherling", "gen", "values", "--type", "t.txt", "-p", "ramp",
+                                   "--start", "-5", "--step", "2", "-o", "-")).unwrap();
+        match conf.command {
+            Command::GenValues(values_conf) => assert_eq!(values_conf.pattern, Pattern::Ramp {start: -5, step: 2}),
+            _ => panic!("expected gen values")
+        }
+    }
+
+    #[test]
+    fn test_audio_import_and_export() {
+        let conf = parse_args(vec!("aetherling", "import", "audio", "-i", "clip.wav", "-o", "-")).unwrap();
+        match conf.command {
+            Command::Import(import_conf) => assert_eq!(import_conf.source, ImportSource::Audio),
+            _ => panic!("expected import")
+        }
+        let conf = parse_args(vec!("aetherling", "export", "audio", "--values", "-", "-o", "out.wav",
+                                   "--bits", "8")).unwrap();
+        match conf.command {
+            Command::Export(export_conf) => assert_eq!(export_conf.sink, ExportSink::Audio(
+                AudioConfig {sample_rate: 44100, channels: 0, bits: 8})),
+            _ => panic!("expected export")
+        }
+    }
+
+    #[test]
+    fn test_bad_port_argument() {
+        let err = parse_args(vec!("aetherling", "gen", "testbench", "-o", "tb", "--input", "I0")).unwrap_err();
+        assert_eq!(err.kind, clap::ErrorKind::ValueValidation);
+    }
+}
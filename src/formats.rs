@@ -0,0 +1,70 @@
+use crate::languages::seq_value_to_st_value_and_valid_strings::STAtomsAndValids;
+use crate::languages::ports::STPort;
+use crate::languages::space_time::types::Type;
+use serde::de::DeserializeOwned;
+use std::error::Error;
+
+pub mod vcd;
+pub mod readmem;
+pub mod csv;
+pub mod npy;
+pub mod fault;
+pub mod testbench;
+pub mod pnm;
+pub mod y4m;
+pub mod wav;
+pub mod raw;
+
+/// Parse a JSON or TOML file, such as a manifest or a layout descriptor, named `what` in errors.
+/// Files ending in `.json` are JSON, files ending in `.toml` are TOML,
+/// and other files are JSON if they start with `{` and TOML otherwise.
+pub(crate) fn parse_json_or_toml<T: DeserializeOwned>(text: &str, path: &str, what: &str) -> Result<T, Box<dyn Error>> {
+    let is_json = if path.ends_with(".json") {
+        true
+    } else if path.ends_with(".toml") {
+        false
+    } else {
+        text.trim_start().starts_with('{')
+    };
+    if is_json {
+        serde_json::from_str(text).map_err(|e| format!("bad JSON {} {}: {}", what, path, e).into())
+    } else {
+        toml::from_str(text).map_err(|e| format!("bad TOML {} {}: {}", what, path, e).into())
+    }
+}
+
+/// A Space-Time value and the names of its signals, for formats that hold
+/// the signals of multiple ports side by side
+pub(crate) struct NamedSTValue<'a> {
+    pub valid_name: String,
+    pub lane_prefix: String,
+    pub st_type: &'a Type,
+    pub st_vals: &'a STAtomsAndValids
+}
+
+impl<'a> NamedSTValue<'a> {
+    /// Name the signals of a value that is alone in a file `valid` and `lane_<n>`
+    pub fn single(st_vals: &'a STAtomsAndValids, st_type: &'a Type) -> NamedSTValue<'a> {
+        NamedSTValue {
+            valid_name: String::from("valid"),
+            lane_prefix: String::from("lane"),
+            st_type,
+            st_vals
+        }
+    }
+
+    /// Name the signals of a port `<port>_valid` and `<port>_<n>`
+    pub fn port(port: &'a STPort) -> NamedSTValue<'a> {
+        NamedSTValue {
+            valid_name: format!("{}_valid", port.name),
+            lane_prefix: port.name.clone(),
+            st_type: &port.st_type,
+            st_vals: &port.st_vals
+        }
+    }
+
+    /// Get the name of a lane's field, such as `lane_1_left`
+    pub fn lane_name(&self, lane: u32, field_suffix: &str) -> String {
+        format!("{}_{}{}", self.lane_prefix, lane, field_suffix)
+    }
+}
@@ -0,0 +1,247 @@
+//! write a Space-Time value as a CSV table with one row per clock and one column
+//! per lane, so spreadsheets and pandas can load it directly
+use crate::languages::sequence::serialize_values::SerializableSeqValue;
+use crate::languages::seq_value_to_st_value_and_valid_strings::{
+    convert_seq_val_to_st_atoms_and_valids, STAtomsAndValids
+};
+use crate::languages::space_time::types::Type;
+use crate::languages::atom_format::{is_dont_care, TextConfig};
+use crate::languages::ports::{check_same_clocks, STPort};
+use super::NamedSTValue;
+use std::error::Error;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+/// Convert a Sequence value to a Space-Time value and write it as a CSV table
+pub fn convert_seq_val_to_st_csv<T: SerializableSeqValue, W: Write>(
+    seq_val: T, st_type: &Type, conf: &TextConfig, sink: &mut W) -> Result<(), Box<dyn Error>> {
+    let st_vals = convert_seq_val_to_st_atoms_and_valids(seq_val, st_type);
+    write_st_csv(&st_vals, st_type, conf, sink)
+}
+
+/// Write a Space-Time value as a CSV table.
+/// The header is `clock,valid,lane_0,...,lane_<n>`. Tuple atoms are split into one
+/// column per field, such as `lane_0_left` and `lane_0_right`.
+/// The valids and atoms are printed with `conf`, so the lanes of invalid clocks
+/// can be don't-care markers.
+///
+/// # Examples
+///
+/// ```
+/// use aetherling::formats::csv::convert_seq_val_to_st_csv;
+/// use aetherling::languages::atom_format::TextConfig;
+/// use aetherling::languages::space_time::types::Type;
+/// let mut builder = Vec::new();
+/// convert_seq_val_to_st_csv(vec!(1, 3), &Type::TSeq {n: 1, i: 1, elem_type: Box::from(
+///                               Type::SSeq {n: 2, elem_type: Box::from(Type::UInt8)})},
+///                           &TextConfig::default(), &mut builder).unwrap();
+///
+/// assert_eq!(String::from_utf8(builder).unwrap(),
+///            "clock,valid,lane_0,lane_1\n0,true,1,3\n1,false,0,0\n");
+/// ```
+pub fn write_st_csv<W: Write>(st_vals: &STAtomsAndValids, st_type: &Type, conf: &TextConfig,
+                              sink: &mut W) -> Result<(), Box<dyn Error>> {
+    write_named_st_vals_csv(&[NamedSTValue::single(st_vals, st_type)], conf, sink)
+}
+
+/// Write the Space-Time values of a module's ports as one CSV table with a shared clock column.
+/// Each port has a `<port>_valid` column and `<port>_<n>` columns for its lanes.
+pub fn write_st_ports_csv<W: Write>(st_ports: &[STPort], conf: &TextConfig,
+                                    sink: &mut W) -> Result<(), Box<dyn Error>> {
+    check_same_clocks(st_ports.iter().map(|p| (p.name.as_str(), &p.st_type)))?;
+    let named_vals: Vec<NamedSTValue> = st_ports.iter().map(NamedSTValue::port).collect();
+    write_named_st_vals_csv(&named_vals, conf, sink)
+}
+
+fn write_named_st_vals_csv<W: Write>(named_vals: &[NamedSTValue], conf: &TextConfig,
+                                     sink: &mut W) -> Result<(), Box<dyn Error>> {
+    let mut writer = ::csv::Writer::from_writer(sink);
+
+    let mut header = vec!(String::from("clock"));
+    for named_val in named_vals {
+        header.push(named_val.valid_name.clone());
+        let fields = named_val.st_type.atom_type().atom_fields();
+        for lane in 0..named_val.st_type.atoms_per_valid() {
+            for (suffix, _) in fields.iter() {
+                header.push(named_val.lane_name(lane, suffix));
+            }
+        }
+    }
+    writer.write_record(&header)?;
+
+    let total_time = named_vals.first().map_or(0, |v| v.st_vals.valids.len());
+    for t in 0..total_time {
+        let mut row = vec!(t.to_string());
+        for named_val in named_vals {
+            let valid = named_val.st_vals.valids[t];
+            row.push(conf.format_valid(valid));
+            let fields = named_val.st_type.atom_type().atom_fields();
+            for atom in named_val.st_vals.atoms[t].iter() {
+                row.extend(atom.leaves().iter().zip(fields.iter())
+                    .map(|(leaf, (_, field_type))| conf.format_lane(leaf, field_type, valid)));
+            }
+        }
+        writer.write_record(&row)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// A cell that differs between an expected and an actual CSV table
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mismatch {
+    pub clock: usize,
+    pub column: String,
+    pub expected: String,
+    pub actual: String
+}
+
+/// Compare a simulation's output, as a CSV table, against an expected CSV table written by
+/// `write_st_csv` or `write_st_ports_csv`. The actual table must have all of the expected
+/// table's columns, printed in the same atom format, and may have more.
+/// A lane is only compared on the clocks where its valid column is true, and an expected
+/// don't-care marker matches any atom. Valid columns are always compared.
+/// Returns every mismatching cell, with a missing clock reported in the `clock` column.
+///
+/// # Examples
+///
+/// ```
+/// use aetherling::formats::csv::check_st_csv;
+/// let expected = "clock,valid,lane_0\n0,true,1\n1,false,x\n";
+/// let actual = "clock,valid,lane_0\n0,true,2\n1,false,7\n";
+/// let mismatches = check_st_csv(expected.as_bytes(), actual.as_bytes()).unwrap();
+///
+/// assert_eq!(mismatches.len(), 1);
+/// assert_eq!((mismatches[0].clock, mismatches[0].column.as_str()), (0, "lane_0"));
+/// ```
+pub fn check_st_csv<E: Read, A: Read>(expected: E, actual: A) -> Result<Vec<Mismatch>, Box<dyn Error>> {
+    let mut expected_reader = ::csv::Reader::from_reader(expected);
+    let mut actual_reader = ::csv::Reader::from_reader(actual);
+    let expected_header = expected_reader.headers()?.clone();
+    let actual_columns: HashMap<String, usize> = actual_reader.headers()?.iter()
+        .enumerate().map(|(idx, name)| (name.to_string(), idx)).collect();
+
+    // for each expected column, its index in the actual table and the expected column with its valid
+    let mut columns: Vec<(usize, usize, Option<usize>)> = Vec::new();
+    for (idx, name) in expected_header.iter().enumerate() {
+        if name == "clock" {
+            continue;
+        }
+        let actual_idx = *actual_columns.get(name)
+            .ok_or_else(|| format!("actual table has no column {}", name))?;
+        columns.push((idx, actual_idx, valid_column_of(&expected_header, name)));
+    }
+
+    let mut mismatches = Vec::new();
+    let mut actual_rows = actual_reader.records();
+    for (clock, expected_row) in expected_reader.records().enumerate() {
+        let expected_row = expected_row?;
+        let actual_row = match actual_rows.next() {
+            Some(row) => row?,
+            None => {
+                mismatches.push(Mismatch {
+                    clock, column: String::from("clock"),
+                    expected: clock.to_string(), actual: String::new()
+                });
+                break;
+            }
+        };
+        for (expected_idx, actual_idx, valid_idx) in columns.iter() {
+            let expected_cell = &expected_row[*expected_idx];
+            let actual_cell = actual_row.get(*actual_idx).unwrap_or("");
+            let matches = match valid_idx {
+                // a valid column
+                Some(v) if v == expected_idx => is_true(expected_cell) == is_true(actual_cell),
+                Some(v) if !is_true(&expected_row[*v]) => true,
+                _ => is_dont_care(expected_cell) || expected_cell == actual_cell
+            };
+            if !matches {
+                mismatches.push(Mismatch {
+                    clock, column: expected_header[*expected_idx].to_string(),
+                    expected: expected_cell.to_string(), actual: actual_cell.to_string()
+                });
+            }
+        }
+    }
+    Ok(mismatches)
+}
+
+/// Find the valid column of a column: itself if it's a valid column,
+/// `valid` for `lane_<n>`, or `<port>_valid` for `<port>_<n>`
+fn valid_column_of(header: &::csv::StringRecord, name: &str) -> Option<usize> {
+    let position = |valid_name: &str| header.iter().position(|c| c == valid_name);
+    if name == "valid" || name.ends_with("_valid") {
+        return position(name);
+    }
+    if name.starts_with("lane_") {
+        if let Some(idx) = position("valid") {
+            return Some(idx);
+        }
+    }
+    // the longest port name prefix wins, in case one port's name is a prefix of another's
+    header.iter().enumerate()
+        .filter_map(|(idx, c)| c.strip_suffix("_valid").map(|port| (idx, port)))
+        .filter(|(_, port)| name.starts_with(&format!("{}_", port)))
+        .max_by_key(|(_, port)| port.len())
+        .map(|(idx, _)| idx)
+}
+
+fn is_true(cell: &str) -> bool {
+    cell == "1" || cell.eq_ignore_ascii_case("true")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::languages::ports::{convert_seq_ports_to_st_ports, SeqPort};
+    use crate::languages::atom_format::{AtomFormat, DontCare};
+
+    #[test]
+    fn test_csv_tuple_columns() {
+        let mut builder = Vec::new();
+        convert_seq_val_to_st_csv(vec!((-1, true), (2, false)),
+                                  &Type::SSeq {n: 2, elem_type: Box::from(
+                                      Type::ATuple {left: Box::from(Type::Int8),
+                                                    right: Box::from(Type::Bit)})},
+                                  &TextConfig::default(), &mut builder).unwrap();
+        assert_eq!(String::from_utf8(builder).unwrap(),
+                   "clock,valid,lane_0_left,lane_0_right,lane_1_left,lane_1_right\n\
+                    0,true,-1,true,2,false\n");
+    }
+
+    #[test]
+    fn test_csv_ports() {
+        let st_ports = convert_seq_ports_to_st_ports(vec!(
+            SeqPort {name: String::from("in0"), seq_val: vec!(1, 3),
+                     st_type: Type::TSeq {n: 2, i: 0, elem_type: Box::from(Type::UInt8)}},
+            SeqPort {name: String::from("in1"), seq_val: vec!(2, 4),
+                     st_type: Type::TSeq {n: 1, i: 1, elem_type: Box::from(
+                         Type::SSeq {n: 2, elem_type: Box::from(Type::UInt8)})}}
+        )).unwrap();
+        let mut builder = Vec::new();
+        let conf = TextConfig {atom_format: AtomFormat::Hex, dont_care: Some(DontCare::Empty)};
+        write_st_ports_csv(&st_ports, &conf, &mut builder).unwrap();
+        assert_eq!(String::from_utf8(builder).unwrap(),
+                   "clock,in0_valid,in0_0,in1_valid,in1_0,in1_1\n\
+                    0,1,01,1,02,04\n\
+                    1,1,03,0,,\n");
+    }
+
+    #[test]
+    fn test_check_ports_by_valid() {
+        let expected = "clock,in0_valid,in0_0,in01_valid,in01_0\n0,1,01,0,05\n1,1,x,1,03\n";
+        let actual = "clock,in01_0,in0_0,in0_valid,in01_valid,extra\n0,06,01,1,0,9\n1,04,02,1,1,9\n";
+        let mismatches = check_st_csv(expected.as_bytes(), actual.as_bytes()).unwrap();
+        assert_eq!(mismatches, vec!(Mismatch {clock: 1, column: String::from("in01_0"),
+                                               expected: String::from("03"), actual: String::from("04")}));
+    }
+
+    #[test]
+    fn test_check_missing_clocks_and_valids() {
+        let expected = "clock,valid,lane_0\n0,true,1\n1,true,2\n";
+        let actual = "clock,valid,lane_0\n0,false,1\n";
+        let mismatches = check_st_csv(expected.as_bytes(), actual.as_bytes()).unwrap();
+        let columns: Vec<(usize, &str)> = mismatches.iter().map(|m| (m.clock, m.column.as_str())).collect();
+        assert_eq!(columns, vec!((0, "valid"), (1, "clock")));
+    }
+}
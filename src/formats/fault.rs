@@ -0,0 +1,245 @@
+//! write a self-contained Python test that drives a Magma circuit with the
+//! `fault` library, poking the inputs' Space-Time values and expecting the outputs'
+use crate::languages::sequence::serialize_values::SerializableSeqValue;
+use crate::languages::atom_format::{format_atom, AtomFormat};
+use crate::languages::ports::{check_same_clocks, convert_seq_ports_to_st_ports, SeqPort, STPort};
+use crate::languages::space_time::types::Type;
+use std::error::Error;
+use std::io::Write;
+
+/// The options for a generated `fault` test
+#[derive(Debug, Clone, PartialEq)]
+pub struct FaultConfig {
+    /// The Python module to import the circuit from
+    pub circuit_module: String,
+    /// The name of the Magma circuit in `circuit_module`
+    pub circuit_name: String,
+    /// The circuit's clock port
+    pub clock_name: String,
+    /// An input port poked with the input ports' valids, such as Aetherling's `valid_up`.
+    /// Every input port must have the same valids. With no input ports, it's poked high every clock.
+    pub valid_in_name: Option<String>,
+    /// An output port that is expected high on the outputs' valid clocks, such as Aetherling's `valid_down`
+    pub valid_out_name: Option<String>,
+    /// The number of clocks between poking the first input and the first output clock
+    pub output_latency: u32,
+    /// The `fault` simulator target
+    pub target: String
+}
+
+impl Default for FaultConfig {
+    fn default() -> Self {
+        FaultConfig {
+            circuit_module: String::from("circuit"),
+            circuit_name: String::from("Top"),
+            clock_name: String::from("CLK"),
+            valid_in_name: Some(String::from("valid_up")),
+            valid_out_name: Some(String::from("valid_down")),
+            output_latency: 0,
+            target: String::from("verilator")
+        }
+    }
+}
+
+/// Convert the Sequence values on a circuit's input and output ports to Space-Time values
+/// and write a `fault` test for the circuit
+pub fn convert_seq_ports_to_fault_test<T: SerializableSeqValue, U: SerializableSeqValue, W: Write>(
+    inputs: Vec<SeqPort<T>>, outputs: Vec<SeqPort<U>>, conf: &FaultConfig,
+    sink: &mut W) -> Result<(), Box<dyn Error>> {
+    let st_inputs = convert_seq_ports_to_st_ports(inputs)?;
+    let st_outputs = convert_seq_ports_to_st_ports(outputs)?;
+    write_fault_test(&st_inputs, &st_outputs, conf, sink)
+}
+
+/// Write a `fault` test that pokes every input port's lanes and valid each clock, steps the clock,
+/// and expects every output port's lanes only on the outputs' valid clocks.
+/// A lane's atom is the port's element at the lane's index in each `SSeq` and `STuple`,
+/// outermost first, and a tuple atom's fields are its elements `0` and `1`.
+///
+/// # Examples
+///
+/// ```
+/// use aetherling::formats::fault::{convert_seq_ports_to_fault_test, FaultConfig};
+/// use aetherling::languages::ports::SeqPort;
+/// use aetherling::languages::space_time::types::Type;
+/// let st_type = Type::TSeq {n: 1, i: 1, elem_type: Box::from(
+///     Type::SSeq {n: 2, elem_type: Box::from(Type::UInt8)})};
+/// let mut builder = Vec::new();
+/// convert_seq_ports_to_fault_test(
+///     vec!(SeqPort {name: String::from("I"), seq_val: vec!(1, 3), st_type: st_type.clone()}),
+///     vec!(SeqPort {name: String::from("O"), seq_val: vec!(2, 4), st_type: st_type.clone()}),
+///     &FaultConfig::default(), &mut builder).unwrap();
+/// let test = String::from_utf8(builder).unwrap();
+///
+/// assert!(test.contains("\"I\": ([(0,), (1,)], [[1, 3], [0, 0]])"));
+/// assert!(test.contains("\"O\": ([(0,), (1,)], [[2, 4], [0, 0]])"));
+/// assert!(test.contains("I_VALIDS = [True, False]"));
+/// assert!(test.contains("tester.circuit.valid_up = int(clk < INPUT_CLOCKS and I_VALIDS[clk])"));
+/// assert!(test.contains("O_VALIDS = [True, False]"));
+/// ```
+pub fn write_fault_test<W: Write>(inputs: &[STPort], outputs: &[STPort], conf: &FaultConfig,
+                                  sink: &mut W) -> Result<(), Box<dyn Error>> {
+    check_same_clocks(inputs.iter().map(|p| (p.name.as_str(), &p.st_type)))?;
+    check_same_clocks(outputs.iter().map(|p| (p.name.as_str(), &p.st_type)))?;
+    let input_valids = inputs.first().map_or(Vec::new(), |p| p.st_vals.valids.clone());
+    if let Some(port) = inputs.iter().find(|p| p.st_vals.valids != input_valids) {
+        return Err(format!("input {} has different valids than input {}", port.name, inputs[0].name).into());
+    }
+    let input_clocks = input_valids.len();
+    let output_valids = outputs.first().map_or(Vec::new(), |p| p.st_vals.valids.clone());
+    let total_clocks = std::cmp::max(input_clocks, conf.output_latency as usize + output_valids.len());
+
+    writeln!(sink, "# generated by aetherling from Sequence values and Space-Time types")?;
+    writeln!(sink, "import fault")?;
+    writeln!(sink, "from {} import {}", conf.circuit_module, conf.circuit_name)?;
+    writeln!(sink)?;
+    writeln!(sink, "# port name: (the index path of each lane's field, the fields' values on each clock)")?;
+    write_ports_dict(sink, "INPUTS", inputs)?;
+    write_ports_dict(sink, "OUTPUTS", outputs)?;
+    writeln!(sink, "I_VALIDS = {}", python_list(input_valids.iter().map(|v| python_bool(*v))))?;
+    writeln!(sink, "O_VALIDS = {}", python_list(output_valids.iter().map(|v| python_bool(*v))))?;
+    writeln!(sink, "INPUT_CLOCKS = {}", input_clocks)?;
+    writeln!(sink, "OUTPUT_LATENCY = {}", conf.output_latency)?;
+    writeln!(sink, "TOTAL_CLOCKS = {}", total_clocks)?;
+    writeln!(sink)?;
+    writeln!(sink)?;
+    writeln!(sink, "def port_field(circuit, name, path):")?;
+    writeln!(sink, "    field = getattr(circuit, name)")?;
+    writeln!(sink, "    for idx in path:")?;
+    writeln!(sink, "        field = field[idx]")?;
+    writeln!(sink, "    return field")?;
+    writeln!(sink)?;
+    writeln!(sink)?;
+    writeln!(sink, "def test_{}():", conf.circuit_name)?;
+    writeln!(sink, "    tester = fault.Tester({0}, {0}.{1})", conf.circuit_name, conf.clock_name)?;
+    writeln!(sink, "    for clk in range(TOTAL_CLOCKS):")?;
+    match &conf.valid_in_name {
+        Some(valid_in) if inputs.is_empty() => writeln!(sink, "        tester.circuit.{} = 1", valid_in)?,
+        Some(valid_in) => writeln!(sink, "        tester.circuit.{} = int(clk < INPUT_CLOCKS and I_VALIDS[clk])", valid_in)?,
+        None => ()
+    }
+    writeln!(sink, "        if clk < INPUT_CLOCKS:")?;
+    writeln!(sink, "            for name, (paths, clocks) in INPUTS.items():")?;
+    writeln!(sink, "                for path, value in zip(paths, clocks[clk]):")?;
+    writeln!(sink, "                    tester.poke(port_field({}, name, path), value)", conf.circuit_name)?;
+    writeln!(sink, "        tester.eval()")?;
+    writeln!(sink, "        out_clk = clk - OUTPUT_LATENCY")?;
+    writeln!(sink, "        if 0 <= out_clk < len(O_VALIDS) and O_VALIDS[out_clk]:")?;
+    if let Some(valid_out) = &conf.valid_out_name {
+        writeln!(sink, "            tester.circuit.{}.expect(1)", valid_out)?;
+    }
+    writeln!(sink, "            for name, (paths, clocks) in OUTPUTS.items():")?;
+    writeln!(sink, "                for path, value in zip(paths, clocks[out_clk]):")?;
+    writeln!(sink, "                    tester.expect(port_field({}, name, path), value)", conf.circuit_name)?;
+    writeln!(sink, "        tester.step(2)")?;
+    writeln!(sink, "    tester.compile_and_run(\"{}\", magma_output=\"coreir-verilog\")", conf.target)?;
+    writeln!(sink)?;
+    writeln!(sink)?;
+    writeln!(sink, "if __name__ == \"__main__\":")?;
+    writeln!(sink, "    test_{}()", conf.circuit_name)?;
+    sink.flush()?;
+    Ok(())
+}
+
+fn write_ports_dict<W: Write>(sink: &mut W, dict_name: &str, ports: &[STPort]) -> Result<(), Box<dyn Error>> {
+    writeln!(sink, "{} = {{", dict_name)?;
+    for port in ports {
+        let paths = python_list(lane_field_paths(&port.st_type).iter().map(|path| match path.len() {
+            1 => format!("({},)", path[0]),
+            _ => format!("({})", path.iter().map(|i| i.to_string()).collect::<Vec<String>>().join(", "))
+        }));
+        let fields = port.st_type.atom_type().atom_fields();
+        let clocks = python_list(port.st_vals.atoms.iter().map(|clock_atoms| {
+            python_list(clock_atoms.iter().flat_map(|atom| {
+                atom.leaves().into_iter().zip(fields.iter())
+                    .map(|(leaf, (_, field_type))| format_atom(leaf, field_type, AtomFormat::Python))
+                    .collect::<Vec<String>>()
+            }))
+        }));
+        writeln!(sink, "    \"{}\": ({}, {}),", port.name, paths, clocks)?;
+    }
+    writeln!(sink, "}}")?;
+    Ok(())
+}
+
+/// Get the index path into a port of each field of each lane's atom, in lane order
+fn lane_field_paths(st_type: &Type) -> Vec<Vec<u32>> {
+    let mut space_dims = Vec::new();
+    let mut cur_type = st_type;
+    loop {
+        match cur_type {
+            Type::SSeq { n, elem_type } | Type::STuple { n, elem_type } => {
+                space_dims.push(*n);
+                cur_type = elem_type;
+            }
+            Type::TSeq { elem_type, .. } => cur_type = elem_type,
+            _ => break
+        }
+    }
+    let field_paths: Vec<Vec<u32>> = st_type.atom_type().atom_fields().iter()
+        .map(|(suffix, _)| suffix.split('_').skip(1)
+            .map(|side| if side == "left" { 0 } else { 1 }).collect())
+        .collect();
+
+    let mut paths = Vec::new();
+    for lane in 0..st_type.atoms_per_valid() {
+        // the outermost space dimension is the most significant digit of the lane
+        let mut lane_path = vec![0; space_dims.len()];
+        let mut rest = lane;
+        for (digit, n) in space_dims.iter().enumerate().rev() {
+            lane_path[digit] = rest % n;
+            rest /= n;
+        }
+        for field_path in field_paths.iter() {
+            paths.push(lane_path.iter().chain(field_path.iter()).cloned().collect());
+        }
+    }
+    paths
+}
+
+fn python_bool(b: bool) -> String {
+    String::from(if b { "True" } else { "False" })
+}
+
+fn python_list<I: Iterator<Item=String>>(elems: I) -> String {
+    format!("[{}]", elems.collect::<Vec<String>>().join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lane_field_paths_of_nested_tuples() {
+        let st_type = Type::TSeq {n: 2, i: 0, elem_type: Box::from(
+            Type::SSeq {n: 2, elem_type: Box::from(
+                Type::STuple {n: 3, elem_type: Box::from(
+                    Type::ATuple {left: Box::from(Type::Bit), right: Box::from(Type::UInt8)})})})};
+        let paths = lane_field_paths(&st_type);
+        assert_eq!(paths.len(), 12);
+        assert_eq!(paths[0], vec!(0, 0, 0));
+        assert_eq!(paths[1], vec!(0, 0, 1));
+        assert_eq!(paths[11], vec!(1, 2, 1));
+    }
+
+    #[test]
+    fn test_scalar_port_has_empty_path() {
+        let st_type = Type::TSeq {n: 2, i: 0, elem_type: Box::from(Type::Int8)};
+        assert_eq!(lane_field_paths(&st_type), vec!(Vec::<u32>::new()));
+    }
+
+    #[test]
+    fn test_valid_in_follows_input_valids() {
+        let ports = |types: Vec<Type>| convert_seq_ports_to_st_ports(types.into_iter().enumerate().map(|(idx, st_type)|
+            SeqPort {name: format!("I{}", idx), seq_val: vec!(1, 2), st_type}).collect()).unwrap();
+        let a = Type::TSeq {n: 2, i: 1, elem_type: Box::from(Type::UInt8)};
+        let b = Type::TSeq {n: 1, i: 2, elem_type: Box::from(Type::SSeq {n: 2, elem_type: Box::from(Type::UInt8)})};
+        let mut builder = Vec::new();
+        write_fault_test(&ports(vec!(a.clone())), &[], &FaultConfig::default(), &mut builder).unwrap();
+        let test = String::from_utf8(builder).unwrap();
+        assert!(test.contains("I_VALIDS = [True, True, False]"));
+        assert!(!test.contains("valid_up = 1"));
+        let err = write_fault_test(&ports(vec!(a, b)), &[], &FaultConfig::default(), &mut Vec::new()).unwrap_err();
+        assert_eq!(err.to_string(), "input I1 has different valids than input I0");
+    }
+}
@@ -0,0 +1,151 @@
+//! write Space-Time and Sequence values as NumPy `.npy` arrays
+use crate::languages::sequence::atoms::Atom;
+use crate::languages::sequence::serialize_values::SerializableSeqValue;
+use crate::languages::sequence::types::Type as SeqType;
+use crate::languages::seq_value_to_st_value_and_valid_strings::{
+    convert_seq_val_to_st_atoms_and_valids, STAtomsAndValids
+};
+use crate::languages::space_time::types::Type;
+use std::error::Error;
+use std::io::Write;
+
+/// Convert a Sequence value to a Space-Time value and write it as a
+/// clocks by lanes values array and a clocks long bool valids array
+pub fn convert_seq_val_to_st_npy<T: SerializableSeqValue, V: Write, D: Write>(
+    seq_val: T, st_type: &Type, vals_sink: &mut V, valids_sink: &mut D) -> Result<(), Box<dyn Error>> {
+    let st_vals = convert_seq_val_to_st_atoms_and_valids(seq_val, st_type);
+    write_st_npy(&st_vals, st_type, vals_sink, valids_sink)
+}
+
+/// Write a Space-Time value as a clocks by lanes values array and a clocks long bool valids array.
+/// The values' dtype comes from the Space-Time type's atom. Tuple atoms are structured dtypes
+/// with `left` and `right` fields.
+pub fn write_st_npy<V: Write, D: Write>(st_vals: &STAtomsAndValids, st_type: &Type,
+                                        vals_sink: &mut V, valids_sink: &mut D) -> Result<(), Box<dyn Error>> {
+    let atom_type = st_type.atom_type();
+    let shape = vec!(st_vals.atoms.len(), st_type.atoms_per_valid() as usize);
+    write_npy_header(&npy_descr(atom_type)?, &shape, vals_sink)?;
+    for clock_atoms in st_vals.atoms.iter() {
+        write_npy_atoms(clock_atoms, atom_type, vals_sink)?;
+    }
+    vals_sink.flush()?;
+
+    write_npy_header("'|b1'", &[st_vals.valids.len()], valids_sink)?;
+    let valid_bytes: Vec<u8> = st_vals.valids.iter().map(|v| *v as u8).collect();
+    valids_sink.write_all(&valid_bytes)?;
+    valids_sink.flush()?;
+    Ok(())
+}
+
+/// Write a Sequence value as an array with one dimension per Seq in its type.
+///
+/// # Examples
+///
+/// ```
+/// use aetherling::formats::npy::write_seq_npy;
+/// use aetherling::languages::sequence::types::Type;
+/// let mut builder = Vec::new();
+/// write_seq_npy(vec!(vec!(1, 2, 3), vec!(4, 5, 6)),
+///               &Type::Seq {n: 2, elem_type: Box::from(
+///                   Type::Seq {n: 3, elem_type: Box::from(Type::Int16)})},
+///               &mut builder).unwrap();
+///
+/// assert_eq!(&builder[..6], b"\x93NUMPY");
+/// // the header is padded to 128 bytes, followed by 2 bytes for each of the 6 atoms
+/// assert_eq!(builder.len(), 128 + 2 * 3 * 2);
+/// ```
+pub fn write_seq_npy<T: SerializableSeqValue, W: Write>(
+    seq_val: T, seq_type: &SeqType, sink: &mut W) -> Result<(), Box<dyn Error>> {
+    let mut flat_atoms: Vec<Atom> = Vec::new();
+    seq_val.convert_to_flat_atoms(&mut flat_atoms);
+    let shape: Vec<usize> = seq_type.dims().iter().map(|d| *d as usize).collect();
+    let num_atoms: usize = shape.iter().product();
+    if flat_atoms.len() != num_atoms {
+        return Err(format!("value has {} atoms but type {:?} has {} atoms",
+                           flat_atoms.len(), seq_type, num_atoms).into());
+    }
+    let atom_type = Type::from_seq_type(seq_type.atom_type());
+    write_npy_header(&npy_descr(&atom_type)?, &shape, sink)?;
+    write_npy_atoms(&flat_atoms, &atom_type, sink)?;
+    sink.flush()?;
+    Ok(())
+}
+
+/// Get the NumPy dtype description of an atom type
+fn npy_descr(atom_type: &Type) -> Result<String, Box<dyn Error>> {
+    match atom_type {
+        Type::Bit => Ok(String::from("'|b1'")),
+        Type::Int8 => Ok(String::from("'|i1'")),
+        Type::UInt8 => Ok(String::from("'|u1'")),
+        Type::Int16 => Ok(String::from("'<i2'")),
+        Type::UInt16 => Ok(String::from("'<u2'")),
+        Type::Int32 => Ok(String::from("'<i4'")),
+        Type::UInt32 => Ok(String::from("'<u4'")),
+        Type::ATuple {left, right} =>
+            Ok(format!("[('left', {}), ('right', {})]", npy_descr(left)?, npy_descr(right)?)),
+        _ => Err(format!("no NumPy dtype for atom type {:?}", atom_type).into())
+    }
+}
+
+/// Write the magic string, version, and header dict, padded so the data is 64 byte aligned
+fn write_npy_header<W: Write>(descr: &str, shape: &[usize], sink: &mut W) -> Result<(), Box<dyn Error>> {
+    let shape_str = match shape.len() {
+        1 => format!("({},)", shape[0]),
+        _ => format!("({})", shape.iter().map(|d| d.to_string()).collect::<Vec<String>>().join(", "))
+    };
+    let mut header = format!("{{'descr': {}, 'fortran_order': False, 'shape': {}, }}", descr, shape_str);
+    // 6 bytes magic string, 2 bytes version, 2 bytes header length, and a newline ending the header
+    let unpadded_len = 6 + 2 + 2 + header.len() + 1;
+    header.push_str(&" ".repeat((64 - unpadded_len % 64) % 64));
+    header.push('\n');
+    sink.write_all(b"\x93NUMPY\x01\x00")?;
+    sink.write_all(&(header.len() as u16).to_le_bytes())?;
+    sink.write_all(header.as_bytes())?;
+    Ok(())
+}
+
+/// Write atoms as little endian, packed structs of their fields
+fn write_npy_atoms<W: Write>(atoms: &[Atom], atom_type: &Type, sink: &mut W) -> Result<(), Box<dyn Error>> {
+    let fields = atom_type.atom_fields();
+    let mut bytes = Vec::with_capacity(atoms.len() * std::cmp::max(1, atom_type.size() as usize / 8));
+    for atom in atoms {
+        for (leaf, (_, field_type)) in atom.leaves().iter().zip(fields.iter()) {
+            let width = field_type.size();
+            // bits take a whole byte
+            let num_bytes = std::cmp::max(1, width as usize / 8);
+            bytes.extend_from_slice(&leaf.to_bits(width).to_le_bytes()[..num_bytes]);
+        }
+    }
+    sink.write_all(&bytes)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_npy_st_values_and_valids() {
+        let mut vals_builder = Vec::new();
+        let mut valids_builder = Vec::new();
+        convert_seq_val_to_st_npy(vec!(-1, 2), &Type::TSeq {n: 1, i: 1, elem_type: Box::from(
+                                      Type::SSeq {n: 2, elem_type: Box::from(Type::Int16)})},
+                                  &mut vals_builder, &mut valids_builder).unwrap();
+        let vals_header = String::from_utf8(vals_builder[10..128].to_vec()).unwrap();
+        assert!(vals_header.starts_with("{'descr': '<i2', 'fortran_order': False, 'shape': (2, 2), }"));
+        assert!(vals_header.ends_with(" \n"));
+        assert_eq!(&vals_builder[128..], &[0xff, 0xff, 2, 0, 0, 0, 0, 0]);
+        let valids_header = String::from_utf8(valids_builder[10..128].to_vec()).unwrap();
+        assert!(valids_header.starts_with("{'descr': '|b1', 'fortran_order': False, 'shape': (2,), }"));
+        assert_eq!(&valids_builder[128..], &[1, 0]);
+    }
+
+    #[test]
+    fn test_npy_tuple_dtype() {
+        let t = Type::ATuple {left: Box::from(Type::UInt8), right: Box::from(
+            Type::ATuple {left: Box::from(Type::Bit), right: Box::from(Type::Int32)})};
+        assert_eq!(npy_descr(&t).unwrap(),
+                   "[('left', '|u1'), ('right', [('left', '|b1'), ('right', '<i4')])]");
+        assert!(npy_descr(&Type::Unit).is_err());
+    }
+}
@@ -0,0 +1,414 @@
+//! read PGM and PPM images as Sequence values, so image kernels can be tested on pictures,
+//! and render Sequence values back to images to see a circuit's output
+use crate::languages::sequence::atoms::Atom;
+use crate::languages::sequence::serialize_values::SerializableSeqValue;
+use crate::languages::sequence::types::Type as SeqType;
+use std::error::Error;
+use std::io::Write;
+
+/// An image's samples, row by row, with a pixel's channels next to each other
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    /// 1 for gray images, 3 for RGB images
+    pub channels: u32,
+    /// The largest value of a sample. Images with a `max_val` above 255 have 16-bit samples.
+    pub max_val: u32,
+    pub samples: Vec<u32>
+}
+
+/// How the pixels of an image are nested in a Sequence value
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImageLayout {
+    /// `Seq height (Seq width pixel)`
+    Rows,
+    /// `Seq (height * width) pixel`, one row after another
+    Flat
+}
+
+impl ImageLayout {
+    /// The names of the layouts on the command line
+    pub const NAMES: [&'static str; 2] = ["rows", "flat"];
+
+    pub fn from_name(name: &str) -> Option<ImageLayout> {
+        match name {
+            "rows" => Some(ImageLayout::Rows),
+            "flat" => Some(ImageLayout::Flat),
+            _ => None
+        }
+    }
+}
+
+impl Image {
+    /// Get the type of a sample, `UInt8` for 8-bit images and `UInt16` for 16-bit images
+    pub fn sample_type(&self) -> SeqType {
+        if self.max_val <= u8::MAX as u32 { SeqType::UInt8 } else { SeqType::UInt16 }
+    }
+
+    /// Get the type of a pixel. RGB pixels are `ATuple r (ATuple g b)`.
+    pub fn pixel_type(&self) -> SeqType {
+        let sample = self.sample_type();
+        if self.channels == 1 {
+            sample
+        } else {
+            SeqType::ATuple {left: Box::new(sample.clone()), right: Box::new(
+                SeqType::ATuple {left: Box::new(sample.clone()), right: Box::new(sample)})}
+        }
+    }
+
+    /// Get the type of the image as a Sequence value
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use aetherling::formats::pnm::{read_pnm, ImageLayout};
+    /// let image = read_pnm(b"P2\n3 2\n255\n0 1 2\n3 4 5\n").unwrap();
+    ///
+    /// assert_eq!(image.seq_type(ImageLayout::Rows).to_string(), "Seq 2 (Seq 3 UInt8)");
+    /// assert_eq!(image.seq_type(ImageLayout::Flat).to_string(), "Seq 6 UInt8");
+    /// ```
+    pub fn seq_type(&self, layout: ImageLayout) -> SeqType {
+        lay_out_type(self.pixel_type(), self.width, self.height, layout)
+    }
+
+    /// Get the image as a Sequence value of type `seq_type(layout)`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use aetherling::formats::pnm::{read_pnm, ImageLayout};
+    /// use aetherling::languages::sequence::serialize_values::save_value;
+    /// let image = read_pnm(b"P3 2 1 255  10 20 30  40 50 60").unwrap();
+    ///
+    /// assert_eq!(save_value(&image.to_seq_value(ImageLayout::Rows)),
+    ///            save_value(&vec!(vec!((10u32, (20u32, 30u32)), (40u32, (50u32, 60u32))))));
+    /// ```
+    pub fn to_seq_value(&self, layout: ImageLayout) -> Box<dyn SerializableSeqValue> {
+        let pixels: Vec<Box<dyn SerializableSeqValue>> = self.samples.chunks(self.channels as usize)
+            .map(|p| -> Box<dyn SerializableSeqValue> {
+                match p {
+                    [gray] => Box::new(*gray),
+                    [r, g, b] => Box::new((*r, (*g, *b))),
+                    _ => unreachable!("images have 1 or 3 channels")
+                }
+            }).collect();
+        lay_out_pixels(pixels, self.width, self.height, layout)
+    }
+}
+
+/// Nest an image's pixels, row by row, in a Sequence value
+pub(crate) fn lay_out_pixels(pixels: Vec<Box<dyn SerializableSeqValue>>, width: u32, height: u32,
+                             layout: ImageLayout) -> Box<dyn SerializableSeqValue> {
+    match layout {
+        ImageLayout::Flat => Box::new(pixels),
+        ImageLayout::Rows => {
+            let mut rows: Vec<Vec<Box<dyn SerializableSeqValue>>> = Vec::with_capacity(height as usize);
+            let mut pixels = pixels.into_iter();
+            for _ in 0..height {
+                rows.push(pixels.by_ref().take(width as usize).collect());
+            }
+            Box::new(rows)
+        }
+    }
+}
+
+/// Get the type of an image's pixels nested in a Sequence value
+pub(crate) fn lay_out_type(pixel: SeqType, width: u32, height: u32, layout: ImageLayout) -> SeqType {
+    match layout {
+        ImageLayout::Rows => SeqType::Seq {n: height, elem_type: Box::new(
+            SeqType::Seq {n: width, elem_type: Box::new(pixel)})},
+        ImageLayout::Flat => SeqType::Seq {n: height * width, elem_type: Box::new(pixel)}
+    }
+}
+
+/// Read a PGM or PPM image in the ASCII (`P2`, `P3`) or binary (`P5`, `P6`) form,
+/// with 8-bit or 16-bit samples. Binary 16-bit samples are big-endian.
+///
+/// # Examples
+///
+/// ```
+/// use aetherling::formats::pnm::read_pnm;
+/// let image = read_pnm(b"P5\n# a comment\n2 1\n65535\n\x01\x02\xff\xff").unwrap();
+///
+/// assert_eq!((image.width, image.height, image.channels), (2, 1, 1));
+/// assert_eq!(image.samples, vec!(0x0102, 0xffff));
+/// ```
+pub fn read_pnm(src: &[u8]) -> Result<Image, Box<dyn Error>> {
+    let (channels, binary) = match src.get(..2) {
+        Some(b"P2") => (1, false),
+        Some(b"P3") => (3, false),
+        Some(b"P5") => (1, true),
+        Some(b"P6") => (3, true),
+        _ => return Err("not a P2, P3, P5, or P6 image".into())
+    };
+    let mut pos = 2;
+    let width = header_number(src, &mut pos)?;
+    let height = header_number(src, &mut pos)?;
+    let max_val = header_number(src, &mut pos)?;
+    if max_val == 0 || max_val > u16::MAX as u32 {
+        return Err(format!("the image's max value {} isn't from 1 to 65535", max_val).into());
+    }
+    // a flat image is a Seq of all its pixels, so the pixel count must fit in a u32
+    let num_samples = width.checked_mul(height)
+        .and_then(|pixels| (pixels as usize).checked_mul(channels as usize))
+        .ok_or_else(|| format!("the image's {}x{} pixels are too many", width, height))?;
+
+    let samples = if binary {
+        // exactly one whitespace byte separates the header from the samples
+        let raster = src.get(pos + 1..).unwrap_or(&[]);
+        let sample_bytes = if max_val > u8::MAX as u32 { 2 } else { 1 };
+        if raster.len() / sample_bytes < num_samples {
+            return Err(format!("the image has {} bytes of samples, but its header needs {}",
+                               raster.len(), num_samples as u64 * sample_bytes as u64).into());
+        }
+        raster.chunks(sample_bytes).take(num_samples)
+            .map(|b| b.iter().fold(0, |acc, byte| (acc << 8) | *byte as u32)).collect()
+    } else {
+        // don't trust the header for more samples than the input has bytes
+        let mut samples = Vec::with_capacity(num_samples.min(src.len()));
+        for _ in 0..num_samples {
+            samples.push(header_number(src, &mut pos)
+                .map_err(|_| format!("the image has {} samples, but its header needs {}", samples.len(), num_samples))?);
+        }
+        samples
+    };
+    if let Some(s) = samples.iter().find(|s| **s > max_val) {
+        return Err(format!("sample {} is above the image's max value {}", s, max_val).into());
+    }
+    Ok(Image { width, height, channels, max_val, samples })
+}
+
+/// Read a decimal number after whitespace and comments, leaving `pos` right after it
+fn header_number(src: &[u8], pos: &mut usize) -> Result<u32, Box<dyn Error>> {
+    loop {
+        match src.get(*pos) {
+            Some(b) if b.is_ascii_whitespace() => *pos += 1,
+            Some(b'#') => while src.get(*pos).is_some_and(|b| *b != b'\n') { *pos += 1 },
+            _ => break
+        }
+    }
+    let start = *pos;
+    while src.get(*pos).is_some_and(u8::is_ascii_digit) {
+        *pos += 1;
+    }
+    std::str::from_utf8(&src[start..*pos]).unwrap().parse()
+        .map_err(|_| format!("expected a number at byte {} of the image", start).into())
+}
+
+/// How to fit atoms into an image's samples
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SampleMapping {
+    /// Samples below 0 are 0 and samples above the max value are the max value
+    Clamp,
+    /// Stretch the smallest and largest atoms in the image to 0 and the max value
+    Scale
+}
+
+impl SampleMapping {
+    /// The names of the mappings on the command line
+    pub const NAMES: [&'static str; 2] = ["clamp", "scale"];
+
+    pub fn from_name(name: &str) -> Option<SampleMapping> {
+        match name {
+            "clamp" => Some(SampleMapping::Clamp),
+            "scale" => Some(SampleMapping::Scale),
+            _ => None
+        }
+    }
+}
+
+/// The options for rendering a Sequence value as an image
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderConfig {
+    pub width: u32,
+    pub height: u32,
+    /// The largest sample, 255 for 8-bit images or up to 65535 for 16-bit images
+    pub max_val: u32,
+    pub mapping: SampleMapping
+}
+
+impl Default for RenderConfig {
+    fn default() -> Self {
+        RenderConfig { width: 0, height: 0, max_val: u8::MAX as u32, mapping: SampleMapping::Clamp }
+    }
+}
+
+/// Render a Sequence value as an image. The value's atoms are its pixels, row by row.
+/// Integer and bit atoms are gray pixels and `ATuple r (ATuple g b)` atoms are RGB pixels.
+///
+/// # Examples
+///
+/// ```
+/// use aetherling::formats::pnm::{seq_value_to_image, RenderConfig, SampleMapping};
+/// let conf = RenderConfig {width: 2, height: 2, ..RenderConfig::default()};
+///
+/// assert_eq!(seq_value_to_image(vec!(vec!(-5, 10), vec!(300, 255)), &conf).unwrap().samples,
+///            vec!(0, 10, 255, 255));
+/// assert_eq!(seq_value_to_image(vec!(0, 1, 2, 4), &RenderConfig {mapping: SampleMapping::Scale, ..conf})
+///                .unwrap().samples, vec!(0, 64, 128, 255));
+/// ```
+pub fn seq_value_to_image<T: SerializableSeqValue>(seq_val: T, conf: &RenderConfig) -> Result<Image, Box<dyn Error>> {
+    let (channels, samples) = seq_value_samples(seq_val, conf)?;
+    render_samples(channels, &samples, conf)
+}
+
+/// Render the absolute difference between a Sequence value and a reference value as an image,
+/// so pixels that don't match stand out. Scaling the difference makes small errors visible.
+///
+/// # Examples
+///
+/// ```
+/// use aetherling::formats::pnm::{seq_values_diff_image, RenderConfig};
+/// let conf = RenderConfig {width: 3, height: 1, ..RenderConfig::default()};
+///
+/// assert_eq!(seq_values_diff_image(vec!(5, 7, 9), vec!(5, 9, 8), &conf).unwrap().samples, vec!(0, 2, 1));
+/// ```
+pub fn seq_values_diff_image<T: SerializableSeqValue, R: SerializableSeqValue>(
+    seq_val: T, reference: R, conf: &RenderConfig) -> Result<Image, Box<dyn Error>> {
+    let (channels, samples) = seq_value_samples(seq_val, conf)?;
+    let (ref_channels, ref_samples) = seq_value_samples(reference, conf)?;
+    if channels != ref_channels {
+        return Err("the value and the reference have different pixel types".into());
+    }
+    let diffs: Vec<i64> = samples.iter().zip(ref_samples.iter()).map(|(a, b)| (a - b).abs()).collect();
+    render_samples(channels, &diffs, conf)
+}
+
+/// Get the channels and samples of a value's pixels, checking there's one pixel per point in the image
+fn seq_value_samples<T: SerializableSeqValue>(seq_val: T, conf: &RenderConfig) -> Result<(u32, Vec<i64>), Box<dyn Error>> {
+    let mut atoms = Vec::new();
+    seq_val.convert_to_flat_atoms(&mut atoms);
+    let pixels = conf.width as usize * conf.height as usize;
+    if atoms.len() != pixels {
+        return Err(format!("the value has {} pixels, but a {}x{} image needs {}",
+                           atoms.len(), conf.width, conf.height, pixels).into());
+    }
+    let sample = |atom: &Atom| match atom {
+        Atom::Bit(b) => Ok(*b as i64),
+        Atom::Int(i) => Ok(*i as i64),
+        Atom::UInt(u) => Ok(*u as i64),
+        _ => Err(format!("{:?} isn't a gray or RGB pixel", atom))
+    };
+    let channels = match atoms.first() {
+        Some(Atom::Tuple(..)) => 3,
+        _ => 1
+    };
+    let mut samples = Vec::with_capacity(atoms.len() * channels);
+    for atom in atoms.iter() {
+        match (channels, atom) {
+            (3, Atom::Tuple(r, gb)) => match gb.as_ref() {
+                Atom::Tuple(g, b) => samples.extend([sample(r)?, sample(g)?, sample(b)?]),
+                _ => return Err(format!("{:?} isn't an RGB pixel", atom).into())
+            },
+            (1, _) => samples.push(sample(atom)?),
+            _ => return Err(format!("{:?} isn't an RGB pixel", atom).into())
+        }
+    }
+    Ok((channels as u32, samples))
+}
+
+fn render_samples(channels: u32, samples: &[i64], conf: &RenderConfig) -> Result<Image, Box<dyn Error>> {
+    if conf.max_val == 0 || conf.max_val > u16::MAX as u32 {
+        return Err(format!("the image's max value {} isn't from 1 to 65535", conf.max_val).into());
+    }
+    let max_val = conf.max_val as i64;
+    let samples = match conf.mapping {
+        SampleMapping::Clamp => samples.iter().map(|s| (*s).clamp(0, max_val) as u32).collect(),
+        SampleMapping::Scale => {
+            let lo = samples.iter().copied().min().unwrap_or(0);
+            let hi = samples.iter().copied().max().unwrap_or(0);
+            samples.iter().map(|s| if hi == lo { 0 } else {
+                (((s - lo) as f64 / (hi - lo) as f64) * max_val as f64).round() as u32
+            }).collect()
+        }
+    };
+    Ok(Image { width: conf.width, height: conf.height, channels, max_val: conf.max_val, samples })
+}
+
+/// Write an image as a binary (`P5`, `P6`) or ASCII (`P2`, `P3`) PGM or PPM,
+/// with one ASCII row of the image per line
+///
+/// # Examples
+///
+/// ```
+/// use aetherling::formats::pnm::{read_pnm, write_pnm};
+/// let image = read_pnm(b"P3 2 1 1000  1 2 3  997 998 999").unwrap();
+/// let mut builder = Vec::new();
+/// write_pnm(&image, true, &mut builder).unwrap();
+///
+/// assert!(builder.starts_with(b"P6\n2 1\n1000\n\x00\x01"));
+/// assert_eq!(read_pnm(&builder).unwrap(), image);
+/// ```
+pub fn write_pnm<W: Write>(image: &Image, binary: bool, sink: &mut W) -> Result<(), Box<dyn Error>> {
+    let magic = match (image.channels, binary) {
+        (1, false) => "P2",
+        (3, false) => "P3",
+        (1, true) => "P5",
+        (3, true) => "P6",
+        _ => return Err(format!("images have 1 or 3 channels, not {}", image.channels).into())
+    };
+    write!(sink, "{}\n{} {}\n{}\n", magic, image.width, image.height, image.max_val)?;
+    if binary {
+        let wide = image.max_val > u8::MAX as u32;
+        let bytes: Vec<u8> = image.samples.iter().flat_map(|s| {
+            if wide { vec!((*s >> 8) as u8, *s as u8) } else { vec!(*s as u8) }
+        }).collect();
+        sink.write_all(&bytes)?;
+    } else {
+        let row_len = (image.width * image.channels) as usize;
+        for row in image.samples.chunks(row_len.max(1)) {
+            let row_strs: Vec<String> = row.iter().map(u32::to_string).collect();
+            writeln!(sink, "{}", row_strs.join(" "))?;
+        }
+    }
+    sink.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::languages::sequence::serialize_values::save_value;
+
+    #[test]
+    fn test_ascii_and_binary_match() {
+        let ascii = read_pnm(b"P3\n2 2\n255\n1 2 3 4 5 6\n7 8 9 10 11 12\n").unwrap();
+        let binary = read_pnm(b"P6 2 2 255\n\x01\x02\x03\x04\x05\x06\x07\x08\x09\x0a\x0b\x0c").unwrap();
+        assert_eq!(ascii, binary);
+        assert_eq!(save_value(&binary.to_seq_value(ImageLayout::Flat)),
+                   save_value(&vec!((1u32, (2u32, 3u32)), (4, (5, 6)), (7, (8, 9)), (10, (11, 12)))));
+    }
+
+    #[test]
+    fn test_16_bit_type() {
+        let image = read_pnm(b"P2 1 2 1023 1023 0").unwrap();
+        assert_eq!(image.seq_type(ImageLayout::Rows).to_string(), "Seq 2 (Seq 1 UInt16)");
+    }
+
+    #[test]
+    fn test_render_rgb_and_diff() {
+        let conf = RenderConfig {width: 1, height: 2, max_val: 1023, mapping: SampleMapping::Clamp};
+        let image = seq_value_to_image(vec!((1u32, (2u32, 3u32)), (2000, (0, 5))), &conf).unwrap();
+        assert_eq!(image.samples, vec!(1, 2, 3, 1023, 0, 5));
+        let mut builder = Vec::new();
+        write_pnm(&image, false, &mut builder).unwrap();
+        assert_eq!(String::from_utf8(builder).unwrap(), "P3\n1 2\n1023\n1 2 3\n1023 0 5\n");
+        let err = seq_values_diff_image(vec!(1u32, 2), vec!((1u32, (2u32, 3u32)), (2, (0, 5))), &conf);
+        assert_eq!(err.unwrap_err().to_string(), "the value and the reference have different pixel types");
+        let conf = RenderConfig {width: 70000, height: 70000, ..conf};
+        assert_eq!(seq_value_to_image(vec!(1u32), &conf).unwrap_err().to_string(),
+                   "the value has 1 pixels, but a 70000x70000 image needs 4900000000");
+    }
+
+    #[test]
+    fn test_bad_images() {
+        let err = |src: &[u8]| read_pnm(src).unwrap_err().to_string();
+        assert_eq!(err(b"P5 2 2 255\n\x00\x00\x00"), "the image has 3 bytes of samples, but its header needs 4");
+        assert_eq!(err(b"P2 2 1 15 3 16"), "sample 16 is above the image's max value 15");
+        assert_eq!(err(b"P1 2 1 1 0"), "not a P2, P3, P5, or P6 image");
+        assert_eq!(err(b"P2 60000 60000 255 1 2 3"), "the image has 3 samples, but its header needs 3600000000");
+        assert_eq!(err(b"P2 100000 100000 255 1 2 3"), "the image's 100000x100000 pixels are too many");
+        assert_eq!(err(b"P6 4294967295 4294967295 65535"), "the image's 4294967295x4294967295 pixels are too many");
+    }
+}
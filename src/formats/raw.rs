@@ -0,0 +1,342 @@
+//! read and write raw binary frame buffers, such as `.bin` captures, laid out
+//! as a layout descriptor says, as Sequence values of pixels
+use super::parse_json_or_toml;
+use super::pnm::{lay_out_pixels, lay_out_type, ImageLayout};
+use crate::languages::sequence::atoms::Atom;
+use crate::languages::sequence::serialize_values::SerializableSeqValue;
+use crate::languages::sequence::types::Type as SeqType;
+use serde::Deserialize;
+use std::error::Error;
+use std::io::Write;
+
+/// The order of a sample's bytes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Endian {
+    #[default]
+    Little,
+    Big
+}
+
+/// How a raw buffer's samples are laid out. In TOML:
+///
+/// ```toml
+/// width = 640
+/// height = 480
+/// channels = 3
+/// bits = 16
+/// endian = "big"
+/// stride = 3872
+/// planar = true
+/// ```
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RawLayout {
+    pub width: u32,
+    pub height: u32,
+    /// The samples in each pixel. Pixels with more than one are `ATuple c0 (ATuple c1 ...)`.
+    #[serde(default = "default_channels")]
+    pub channels: u32,
+    /// The bits of each sample, 8, 16, or 32
+    #[serde(default = "default_bits")]
+    pub bits: u32,
+    #[serde(default)]
+    pub signed: bool,
+    #[serde(default)]
+    pub endian: Endian,
+    /// The bytes from the start of one row to the next, including padding.
+    /// If missing, rows aren't padded.
+    #[serde(default)]
+    pub stride: Option<u32>,
+    /// Whether each channel is a plane of its own, rather than the channels of each pixel side by side
+    #[serde(default)]
+    pub planar: bool,
+    /// The frames in the buffer, one after another. If missing, the buffer is one image
+    /// rather than a Seq of frames.
+    #[serde(default)]
+    pub frames: Option<u32>
+}
+
+fn default_channels() -> u32 { 1 }
+fn default_bits() -> u32 { 8 }
+
+/// Parse a layout descriptor. Files ending in `.json` are JSON, files ending in `.toml` are TOML,
+/// and other files are JSON if they start with `{` and TOML otherwise.
+///
+/// # Examples
+///
+/// ```
+/// use aetherling::formats::raw::{parse_raw_layout, Endian};
+/// let layout = parse_raw_layout("width = 4\nheight = 2\nbits = 16\nendian = \"big\"\n", "cap.toml").unwrap();
+///
+/// assert_eq!((layout.width, layout.channels, layout.endian), (4, 1, Endian::Big));
+/// assert_eq!(layout.stride_bytes().unwrap(), 8);
+/// ```
+pub fn parse_raw_layout(text: &str, path: &str) -> Result<RawLayout, Box<dyn Error>> {
+    parse_json_or_toml(text, path, "layout")
+}
+
+impl RawLayout {
+    /// Get the type of a sample, such as `UInt16` for unsigned 16-bit samples
+    pub fn sample_type(&self) -> Result<SeqType, Box<dyn Error>> {
+        match (self.bits, self.signed) {
+            (8, false) => Ok(SeqType::UInt8),
+            (8, true) => Ok(SeqType::Int8),
+            (16, false) => Ok(SeqType::UInt16),
+            (16, true) => Ok(SeqType::Int16),
+            (32, false) => Ok(SeqType::UInt32),
+            (32, true) => Ok(SeqType::Int32),
+            (bits, _) => Err(format!("the sample size {} isn't 8, 16, or 32 bits", bits).into())
+        }
+    }
+
+    /// Get the type of a pixel, `ATuple c0 (ATuple c1 ...)` for more than one channel
+    pub fn pixel_type(&self) -> Result<SeqType, Box<dyn Error>> {
+        let sample = self.sample_type()?;
+        if self.channels == 0 {
+            return Err("a pixel needs at least one channel".into());
+        }
+        Ok((1..self.channels).fold(sample.clone(), |right, _| {
+            SeqType::ATuple {left: Box::new(sample.clone()), right: Box::new(right)}
+        }))
+    }
+
+    /// Get the type of the buffer's Sequence value. Buffers with `frames` are a Seq of frames.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use aetherling::formats::raw::parse_raw_layout;
+    /// use aetherling::formats::pnm::ImageLayout;
+    /// let layout = parse_raw_layout(r#"{"width": 3, "height": 2, "channels": 2, "signed": true, "frames": 5}"#,
+    ///                               "cap.json").unwrap();
+    ///
+    /// assert_eq!(layout.seq_type(ImageLayout::Flat).unwrap().to_string(),
+    ///            "Seq 5 (Seq 6 (ATuple Int8 Int8))");
+    /// ```
+    pub fn seq_type(&self, layout: ImageLayout) -> Result<SeqType, Box<dyn Error>> {
+        // a flat frame is a Seq of all its pixels, so the pixel count must fit in a u32
+        if self.width.checked_mul(self.height).is_none() {
+            return Err(self.too_big());
+        }
+        let frame = lay_out_type(self.pixel_type()?, self.width, self.height, layout);
+        Ok(match self.frames {
+            Some(n) => SeqType::Seq {n, elem_type: Box::new(frame)},
+            None => frame
+        })
+    }
+
+    fn too_big(&self) -> Box<dyn Error> {
+        format!("the layout's {}x{} frames are too big", self.width, self.height).into()
+    }
+
+    /// Get the bytes of the samples in each row of a plane, without padding
+    fn row_bytes(&self) -> Result<usize, Box<dyn Error>> {
+        let channels = if self.planar { 1 } else { self.channels as usize };
+        (self.width as usize).checked_mul(channels).and_then(|samples| samples.checked_mul(self.bits as usize / 8))
+            .ok_or_else(|| self.too_big())
+    }
+
+    /// Get the bytes from the start of one row to the next
+    pub fn stride_bytes(&self) -> Result<usize, Box<dyn Error>> {
+        let row_bytes = self.row_bytes()?;
+        match self.stride {
+            Some(stride) if (stride as usize) < row_bytes =>
+                Err(format!("the stride {} is shorter than a row's {} bytes", stride, row_bytes).into()),
+            Some(stride) => Ok(stride as usize),
+            None => Ok(row_bytes)
+        }
+    }
+
+    /// Get the bytes of the whole buffer, counting each plane's rows
+    fn buffer_bytes(&self) -> Result<usize, Box<dyn Error>> {
+        let planes = if self.planar { self.channels as usize } else { 1 };
+        (self.frames.unwrap_or(1) as usize).checked_mul(planes)
+            .and_then(|n| n.checked_mul(self.height as usize))
+            .and_then(|rows| rows.checked_mul(self.stride_bytes().ok()?))
+            .ok_or_else(|| self.too_big())
+    }
+
+    /// Get the offset in the buffer of a sample of a frame
+    fn sample_offset(&self, stride: usize, frame: usize, row: usize, col: usize, channel: usize) -> usize {
+        let (height, channels) = (self.height as usize, self.channels as usize);
+        let bytes = self.bits as usize / 8;
+        if self.planar {
+            ((frame * channels + channel) * height + row) * stride + col * bytes
+        } else {
+            (frame * height + row) * stride + (col * channels + channel) * bytes
+        }
+    }
+}
+
+/// Read a raw buffer as a Sequence value of type `raw_layout.seq_type(layout)`.
+/// The buffer must be exactly as long as the layout, though the last row may leave out its padding.
+///
+/// # Examples
+///
+/// ```
+/// use aetherling::formats::raw::{parse_raw_layout, read_raw};
+/// use aetherling::formats::pnm::ImageLayout;
+/// use aetherling::languages::sequence::serialize_values::save_value;
+/// // two rows of two 16-bit big-endian samples, padded to 6 bytes
+/// let layout = parse_raw_layout("width = 2\nheight = 2\nbits = 16\nendian = \"big\"\nstride = 6\n",
+///                               "cap.toml").unwrap();
+/// let value = read_raw(b"\x00\x01\x00\x02..\x01\x00\x02\x00..", &layout, ImageLayout::Rows).unwrap();
+///
+/// assert_eq!(save_value(&value), save_value(&vec!(vec!(1u32, 2), vec!(256u32, 512))));
+/// assert!(read_raw(b"\x00\x01\x00\x02..\x01\x00\x02", &layout, ImageLayout::Rows).is_err());
+/// ```
+pub fn read_raw(src: &[u8], raw_layout: &RawLayout,
+                layout: ImageLayout) -> Result<Box<dyn SerializableSeqValue>, Box<dyn Error>> {
+    let seq_type = raw_layout.seq_type(layout)?;
+    let stride = raw_layout.stride_bytes()?;
+    let padding = stride - raw_layout.row_bytes()?;
+    let full_len = raw_layout.buffer_bytes()?;
+    if src.len() != full_len && src.len() + padding != full_len {
+        return Err(format!("the buffer has {} bytes, but a {} needs {}", src.len(), seq_type, full_len).into());
+    }
+    let bytes = raw_layout.bits as usize / 8;
+    let sample = |offset: usize| -> Box<dyn SerializableSeqValue> {
+        let b = &src[offset..offset + bytes];
+        let unsigned = match raw_layout.endian {
+            Endian::Little => b.iter().rev().fold(0u32, |acc, byte| (acc << 8) | *byte as u32),
+            Endian::Big => b.iter().fold(0u32, |acc, byte| (acc << 8) | *byte as u32)
+        };
+        if raw_layout.signed {
+            // sign-extend from the sample's width
+            let shift = 32 - raw_layout.bits;
+            Box::new(((unsigned << shift) as i32) >> shift)
+        } else {
+            Box::new(unsigned)
+        }
+    };
+    let (width, height) = (raw_layout.width as usize, raw_layout.height as usize);
+    let frames: Vec<Box<dyn SerializableSeqValue>> = (0..raw_layout.frames.unwrap_or(1) as usize).map(|frame| {
+        let pixels = (0..height).flat_map(|row| (0..width).map(move |col| (row, col))).map(|(row, col)| {
+            // nest the channels from the last one out, as in the pixel type
+            (0..raw_layout.channels as usize - 1).rev().fold(
+                sample(raw_layout.sample_offset(stride, frame, row, col, raw_layout.channels as usize - 1)),
+                |right, channel| Box::new((sample(raw_layout.sample_offset(stride, frame, row, col, channel)), right)))
+        }).collect();
+        lay_out_pixels(pixels, raw_layout.width, raw_layout.height, layout)
+    }).collect();
+    Ok(match raw_layout.frames {
+        Some(_) => Box::new(frames),
+        None => frames.into_iter().next().unwrap()
+    })
+}
+
+/// Write a Sequence value of pixels as a raw buffer, clamping samples to the sample type's range
+/// and filling the padding at the end of each row with zeros.
+/// The value's pixels must fill the layout's frames.
+pub fn write_raw<T: SerializableSeqValue + ?Sized, W: Write>(value: &T, raw_layout: &RawLayout,
+                                                             sink: &mut W) -> Result<(), Box<dyn Error>> {
+    let (min, max) = raw_layout.sample_type()?.range().unwrap();
+    let stride = raw_layout.stride_bytes()?;
+    let buffer_bytes = raw_layout.buffer_bytes()?;
+    let channels = raw_layout.channels as usize;
+    let mut atoms = Vec::new();
+    value.convert_to_flat_atoms(&mut atoms);
+    let mut samples = Vec::with_capacity(atoms.len() * channels);
+    for atom in atoms.iter() {
+        let before = samples.len();
+        flatten_samples(atom, &mut samples)?;
+        if samples.len() - before != channels {
+            return Err(format!("{:?} doesn't have {} channels", atom, channels).into());
+        }
+    }
+    // the buffer's size is checked, so its pixel count fits
+    let pixels = raw_layout.width as usize * raw_layout.height as usize * raw_layout.frames.unwrap_or(1) as usize;
+    if atoms.len() != pixels {
+        return Err(format!("the value has {} pixels, but the layout needs {}", atoms.len(), pixels).into());
+    }
+
+    let bytes = raw_layout.bits as usize / 8;
+    let mut buffer = vec![0u8; buffer_bytes];
+    let (width, height) = (raw_layout.width as usize, raw_layout.height as usize);
+    for (idx, sample) in samples.iter().enumerate() {
+        let channel = idx % channels;
+        let pixel = idx / channels;
+        let (frame, row, col) = (pixel / (width * height), pixel / width % height, pixel % width);
+        let offset = raw_layout.sample_offset(stride, frame, row, col, channel);
+        let clamped = (*sample).clamp(min, max) as u32;
+        let le = clamped.to_le_bytes();
+        let sample_bytes = &mut buffer[offset..offset + bytes];
+        match raw_layout.endian {
+            Endian::Little => sample_bytes.copy_from_slice(&le[..bytes]),
+            Endian::Big => sample_bytes.iter_mut().zip(le[..bytes].iter().rev()).for_each(|(b, l)| *b = *l)
+        }
+    }
+    sink.write_all(&buffer)?;
+    sink.flush()?;
+    Ok(())
+}
+
+/// Collect the samples of a pixel's atom, left first
+fn flatten_samples(atom: &Atom, samples: &mut Vec<i64>) -> Result<(), Box<dyn Error>> {
+    match atom {
+        Atom::Bit(b) => samples.push(*b as i64),
+        Atom::Int(i) => samples.push(*i as i64),
+        Atom::UInt(u) => samples.push(*u as i64),
+        Atom::Tuple(left, right) => {
+            flatten_samples(left, samples)?;
+            flatten_samples(right, samples)?;
+        }
+        Atom::Unit => return Err("a Unit isn't a sample".into())
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::languages::sequence::serialize_values::save_value;
+
+    #[test]
+    fn test_planar_frames_round_trip() {
+        let layout = parse_raw_layout(r#"{"width": 2, "height": 1, "channels": 3, "planar": true,
+                                          "stride": 4, "frames": 2}"#, "cap.json").unwrap();
+        // each frame is three one-row planes of two samples and two bytes of padding
+        let src = b"\x01\x02..\x03\x04..\x05\x06..\x07\x08..\x09\x0a..\x0b\x0c..";
+        let value = read_raw(src, &layout, ImageLayout::Flat).unwrap();
+        assert_eq!(save_value(&value), save_value(&vec!(
+            vec!((1u32, (3u32, 5u32)), (2, (4, 6))),
+            vec!((7u32, (9u32, 11u32)), (8, (10, 12))))));
+        let mut builder = Vec::new();
+        write_raw(&value, &layout, &mut builder).unwrap();
+        assert_eq!(builder, src.iter().map(|b| if *b == b'.' { 0 } else { *b }).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn test_signed_little_endian() {
+        let layout = parse_raw_layout("width = 2\nheight = 1\nbits = 16\nsigned = true\n", "cap.toml").unwrap();
+        let value = read_raw(b"\xff\xff\x00\x80", &layout, ImageLayout::Flat).unwrap();
+        assert_eq!(save_value(&value), save_value(&vec!(-1, -32768)));
+        let mut builder = Vec::new();
+        write_raw(&vec!(-1, 40000), &layout, &mut builder).unwrap();
+        assert_eq!(builder, b"\xff\xff\xff\x7f");
+    }
+
+    #[test]
+    fn test_layout_errors() {
+        let layout = parse_raw_layout("width = 4\nheight = 2\n", "cap.toml").unwrap();
+        assert_eq!(read_raw(&[0; 7], &layout, ImageLayout::Rows).err().unwrap().to_string(),
+                   "the buffer has 7 bytes, but a Seq 2 (Seq 4 UInt8) needs 8");
+        assert_eq!(write_raw(&vec!(1u32, 2), &layout, &mut Vec::new()).unwrap_err().to_string(),
+                   "the value has 2 pixels, but the layout needs 8");
+        let layout = RawLayout {stride: Some(3), ..layout};
+        assert_eq!(layout.stride_bytes().unwrap_err().to_string(), "the stride 3 is shorter than a row's 4 bytes");
+        assert!(parse_raw_layout("width = 4\nheight = 2\ndepth = 8\n", "cap.toml").is_err());
+        let wide = parse_raw_layout("width = 2000000000\nheight = 1\nbits = 32\n", "cap.toml").unwrap();
+        assert_eq!(read_raw(&[0; 4], &wide, ImageLayout::Flat).err().unwrap().to_string(),
+                   "the buffer has 4 bytes, but a Seq 2000000000 UInt32 needs 8000000000");
+        assert_eq!(write_raw(&vec!(1u32), &wide, &mut Vec::new()).unwrap_err().to_string(),
+                   "the value has 1 pixels, but the layout needs 2000000000");
+        let huge = RawLayout {height: 3, ..wide};
+        assert_eq!(read_raw(&[0; 4], &huge, ImageLayout::Flat).err().unwrap().to_string(),
+                   "the layout's 2000000000x3 frames are too big");
+        let huge = RawLayout {frames: Some(u32::MAX), height: u32::MAX, ..huge};
+        assert_eq!(write_raw(&vec!(1u32), &huge, &mut Vec::new()).unwrap_err().to_string(),
+                   "the layout's 2000000000x4294967295 frames are too big");
+    }
+}
@@ -0,0 +1,181 @@
+//! write a Space-Time value as bit-packed memory files that Verilog testbenches
+//! can load with `$readmemh` or `$readmemb`
+use crate::languages::sequence::atoms::Atom;
+use crate::languages::atom_format::{atom_bits, bits_to_digits};
+use crate::languages::sequence::serialize_values::SerializableSeqValue;
+use crate::languages::seq_value_to_st_value_and_valid_strings::{
+    convert_seq_val_to_st_atoms_and_valids, STAtomsAndValids
+};
+use crate::languages::space_time::types::Type;
+use std::error::Error;
+use std::io::Write;
+
+/// The digits of each memory word
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Radix {
+    /// for `$readmemh`
+    Hex,
+    /// for `$readmemb`
+    Bin
+}
+
+impl Radix {
+    /// The names of the radixes in command lines and manifests
+    pub const NAMES: [&'static str; 2] = ["hex", "bin"];
+
+    pub fn from_name(name: &str) -> Option<Radix> {
+        match name {
+            "hex" => Some(Radix::Hex),
+            "bin" => Some(Radix::Bin),
+            _ => None
+        }
+    }
+}
+
+/// Where lane 0 goes in each clock's bit vector
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LaneOrder {
+    /// lane 0 is in the least significant bits
+    LsbFirst,
+    /// lane 0 is in the most significant bits
+    MsbFirst
+}
+
+impl LaneOrder {
+    /// The names of the lane orders in command lines and manifests
+    pub const NAMES: [&'static str; 2] = ["lsb", "msb"];
+
+    pub fn from_name(name: &str) -> Option<LaneOrder> {
+        match name {
+            "lsb" => Some(LaneOrder::LsbFirst),
+            "msb" => Some(LaneOrder::MsbFirst),
+            _ => None
+        }
+    }
+}
+
+/// The options for memory files
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReadmemConfig {
+    pub radix: Radix,
+    pub lane_order: LaneOrder,
+    /// If true, the words of invalid clocks are all `x` digits rather than the type's
+    /// default atoms. Checkers must treat `x` as matching any value.
+    pub dont_care: bool
+}
+
+impl Default for ReadmemConfig {
+    fn default() -> Self {
+        ReadmemConfig { radix: Radix::Hex, lane_order: LaneOrder::LsbFirst, dont_care: false }
+    }
+}
+
+/// Convert a Sequence value to a Space-Time value and write it as a values memory
+/// file and a valids memory file
+pub fn convert_seq_val_to_st_readmem<T: SerializableSeqValue, V: Write, D: Write>(
+    seq_val: T, st_type: &Type, conf: &ReadmemConfig, vals_sink: &mut V, valids_sink: &mut D)
+    -> Result<(), Box<dyn Error>> {
+    let st_vals = convert_seq_val_to_st_atoms_and_valids(seq_val, st_type);
+    write_st_readmem(&st_vals, st_type, conf, vals_sink, valids_sink)
+}
+
+/// Write a Space-Time value as a values memory file with one `st_type.size()` bit word
+/// per clock, and a valids memory file with one bit per clock.
+/// The width of each word is padded up to a whole number of digits.
+///
+/// # Examples
+///
+/// ```
+/// use aetherling::formats::readmem::{convert_seq_val_to_st_readmem, ReadmemConfig};
+/// use aetherling::languages::space_time::types::Type;
+/// let mut vals_builder = Vec::new();
+/// let mut valids_builder = Vec::new();
+/// convert_seq_val_to_st_readmem(vec!(1, 3, 2, 4),
+///                               &Type::TSeq {n: 2, i: 1, elem_type: Box::from(
+///                                   Type::SSeq {n: 2, elem_type: Box::from(Type::UInt8)})},
+///                               &ReadmemConfig::default(), &mut vals_builder, &mut valids_builder).unwrap();
+///
+/// assert_eq!(String::from_utf8(vals_builder).unwrap(), "0301\n0402\n0000\n");
+/// assert_eq!(String::from_utf8(valids_builder).unwrap(), "1\n1\n0\n");
+/// ```
+pub fn write_st_readmem<V: Write, D: Write>(st_vals: &STAtomsAndValids, st_type: &Type, conf: &ReadmemConfig,
+                                            vals_sink: &mut V, valids_sink: &mut D) -> Result<(), Box<dyn Error>> {
+    for (clock_atoms, valid) in st_vals.atoms.iter().zip(st_vals.valids.iter()) {
+        let bits = pack_clock_bits(clock_atoms, st_type.atom_type(), conf.lane_order);
+        let word = bits_to_string(&bits, conf.radix);
+        if conf.dont_care && !*valid {
+            writeln!(vals_sink, "{}", "x".repeat(word.len()))?;
+        } else {
+            writeln!(vals_sink, "{}", word)?;
+        }
+        writeln!(valids_sink, "{}", *valid as u8)?;
+    }
+    vals_sink.flush()?;
+    valids_sink.flush()?;
+    Ok(())
+}
+
+/// Pack one clock's lanes into a bit vector, least significant bit first.
+/// Signed atoms are in two's complement. Tuple atoms are concatenated like
+/// Verilog's `{left, right}`, so the right field is in the low bits.
+///
+/// # Examples
+///
+/// ```
+/// use aetherling::formats::readmem::{pack_clock_bits, LaneOrder};
+/// use aetherling::languages::sequence::atoms::Atom;
+/// use aetherling::languages::space_time::types::Type;
+/// let bits = pack_clock_bits(&[Atom::Bit(true), Atom::Bit(false)], &Type::Bit, LaneOrder::LsbFirst);
+///
+/// assert_eq!(bits, vec!(true, false));
+/// ```
+pub fn pack_clock_bits(clock_atoms: &[Atom], atom_type: &Type, lane_order: LaneOrder) -> Vec<bool> {
+    let mut bits = Vec::with_capacity(clock_atoms.len() * atom_type.size() as usize);
+    let mut pack_lane = |atom: &Atom| bits.extend(atom_bits(atom, atom_type));
+    match lane_order {
+        LaneOrder::LsbFirst => clock_atoms.iter().for_each(&mut pack_lane),
+        LaneOrder::MsbFirst => clock_atoms.iter().rev().for_each(&mut pack_lane)
+    }
+    bits
+}
+
+/// Print a bit vector, stored least significant bit first, most significant digit first.
+/// A zero-width vector prints as a single `0`.
+pub fn bits_to_string(bits: &[bool], radix: Radix) -> String {
+    match radix {
+        Radix::Hex => bits_to_digits(bits, 4),
+        Radix::Bin => bits_to_digits(bits, 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pack_signed_tuple_msb_first() {
+        let atom_type = Type::ATuple {left: Box::from(Type::Int8), right: Box::from(Type::Bit)};
+        let clock_atoms = vec!(Atom::Tuple(Box::from(Atom::Int(-2)), Box::from(Atom::Bit(true))),
+                               Atom::Tuple(Box::from(Atom::Int(1)), Box::from(Atom::Bit(false))));
+        let bits = pack_clock_bits(&clock_atoms, &atom_type, LaneOrder::MsbFirst);
+        assert_eq!(bits_to_string(&bits, Radix::Bin), "111111101000000010");
+        assert_eq!(bits_to_string(&bits, Radix::Hex), "3fa02");
+    }
+
+    #[test]
+    fn test_pack_wider_than_64_bits() {
+        let clock_atoms = vec!(Atom::UInt(0xffff_ffff), Atom::UInt(0), Atom::UInt(1));
+        let bits = pack_clock_bits(&clock_atoms, &Type::UInt32, LaneOrder::LsbFirst);
+        assert_eq!(bits_to_string(&bits, Radix::Hex), "0000000100000000ffffffff");
+    }
+
+    #[test]
+    fn test_readmem_dont_care() {
+        let mut vals_builder = Vec::new();
+        let mut valids_builder = Vec::new();
+        let conf = ReadmemConfig { radix: Radix::Bin, dont_care: true, ..ReadmemConfig::default() };
+        convert_seq_val_to_st_readmem(vec!(1), &Type::TSeq {n: 1, i: 1, elem_type: Box::from(Type::UInt8)},
+                                      &conf, &mut vals_builder, &mut valids_builder).unwrap();
+        assert_eq!(String::from_utf8(vals_builder).unwrap(), "00000001\nxxxxxxxx\n");
+    }
+}
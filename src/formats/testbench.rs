@@ -0,0 +1,301 @@
+//! write a self-checking SystemVerilog testbench that drives a circuit's inputs from
+//! `$readmemh` memory files and compares its outputs against expected memory files
+use crate::languages::sequence::serialize_values::SerializableSeqValue;
+use crate::languages::ports::{check_same_clocks, convert_seq_ports_to_st_ports, SeqPort, STPort};
+use super::readmem::{write_st_readmem, ReadmemConfig};
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// The options for a generated testbench
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestbenchConfig {
+    /// The name of the testbench module
+    pub module_name: String,
+    /// The name of the module under test
+    pub dut_name: String,
+    /// The circuit's clock port
+    pub clock_name: String,
+    /// An input port driven with the input ports' valids, such as Aetherling's `valid_up`.
+    /// Every input port must have the same valids.
+    pub valid_in_name: Option<String>,
+    /// An output port that is checked high on the outputs' valid clocks, such as Aetherling's `valid_down`
+    pub valid_out_name: Option<String>,
+    /// The number of clocks between driving the first input and the first output clock
+    pub output_latency: u32,
+    /// The directory the testbench loads the memory files from, as written in `$readmemh`
+    pub mem_dir: String,
+    /// The half period of the clock in the testbench's time unit, at least 1.
+    /// The outputs are checked half way through the low half of each clock.
+    pub half_period: u32
+}
+
+impl Default for TestbenchConfig {
+    fn default() -> Self {
+        TestbenchConfig {
+            module_name: String::from("tb"),
+            dut_name: String::from("Top"),
+            clock_name: String::from("CLK"),
+            valid_in_name: Some(String::from("valid_up")),
+            valid_out_name: Some(String::from("valid_down")),
+            output_latency: 0,
+            mem_dir: String::new(),
+            half_period: 5
+        }
+    }
+}
+
+/// Convert the Sequence values on a circuit's ports to Space-Time values and write a testbench
+/// named `<module_name>.sv` and its memory files to `dir`
+pub fn convert_seq_ports_to_sv_testbench<T: SerializableSeqValue, U: SerializableSeqValue>(
+    inputs: Vec<SeqPort<T>>, outputs: Vec<SeqPort<U>>, conf: &TestbenchConfig,
+    dir: &Path) -> Result<(), Box<dyn Error>> {
+    let st_inputs = convert_seq_ports_to_st_ports(inputs)?;
+    let st_outputs = convert_seq_ports_to_st_ports(outputs)?;
+    let mut tb_file = BufWriter::new(File::create(dir.join(format!("{}.sv", conf.module_name)))?);
+    write_sv_testbench(&st_inputs, &st_outputs, conf, &mut tb_file)?;
+    write_sv_testbench_memories(&st_inputs, &st_outputs, dir)
+}
+
+/// Write each port's values to `<port>.hex` and valids to `<port>_valid.hex` in `dir`,
+/// the memory files that `write_sv_testbench` loads
+pub fn write_sv_testbench_memories(inputs: &[STPort], outputs: &[STPort],
+                                   dir: &Path) -> Result<(), Box<dyn Error>> {
+    for port in inputs.iter().chain(outputs.iter()) {
+        let mut vals_file = BufWriter::new(File::create(dir.join(format!("{}.hex", port.name)))?);
+        let mut valids_file = BufWriter::new(File::create(dir.join(format!("{}_valid.hex", port.name)))?);
+        write_st_readmem(&port.st_vals, &port.st_type, &ReadmemConfig::default(),
+                         &mut vals_file, &mut valids_file)?;
+    }
+    Ok(())
+}
+
+/// Write a SystemVerilog testbench that instantiates the module under test, drives each input
+/// port from its memory file on each falling edge, and on the outputs' valid clocks compares each lane
+/// of each output port against its expected memory file before the next rising edge,
+/// printing the clock and lane of every mismatch.
+/// Each port of the module under test is one packed vector of `st_type.size()` bits with lane 0
+/// in the least significant bits, the layout of `readmem`'s words.
+///
+/// # Examples
+///
+/// ```
+/// use aetherling::formats::testbench::{write_sv_testbench, TestbenchConfig};
+/// use aetherling::languages::ports::{convert_seq_ports_to_st_ports, SeqPort};
+/// use aetherling::languages::space_time::types::Type;
+/// let st_type = Type::TSeq {n: 2, i: 0, elem_type: Box::from(
+///     Type::SSeq {n: 2, elem_type: Box::from(Type::UInt8)})};
+/// let ports = convert_seq_ports_to_st_ports(vec!(
+///     SeqPort {name: String::from("O"), seq_val: vec!(1, 2, 3, 4), st_type: st_type.clone()}
+/// )).unwrap();
+/// let mut builder = Vec::new();
+/// write_sv_testbench(&[], &ports, &TestbenchConfig::default(), &mut builder).unwrap();
+/// let tb = String::from_utf8(builder).unwrap();
+///
+/// assert!(tb.contains("wire [15:0] O;"));
+/// assert!(tb.contains("if (O[lane*8 +: 8] !== O_mem[out_clk][lane*8 +: 8]) begin"));
+/// ```
+pub fn write_sv_testbench<W: Write>(inputs: &[STPort], outputs: &[STPort], conf: &TestbenchConfig,
+                                    sink: &mut W) -> Result<(), Box<dyn Error>> {
+    check_same_clocks(inputs.iter().map(|p| (p.name.as_str(), &p.st_type)))?;
+    check_same_clocks(outputs.iter().map(|p| (p.name.as_str(), &p.st_type)))?;
+    if let Some(port) = inputs.iter().chain(outputs.iter()).find(|p| p.st_vals.valids.is_empty()) {
+        return Err(format!("port {} has no clocks to drive or check", port.name).into());
+    }
+    if let Some(first_input) = inputs.first() {
+        if let Some(port) = inputs.iter().find(|p| p.st_vals.valids != first_input.st_vals.valids) {
+            return Err(format!("input {} has different valids than input {}", port.name, first_input.name).into());
+        }
+    }
+    if conf.half_period == 0 {
+        return Err("the clock's half period must be at least 1".into());
+    }
+    let input_clocks = inputs.first().map_or(0, |p| p.st_vals.valids.len());
+    let output_clocks = outputs.first().map_or(0, |p| p.st_vals.valids.len());
+    let total_clocks = std::cmp::max(input_clocks, conf.output_latency as usize + output_clocks);
+    let mem_path = |file: &str| format!("{}{}", conf.mem_dir, file);
+
+    writeln!(sink, "// generated by aetherling from Sequence values and Space-Time types")?;
+    writeln!(sink, "`timescale 1ns/1ps")?;
+    writeln!(sink, "module {};", conf.module_name)?;
+    writeln!(sink, "    localparam INPUT_CLOCKS = {};", input_clocks)?;
+    writeln!(sink, "    localparam OUTPUT_CLOCKS = {};", output_clocks)?;
+    writeln!(sink, "    localparam OUTPUT_LATENCY = {};", conf.output_latency)?;
+    writeln!(sink, "    localparam TOTAL_CLOCKS = {};", total_clocks)?;
+    writeln!(sink)?;
+    writeln!(sink, "    reg {} = 0;", conf.clock_name)?;
+    writeln!(sink, "    always #{} {1} = ~{1};", conf.half_period, conf.clock_name)?;
+    if let Some(valid_in) = &conf.valid_in_name {
+        writeln!(sink, "    reg {} = 0;", valid_in)?;
+    }
+    if let Some(valid_out) = &conf.valid_out_name {
+        writeln!(sink, "    wire {};", valid_out)?;
+    }
+    for port in inputs {
+        let width = port_width(port);
+        writeln!(sink, "    reg [{}:0] {} = 0;", width - 1, port.name)?;
+        writeln!(sink, "    reg [{}:0] {}_mem [0:INPUT_CLOCKS-1];", width - 1, port.name)?;
+        writeln!(sink, "    reg {}_valid_mem [0:INPUT_CLOCKS-1];", port.name)?;
+    }
+    for port in outputs {
+        let width = port_width(port);
+        writeln!(sink, "    wire [{}:0] {};", width - 1, port.name)?;
+        writeln!(sink, "    reg [{}:0] {}_mem [0:OUTPUT_CLOCKS-1];", width - 1, port.name)?;
+        writeln!(sink, "    reg {}_valid_mem [0:OUTPUT_CLOCKS-1];", port.name)?;
+    }
+    writeln!(sink, "    integer clk, out_clk, lane;")?;
+    writeln!(sink, "    integer errors = 0;")?;
+    writeln!(sink)?;
+
+    let mut connections = vec!(format!(".{0}({0})", conf.clock_name));
+    connections.extend(conf.valid_in_name.iter().chain(conf.valid_out_name.iter())
+        .map(|valid| format!(".{0}({0})", valid)));
+    connections.extend(inputs.iter().chain(outputs.iter()).map(|port| format!(".{0}({0})", port.name)));
+    writeln!(sink, "    {} dut (", conf.dut_name)?;
+    writeln!(sink, "        {}", connections.join(",\n        "))?;
+    writeln!(sink, "    );")?;
+    writeln!(sink)?;
+
+    writeln!(sink, "    initial begin")?;
+    for port in inputs.iter().chain(outputs.iter()) {
+        writeln!(sink, "        $readmemh(\"{}\", {}_mem);", mem_path(&format!("{}.hex", port.name)), port.name)?;
+        writeln!(sink, "        $readmemh(\"{}\", {}_valid_mem);",
+                 mem_path(&format!("{}_valid.hex", port.name)), port.name)?;
+    }
+    writeln!(sink, "        for (clk = 0; clk < TOTAL_CLOCKS; clk = clk + 1) begin")?;
+    // change the inputs on the falling edge so the circuit samples each clock's inputs
+    // on exactly one rising edge, the one after they're driven
+    writeln!(sink, "            @(negedge {});", conf.clock_name)?;
+    if let Some(first_input) = inputs.first() {
+        writeln!(sink, "            if (clk < INPUT_CLOCKS) begin")?;
+        if let Some(valid_in) = &conf.valid_in_name {
+            writeln!(sink, "                {} = {}_valid_mem[clk];", valid_in, first_input.name)?;
+        }
+        for port in inputs {
+            writeln!(sink, "                {0} = {0}_mem[clk];", port.name)?;
+        }
+        writeln!(sink, "            end")?;
+        if let Some(valid_in) = &conf.valid_in_name {
+            writeln!(sink, "            else {} = 0;", valid_in)?;
+        }
+    } else if let Some(valid_in) = &conf.valid_in_name {
+        writeln!(sink, "            {} = 1;", valid_in)?;
+    }
+    // check the outputs before the next rising edge, once they have settled
+    writeln!(sink, "            #{};", conf.half_period as f64 / 2.0)?;
+    writeln!(sink, "            out_clk = clk - OUTPUT_LATENCY;")?;
+    if let Some(first_output) = outputs.first() {
+        writeln!(sink, "            if (out_clk >= 0 && out_clk < OUTPUT_CLOCKS && {}_valid_mem[out_clk]) begin",
+                 first_output.name)?;
+        if let Some(valid_out) = &conf.valid_out_name {
+            writeln!(sink, "                if ({} !== 1'b1) begin", valid_out)?;
+            writeln!(sink, "                    $display(\"{} not high on clock %0d\", out_clk);", valid_out)?;
+            writeln!(sink, "                    errors = errors + 1;")?;
+            writeln!(sink, "                end")?;
+        }
+        for port in outputs {
+            let lane_width = std::cmp::max(1, port.st_type.atom_type().size());
+            writeln!(sink, "                for (lane = 0; lane < {}; lane = lane + 1) begin",
+                     port.st_type.atoms_per_valid())?;
+            writeln!(sink, "                    if ({0}[lane*{1} +: {1}] !== {0}_mem[out_clk][lane*{1} +: {1}]) begin",
+                     port.name, lane_width)?;
+            writeln!(sink, "                        $display(\"mismatch on port {0} clock %0d lane %0d: expected %h, got %h\",", port.name)?;
+            writeln!(sink, "                                 out_clk, lane, {0}_mem[out_clk][lane*{1} +: {1}], {0}[lane*{1} +: {1}]);",
+                     port.name, lane_width)?;
+            writeln!(sink, "                        errors = errors + 1;")?;
+            writeln!(sink, "                    end")?;
+            writeln!(sink, "                end")?;
+        }
+        writeln!(sink, "            end")?;
+    }
+    writeln!(sink, "        end")?;
+    writeln!(sink, "        if (errors == 0) $display(\"PASS\");")?;
+    writeln!(sink, "        else $display(\"FAIL: %0d mismatches\", errors);")?;
+    writeln!(sink, "        $finish;")?;
+    writeln!(sink, "    end")?;
+    writeln!(sink, "endmodule")?;
+    sink.flush()?;
+    Ok(())
+}
+
+/// The width of a port's packed vector, at least 1 so it can be declared
+fn port_width(port: &STPort) -> u32 {
+    std::cmp::max(1, port.st_type.size())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::languages::seq_value_to_st_value_and_valid_strings::STAtomsAndValids;
+    use crate::languages::space_time::types::Type;
+
+    #[test]
+    fn test_testbench_drives_inputs_and_valid() {
+        let st_type = Type::TSeq {n: 2, i: 1, elem_type: Box::from(Type::Int8)};
+        let ports = convert_seq_ports_to_st_ports(vec!(
+            SeqPort {name: String::from("I"), seq_val: vec!(1, -1), st_type: st_type.clone()}
+        )).unwrap();
+        let outputs = convert_seq_ports_to_st_ports(vec!(
+            SeqPort {name: String::from("O"), seq_val: vec!(2, -2), st_type: st_type.clone()}
+        )).unwrap();
+        let conf = TestbenchConfig {output_latency: 2, mem_dir: String::from("mems/"), ..TestbenchConfig::default()};
+        let mut builder = Vec::new();
+        write_sv_testbench(&ports, &outputs, &conf, &mut builder).unwrap();
+        let tb = String::from_utf8(builder).unwrap();
+        assert!(tb.contains("localparam TOTAL_CLOCKS = 5;"));
+        assert!(tb.contains("$readmemh(\"mems/I.hex\", I_mem);"));
+        assert!(tb.contains("valid_up = I_valid_mem[clk];"));
+        assert!(tb.contains("        .CLK(CLK),\n        .valid_up(valid_up),\n        .valid_down(valid_down),\n\
+                             \x20       .I(I),\n        .O(O)\n"));
+    }
+
+    #[test]
+    fn test_inputs_change_on_falling_edge() {
+        let st_type = Type::TSeq {n: 2, i: 0, elem_type: Box::from(Type::UInt8)};
+        let ports = convert_seq_ports_to_st_ports(vec!(
+            SeqPort {name: String::from("I"), seq_val: vec!(1, 2), st_type: st_type.clone()}
+        )).unwrap();
+        let outputs = convert_seq_ports_to_st_ports(vec!(
+            SeqPort {name: String::from("O"), seq_val: vec!(1, 2), st_type: st_type.clone()}
+        )).unwrap();
+        // a registered circuit's output is its input one clock later
+        let conf = TestbenchConfig {output_latency: 1, ..TestbenchConfig::default()};
+        let mut builder = Vec::new();
+        write_sv_testbench(&ports, &outputs, &conf, &mut builder).unwrap();
+        let tb = String::from_utf8(builder).unwrap();
+        let body = &tb[tb.find("for (clk = 0;").unwrap()..];
+        let negedge = body.find("@(negedge CLK);").unwrap();
+        let drive = body.find("I = I_mem[clk];").unwrap();
+        let check = body.find("out_clk = clk - OUTPUT_LATENCY;").unwrap();
+        // one clock edge wait per iteration, so each input is sampled by one rising edge
+        assert!(negedge < drive && drive < check);
+        assert!(!body.contains("@(posedge"));
+        assert!(body.contains("            end\n            else valid_up = 0;\n"));
+        assert!(tb.contains("localparam TOTAL_CLOCKS = 3;"));
+        assert!(body.contains("            #2.5;\n"));
+    }
+
+    #[test]
+    fn test_bad_ports_and_half_period() {
+        let port = |name: &str, st_type: Type| convert_seq_ports_to_st_ports(vec!(
+            SeqPort {name: String::from(name), seq_val: vec!(1, 2), st_type}
+        )).unwrap().remove(0);
+        let err = |inputs: &[STPort], conf: &TestbenchConfig|
+            write_sv_testbench(inputs, &[], conf, &mut Vec::new()).unwrap_err().to_string();
+        let a = port("A", Type::TSeq {n: 2, i: 1, elem_type: Box::from(Type::UInt8)});
+        let b = port("B", Type::TSeq {n: 1, i: 2, elem_type: Box::from(
+            Type::SSeq {n: 2, elem_type: Box::from(Type::UInt8)})});
+        let empty = STPort {name: String::from("E"), st_type: Type::TSeq {n: 0, i: 0, elem_type: Box::from(Type::UInt8)},
+                            st_vals: STAtomsAndValids {atoms: Vec::new(), valids: Vec::new()}};
+        let conf = TestbenchConfig::default();
+        assert_eq!(err(&[a.clone(), b], &conf), "input B has different valids than input A");
+        assert_eq!(err(&[empty], &conf), "port E has no clocks to drive or check");
+        assert_eq!(err(std::slice::from_ref(&a), &TestbenchConfig {half_period: 0, ..conf}),
+                   "the clock's half period must be at least 1");
+        let mut builder = Vec::new();
+        write_sv_testbench(&[a], &[], &TestbenchConfig {half_period: 1, ..TestbenchConfig::default()},
+                           &mut builder).unwrap();
+        assert!(String::from_utf8(builder).unwrap().contains("            #0.5;\n"));
+    }
+}
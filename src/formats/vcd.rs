@@ -0,0 +1,254 @@
+//! write a Space-Time value as a Value Change Dump so it can be viewed in a
+//! waveform viewer like GTKWave next to a simulation's waveform
+use crate::languages::sequence::serialize_values::SerializableSeqValue;
+use crate::languages::seq_value_to_st_value_and_valid_strings::{
+    convert_seq_val_to_st_atoms_and_valids, STAtomsAndValids
+};
+use crate::languages::space_time::types::Type;
+use crate::languages::ports::{check_same_clocks, STPort};
+use super::NamedSTValue;
+use std::error::Error;
+use std::io::Write;
+
+/// The options for a Value Change Dump
+#[derive(Debug, Clone, PartialEq)]
+pub struct VcdConfig {
+    /// The name of the scope holding all the signals
+    pub scope: String,
+    /// The VCD time unit, such as `1ns`
+    pub timescale: String,
+    /// The number of time units per clock
+    pub clock_period: u64,
+    /// If true, the lanes of invalid clocks are `x` rather than the type's default atoms.
+    /// Checkers must treat `x` as matching any value.
+    pub dont_care: bool
+}
+
+impl Default for VcdConfig {
+    fn default() -> Self {
+        VcdConfig {
+            scope: String::from("aetherling"),
+            timescale: String::from("1ns"),
+            clock_period: 1,
+            dont_care: false
+        }
+    }
+}
+
+/// One wire in the dump: a value's valid, or a field of an atom in one of a value's lanes
+struct Signal {
+    name: String,
+    width: u32,
+    value: usize,
+    // None for the valid signal
+    lane_field: Option<(usize, usize)>
+}
+
+/// Convert a Sequence value to a Space-Time value and write it as a Value Change Dump
+pub fn convert_seq_val_to_st_vcd<T: SerializableSeqValue, W: Write>(
+    seq_val: T, st_type: &Type, conf: &VcdConfig, sink: &mut W) -> Result<(), Box<dyn Error>> {
+    let st_vals = convert_seq_val_to_st_atoms_and_valids(seq_val, st_type);
+    write_st_vcd(&st_vals, st_type, conf, sink)
+}
+
+/// Write a Space-Time value as a Value Change Dump.
+/// There is a `valid` signal and a `lane_<n>` signal per lane with the width of the
+/// Space-Time type's atom. Tuple atoms are split into one signal per field,
+/// such as `lane_0_left` and `lane_0_right`.
+///
+/// # Examples
+///
+/// ```
+/// use aetherling::formats::vcd::{convert_seq_val_to_st_vcd, VcdConfig};
+/// use aetherling::languages::space_time::types::Type;
+/// let mut builder = Vec::new();
+/// convert_seq_val_to_st_vcd(vec!(1, 3), &Type::TSeq {n: 2, i: 0, elem_type: Box::from(Type::UInt8)},
+///                           &VcdConfig::default(), &mut builder).unwrap();
+/// let vcd = String::from_utf8(builder).unwrap();
+///
+/// assert!(vcd.contains("$var wire 8 \" lane_0 $end"));
+/// assert!(vcd.contains("#1\nb00000011 \"\n"));
+/// ```
+pub fn write_st_vcd<W: Write>(st_vals: &STAtomsAndValids, st_type: &Type,
+                              conf: &VcdConfig, sink: &mut W) -> Result<(), Box<dyn Error>> {
+    write_named_st_vals_vcd(&[NamedSTValue::single(st_vals, st_type)], conf, sink)
+}
+
+/// Write the Space-Time values of a module's ports as one Value Change Dump.
+/// Each port has a `<port>_valid` signal and `<port>_<n>` signals for its lanes.
+pub fn write_st_ports_vcd<W: Write>(st_ports: &[STPort], conf: &VcdConfig,
+                                    sink: &mut W) -> Result<(), Box<dyn Error>> {
+    check_same_clocks(st_ports.iter().map(|p| (p.name.as_str(), &p.st_type)))?;
+    let named_vals: Vec<NamedSTValue> = st_ports.iter().map(NamedSTValue::port).collect();
+    write_named_st_vals_vcd(&named_vals, conf, sink)
+}
+
+fn write_named_st_vals_vcd<W: Write>(named_vals: &[NamedSTValue], conf: &VcdConfig,
+                                     sink: &mut W) -> Result<(), Box<dyn Error>> {
+    let mut signals: Vec<Signal> = Vec::new();
+    for (value, named_val) in named_vals.iter().enumerate() {
+        signals.push(Signal { name: named_val.valid_name.clone(), width: 1, value, lane_field: None });
+        let fields = named_val.st_type.atom_type().atom_fields();
+        for lane in 0..named_val.st_type.atoms_per_valid() {
+            for (field, (suffix, field_type)) in fields.iter().enumerate() {
+                // units have no wires
+                if field_type.size() > 0 {
+                    signals.push(Signal {
+                        name: named_val.lane_name(lane, suffix),
+                        width: field_type.size(),
+                        value,
+                        lane_field: Some((lane as usize, field))
+                    });
+                }
+            }
+        }
+    }
+
+    writeln!(sink, "$timescale {} $end", conf.timescale)?;
+    writeln!(sink, "$scope module {} $end", conf.scope)?;
+    for (idx, signal) in signals.iter().enumerate() {
+        writeln!(sink, "$var wire {} {} {} $end", signal.width, vcd_id(idx), signal.name)?;
+    }
+    writeln!(sink, "$upscope $end")?;
+    writeln!(sink, "$enddefinitions $end")?;
+
+    let total_time = named_vals.first().map_or(0, |v| v.st_vals.valids.len());
+    let mut prev_strs: Vec<Option<String>> = vec![None; signals.len()];
+    for t in 0..total_time {
+        writeln!(sink, "#{}", t as u64 * conf.clock_period)?;
+        if t == 0 {
+            writeln!(sink, "$dumpvars")?;
+        }
+        // only dump the signals that changed since the last clock
+        for (idx, (signal, prev_str)) in signals.iter().zip(prev_strs.iter_mut()).enumerate() {
+            let st_vals = named_vals[signal.value].st_vals;
+            let cur_str = match signal.lane_field {
+                None => vcd_value_str(st_vals.valids[t] as u64, signal.width, &vcd_id(idx)),
+                Some(_) if conf.dont_care && !st_vals.valids[t] => vcd_dont_care_str(signal.width, &vcd_id(idx)),
+                Some((lane, field)) => {
+                    let bits = st_vals.atoms[t][lane].leaves()[field].to_bits(signal.width);
+                    vcd_value_str(bits, signal.width, &vcd_id(idx))
+                }
+            };
+            if prev_str.as_ref() != Some(&cur_str) {
+                writeln!(sink, "{}", cur_str)?;
+                *prev_str = Some(cur_str);
+            }
+        }
+        if t == 0 {
+            writeln!(sink, "$end")?;
+        }
+    }
+    // mark the end of the last clock so viewers show it
+    writeln!(sink, "#{}", total_time as u64 * conf.clock_period)?;
+    sink.flush()?;
+    Ok(())
+}
+
+/// Get the printable ASCII identifier code for the signal at index `idx`
+fn vcd_id(idx: usize) -> String {
+    let mut id = String::new();
+    let mut rest = idx;
+    loop {
+        id.push((b'!' + (rest % 94) as u8) as char);
+        rest /= 94;
+        if rest == 0 {
+            return id;
+        }
+        rest -= 1;
+    }
+}
+
+fn vcd_value_str(bits: u64, width: u32, id: &str) -> String {
+    if width == 1 {
+        format!("{}{}", bits, id)
+    } else {
+        format!("b{:0width$b} {}", bits, id, width = width as usize)
+    }
+}
+
+fn vcd_dont_care_str(width: u32, id: &str) -> String {
+    if width == 1 {
+        format!("x{}", id)
+    } else {
+        format!("bx {}", id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::languages::ports::{convert_seq_ports_to_st_ports, SeqPort};
+
+    #[test]
+    fn test_vcd_ids_unique() {
+        let ids: Vec<String> = (0..10000).map(vcd_id).collect();
+        let mut deduped = ids.clone();
+        deduped.sort();
+        deduped.dedup();
+        assert_eq!(ids.len(), deduped.len());
+        assert_eq!(vcd_id(0), "!");
+        assert_eq!(vcd_id(94), "!!");
+    }
+
+    #[test]
+    fn test_vcd_tseq_2_1_tuple() {
+        let mut builder = Vec::new();
+        convert_seq_val_to_st_vcd(vec!((-1, true), (2, false)),
+                                  &Type::TSeq {n: 2, i: 1, elem_type: Box::from(
+                                      Type::ATuple {left: Box::from(Type::Int8),
+                                                    right: Box::from(Type::Bit)})},
+                                  &VcdConfig::default(), &mut builder).unwrap();
+        let vcd = String::from_utf8(builder).unwrap();
+        assert_eq!(vcd, String::from(
+            "$timescale 1ns $end\n\
+             $scope module aetherling $end\n\
+             $var wire 1 ! valid $end\n\
+             $var wire 8 \" lane_0_left $end\n\
+             $var wire 1 # lane_0_right $end\n\
+             $upscope $end\n\
+             $enddefinitions $end\n\
+             #0\n\
+             $dumpvars\n\
+             1!\n\
+             b11111111 \"\n\
+             1#\n\
+             $end\n\
+             #1\n\
+             b00000010 \"\n\
+             0#\n\
+             #2\n\
+             0!\n\
+             b00000000 \"\n\
+             #3\n"));
+    }
+
+    #[test]
+    fn test_vcd_ports() {
+        let st_type = Type::TSeq {n: 2, i: 0, elem_type: Box::from(Type::Bit)};
+        let st_ports = convert_seq_ports_to_st_ports(vec!(
+            SeqPort {name: String::from("in0"), seq_val: vec!(true, true), st_type: st_type.clone()},
+            SeqPort {name: String::from("in1"), seq_val: vec!(false, true), st_type: st_type.clone()}
+        )).unwrap();
+        let mut builder = Vec::new();
+        write_st_ports_vcd(&st_ports, &VcdConfig::default(), &mut builder).unwrap();
+        let vcd = String::from_utf8(builder).unwrap();
+        assert!(vcd.contains("$var wire 1 ! in0_valid $end\n\
+                              $var wire 1 \" in0_0 $end\n\
+                              $var wire 1 # in1_valid $end\n\
+                              $var wire 1 $ in1_0 $end\n"));
+        assert!(vcd.ends_with("#1\n1$\n#2\n"));
+    }
+
+    #[test]
+    fn test_vcd_dont_care() {
+        let mut builder = Vec::new();
+        let conf = VcdConfig { dont_care: true, ..VcdConfig::default() };
+        convert_seq_val_to_st_vcd(vec!((3, true)), &Type::TSeq {n: 1, i: 1, elem_type: Box::from(
+                                      Type::ATuple {left: Box::from(Type::UInt8),
+                                                    right: Box::from(Type::Bit)})},
+                                  &conf, &mut builder).unwrap();
+        let vcd = String::from_utf8(builder).unwrap();
+        assert!(vcd.ends_with("#1\n0!\nbx \"\nx#\n#2\n"));
+    }
+}
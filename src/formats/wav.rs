@@ -0,0 +1,301 @@
+//! read and write PCM WAV audio as a Sequence value of samples,
+//! so 1D pipelines such as FIR filters can be tested on real audio clips
+use crate::languages::sequence::atoms::Atom;
+use crate::languages::sequence::serialize_values::SerializableSeqValue;
+use crate::languages::sequence::types::Type as SeqType;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::io::Write;
+
+/// The format code of integer PCM samples
+const FORMAT_PCM: u16 = 1;
+/// The format code of a `fmt ` chunk whose format is in a sub-format GUID
+const FORMAT_EXTENSIBLE: u16 = 0xfffe;
+
+/// PCM audio
+#[derive(Debug, Clone, PartialEq)]
+pub struct Audio {
+    pub sample_rate: u32,
+    /// 1 for mono or 2 for stereo
+    pub channels: u16,
+    /// The bits of each sample, 8, 16, or 32
+    pub bits: u16,
+    /// The samples of every channel, interleaved. 8-bit samples are unsigned, as they are in the file,
+    /// and deeper samples are signed.
+    pub samples: Vec<i32>
+}
+
+/// The options for making audio from a Sequence value
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioConfig {
+    pub sample_rate: u32,
+    /// 1 for mono or 2 for stereo. If 0, stereo if the value's atoms are tuples.
+    pub channels: u16,
+    pub bits: u16
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        AudioConfig { sample_rate: 44100, channels: 0, bits: 16 }
+    }
+}
+
+impl Audio {
+    /// Get the type of a sample: `UInt8`, `Int16`, or `Int32`
+    pub fn sample_type(&self) -> SeqType {
+        match self.bits {
+            8 => SeqType::UInt8,
+            16 => SeqType::Int16,
+            _ => SeqType::Int32
+        }
+    }
+
+    /// Get the number of samples in each channel, or 0 if there are no channels
+    pub fn len(&self) -> usize {
+        self.samples.len().checked_div(self.channels as usize).unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Get the type of the audio's Sequence value, `Seq n sample` for mono audio
+    /// and `Seq n (ATuple left right)` for stereo audio
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use aetherling::formats::wav::Audio;
+    /// let audio = Audio {sample_rate: 8000, channels: 2, bits: 16, samples: vec!(1, -1, 2, -2)};
+    ///
+    /// assert_eq!(audio.seq_type().to_string(), "Seq 2 (ATuple Int16 Int16)");
+    /// ```
+    pub fn seq_type(&self) -> SeqType {
+        let sample = self.sample_type();
+        let elem_type = if self.channels == 2 {
+            SeqType::ATuple {left: Box::new(sample.clone()), right: Box::new(sample)}
+        } else {
+            sample
+        };
+        SeqType::Seq {n: self.len() as u32, elem_type: Box::new(elem_type)}
+    }
+
+    /// Get the audio's Sequence value, of type `seq_type()`
+    pub fn to_seq_value(&self) -> Box<dyn SerializableSeqValue> {
+        let sample = |s: i32| -> Box<dyn SerializableSeqValue> {
+            if self.bits == 8 { Box::new(s as u32) } else { Box::new(s) }
+        };
+        let elems: Vec<Box<dyn SerializableSeqValue>> = if self.channels == 2 {
+            self.samples.chunks(2).map(|lr| Box::new((sample(lr[0]), sample(lr[1]))) as Box<dyn SerializableSeqValue>)
+                .collect()
+        } else {
+            self.samples.iter().map(|s| sample(*s)).collect()
+        };
+        Box::new(elems)
+    }
+
+    /// Make audio from a Sequence value of samples or of `ATuple left right` pairs,
+    /// clamping samples to the bit depth's range.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use aetherling::formats::wav::{Audio, AudioConfig};
+    /// let audio = Audio::from_seq_value(&vec!((1, -1), (40000, 2)), &AudioConfig::default()).unwrap();
+    ///
+    /// assert_eq!(audio.channels, 2);
+    /// assert_eq!(audio.samples, vec!(1, -1, 32767, 2));
+    /// ```
+    pub fn from_seq_value<T: SerializableSeqValue + ?Sized>(value: &T, conf: &AudioConfig) -> Result<Audio, Box<dyn Error>> {
+        let (min, max) = match conf.bits {
+            8 => (0, u8::MAX as i64),
+            16 => (i16::MIN as i64, i16::MAX as i64),
+            32 => (i32::MIN as i64, i32::MAX as i64),
+            bits => return Err(format!("the sample size {} isn't 8, 16, or 32 bits", bits).into())
+        };
+        let sample = |atom: &Atom| -> Result<i32, Box<dyn Error>> {
+            match atom {
+                Atom::Bit(b) => Ok(*b as i32),
+                Atom::Int(i) => Ok((*i as i64).clamp(min, max) as i32),
+                Atom::UInt(u) => Ok((*u as i64).clamp(min, max) as i32),
+                _ => Err(format!("{:?} isn't a sample", atom).into())
+            }
+        };
+        let mut atoms = Vec::new();
+        value.convert_to_flat_atoms(&mut atoms);
+        let channels = match conf.channels {
+            0 if matches!(atoms.first(), Some(Atom::Tuple(_, _))) => 2,
+            0 => 1,
+            1 | 2 => conf.channels,
+            channels => return Err(format!("{} channels aren't supported, only 1 or 2", channels).into())
+        };
+        let mut samples = Vec::with_capacity(atoms.len() * channels as usize);
+        for atom in atoms.iter() {
+            match (channels, atom) {
+                (2, Atom::Tuple(left, right)) => {
+                    samples.push(sample(left)?);
+                    samples.push(sample(right)?);
+                }
+                (2, _) => return Err(format!("{:?} isn't an ATuple left right", atom).into()),
+                _ => samples.push(sample(atom)?)
+            }
+        }
+        Ok(Audio { sample_rate: conf.sample_rate, channels, bits: conf.bits, samples })
+    }
+}
+
+/// Read a PCM WAV file of 8-, 16-, or 32-bit samples in 1 or 2 channels.
+/// A data chunk longer than the file, as streaming encoders write, runs to the end of the file.
+///
+/// # Examples
+///
+/// ```
+/// use aetherling::formats::wav::{read_wav, write_wav, Audio};
+/// let audio = Audio {sample_rate: 8000, channels: 1, bits: 16, samples: vec!(0, -300, 300)};
+/// let mut builder = Vec::new();
+/// write_wav(&audio, &mut builder).unwrap();
+///
+/// assert_eq!(builder.len(), 44 + 6);
+/// assert_eq!(read_wav(&builder).unwrap(), audio);
+/// ```
+pub fn read_wav(src: &[u8]) -> Result<Audio, Box<dyn Error>> {
+    if src.len() < 12 || &src[..4] != b"RIFF" || &src[8..12] != b"WAVE" {
+        return Err("not a RIFF WAVE file".into());
+    }
+    let u16_at = |chunk: &[u8], pos: usize| u16::from_le_bytes([chunk[pos], chunk[pos + 1]]);
+    let u32_at = |chunk: &[u8], pos: usize| u32::from_le_bytes([chunk[pos], chunk[pos + 1], chunk[pos + 2], chunk[pos + 3]]);
+    let mut format = None;
+    let mut pos = 12;
+    while pos + 8 <= src.len() {
+        let id = &src[pos..pos + 4];
+        let size = u32_at(src, pos + 4) as usize;
+        let body = &src[pos + 8..std::cmp::min(src.len(), (pos + 8).saturating_add(size))];
+        if id == b"fmt " {
+            if body.len() < 16 {
+                return Err("the fmt chunk is cut off".into());
+            }
+            let mut code = u16_at(body, 0);
+            if code == FORMAT_EXTENSIBLE && body.len() >= 26 {
+                code = u16_at(body, 24);
+            }
+            if code != FORMAT_PCM {
+                return Err(format!("the audio format {} isn't integer PCM", code).into());
+            }
+            let (channels, sample_rate, bits) = (u16_at(body, 2), u32_at(body, 4), u16_at(body, 14));
+            if channels != 1 && channels != 2 {
+                return Err(format!("{} channels aren't supported, only 1 or 2", channels).into());
+            }
+            if bits != 8 && bits != 16 && bits != 32 {
+                return Err(format!("the sample size {} isn't 8, 16, or 32 bits", bits).into());
+            }
+            format = Some((channels, sample_rate, bits));
+        } else if id == b"data" {
+            let (channels, sample_rate, bits) = format.ok_or("the data chunk comes before the fmt chunk")?;
+            let sample_bytes = bits as usize / 8;
+            if !body.len().is_multiple_of(sample_bytes * channels as usize) {
+                return Err("the data chunk isn't a whole number of samples in each channel".into());
+            }
+            let samples = body.chunks(sample_bytes).map(|b| match bits {
+                8 => b[0] as i32,
+                16 => i16::from_le_bytes([b[0], b[1]]) as i32,
+                _ => i32::from_le_bytes([b[0], b[1], b[2], b[3]])
+            }).collect();
+            return Ok(Audio { sample_rate, channels, bits, samples });
+        }
+        // chunks are padded to an even length
+        pos = (pos + 8).saturating_add(size).saturating_add(size % 2);
+    }
+    Err("the file has no data chunk".into())
+}
+
+/// Write a PCM WAV file. Errors if the byte rate or the data don't fit in the header's 32-bit sizes.
+pub fn write_wav<W: Write>(audio: &Audio, sink: &mut W) -> Result<(), Box<dyn Error>> {
+    if audio.channels == 0 {
+        return Err("the audio has no channels".into());
+    }
+    let sample_bytes = audio.bits as u32 / 8;
+    let block_align = u16::try_from(audio.channels as u32 * sample_bytes)
+        .map_err(|_| format!("{} channels of {}-bit samples are too many", audio.channels, audio.bits))?;
+    let byte_rate = audio.sample_rate.checked_mul(block_align as u32)
+        .ok_or_else(|| format!("the sample rate {} is too high for {} channels of {}-bit samples",
+                               audio.sample_rate, audio.channels, audio.bits))?;
+    // the RIFF size counts the 36 header bytes after it and the data's padding byte
+    let data_len = u32::try_from(audio.samples.len()).ok()
+        .and_then(|len| len.checked_mul(sample_bytes))
+        .filter(|len| len.checked_add(36 + 1).is_some())
+        .ok_or_else(|| format!("the audio's {} samples don't fit in a WAV file", audio.samples.len()))?;
+    sink.write_all(b"RIFF")?;
+    sink.write_all(&(36 + data_len + data_len % 2).to_le_bytes())?;
+    sink.write_all(b"WAVEfmt ")?;
+    sink.write_all(&16u32.to_le_bytes())?;
+    sink.write_all(&FORMAT_PCM.to_le_bytes())?;
+    sink.write_all(&audio.channels.to_le_bytes())?;
+    sink.write_all(&audio.sample_rate.to_le_bytes())?;
+    sink.write_all(&byte_rate.to_le_bytes())?;
+    sink.write_all(&block_align.to_le_bytes())?;
+    sink.write_all(&audio.bits.to_le_bytes())?;
+    sink.write_all(b"data")?;
+    sink.write_all(&data_len.to_le_bytes())?;
+    let bytes: Vec<u8> = audio.samples.iter().flat_map(|s| match audio.bits {
+        8 => vec!(*s as u8),
+        16 => (*s as i16).to_le_bytes().to_vec(),
+        _ => s.to_le_bytes().to_vec()
+    }).collect();
+    sink.write_all(&bytes)?;
+    if data_len % 2 == 1 {
+        sink.write_all(&[0])?;
+    }
+    sink.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::languages::sequence::serialize_values::save_value;
+
+    #[test]
+    fn test_stereo_8_bit_round_trip() {
+        let audio = Audio {sample_rate: 22050, channels: 2, bits: 8, samples: vec!(0, 128, 255, 1, 7, 9)};
+        let mut builder = Vec::new();
+        write_wav(&audio, &mut builder).unwrap();
+        assert_eq!(builder.len(), 44 + 6);
+        let read = read_wav(&builder).unwrap();
+        assert_eq!(save_value(&read.to_seq_value()),
+                   save_value(&vec!((0u32, 128u32), (255, 1), (7, 9))));
+        let conf = AudioConfig {sample_rate: 22050, channels: 0, bits: 8};
+        assert_eq!(Audio::from_seq_value(&read.to_seq_value(), &conf).unwrap(), audio);
+    }
+
+    #[test]
+    fn test_skips_other_chunks() {
+        let audio = Audio {sample_rate: 8000, channels: 1, bits: 32, samples: vec!(i32::MIN, -1, i32::MAX)};
+        let mut builder = Vec::new();
+        write_wav(&audio, &mut builder).unwrap();
+        // put an odd-length LIST chunk between the header and the fmt chunk
+        let mut with_list = builder[..12].to_vec();
+        with_list.extend_from_slice(b"LIST\x03\x00\x00\x00abc\x00");
+        with_list.extend_from_slice(&builder[12..]);
+        assert_eq!(read_wav(&with_list).unwrap(), audio);
+    }
+
+    #[test]
+    fn test_unsupported_files() {
+        let audio = Audio {sample_rate: 8000, channels: 1, bits: 16, samples: vec!(1)};
+        let mut builder = Vec::new();
+        write_wav(&audio, &mut builder).unwrap();
+        builder[20] = 3;
+        assert_eq!(read_wav(&builder).unwrap_err().to_string(), "the audio format 3 isn't integer PCM");
+        assert_eq!(read_wav(b"RIFF\x04\x00\x00\x00WAVE").unwrap_err().to_string(), "the file has no data chunk");
+    }
+
+    #[test]
+    fn test_unwritable_audio() {
+        let audio = Audio {sample_rate: 4000000000, channels: 1, bits: 16, samples: vec!(1)};
+        assert_eq!(write_wav(&audio, &mut Vec::new()).unwrap_err().to_string(),
+                   "the sample rate 4000000000 is too high for 1 channels of 16-bit samples");
+        let audio = Audio {sample_rate: 8000, channels: 0, ..audio};
+        assert_eq!(audio.len(), 0);
+        assert_eq!(write_wav(&audio, &mut Vec::new()).unwrap_err().to_string(), "the audio has no channels");
+    }
+}
@@ -0,0 +1,494 @@
+//! read and write YUV4MPEG2 (`.y4m`) videos with one Sequence value per frame,
+//! so video pipelines can be tested on many frames streamed back to back
+use super::pnm::{lay_out_pixels, lay_out_type, ImageLayout};
+use crate::languages::sequence::atoms::Atom;
+use crate::languages::sequence::serialize_values::SerializableSeqValue;
+use crate::languages::sequence::types::Type as SeqType;
+use std::error::Error;
+use std::io::Write;
+
+/// How a video's chroma planes are subsampled
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChromaFormat {
+    /// No chroma planes
+    Mono,
+    /// Chroma at half the width and half the height
+    C420,
+    /// Chroma at half the width
+    C422,
+    /// Chroma at the full size
+    C444
+}
+
+impl ChromaFormat {
+    /// The names of the formats on the command line, as in a Y4M header without the bit depth
+    pub const NAMES: [&'static str; 4] = ["mono", "420", "422", "444"];
+
+    pub fn from_name(name: &str) -> Option<ChromaFormat> {
+        match name {
+            "mono" => Some(ChromaFormat::Mono),
+            "420" => Some(ChromaFormat::C420),
+            "422" => Some(ChromaFormat::C422),
+            "444" => Some(ChromaFormat::C444),
+            _ => None
+        }
+    }
+
+    /// Get how many luma samples wide and high each chroma sample is
+    fn subsampling(&self) -> (u32, u32) {
+        match self {
+            ChromaFormat::C420 => (2, 2),
+            ChromaFormat::C422 => (2, 1),
+            ChromaFormat::Mono | ChromaFormat::C444 => (1, 1)
+        }
+    }
+
+    /// Get the width and height of each chroma plane. Mono videos have empty chroma planes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use aetherling::formats::y4m::ChromaFormat;
+    ///
+    /// assert_eq!(ChromaFormat::C420.chroma_dims(5, 4), (3, 2));
+    /// assert_eq!(ChromaFormat::Mono.chroma_dims(5, 4), (0, 0));
+    /// ```
+    pub fn chroma_dims(&self, width: u32, height: u32) -> (u32, u32) {
+        if *self == ChromaFormat::Mono {
+            return (0, 0);
+        }
+        let (sx, sy) = self.subsampling();
+        (width.div_ceil(sx), height.div_ceil(sy))
+    }
+}
+
+/// Which planes of a frame are in its Sequence value
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChromaLayout {
+    /// The luma plane
+    Luma,
+    /// The chroma planes at their own size, with each pixel an `ATuple u v`
+    Chroma,
+    /// Every pixel as an `ATuple y (ATuple u v)`, with each chroma sample repeated
+    /// for all the pixels it covers
+    Interleaved
+}
+
+impl ChromaLayout {
+    /// The names of the layouts on the command line
+    pub const NAMES: [&'static str; 3] = ["luma", "chroma", "interleaved"];
+
+    pub fn from_name(name: &str) -> Option<ChromaLayout> {
+        match name {
+            "luma" => Some(ChromaLayout::Luma),
+            "chroma" => Some(ChromaLayout::Chroma),
+            "interleaved" => Some(ChromaLayout::Interleaved),
+            _ => None
+        }
+    }
+}
+
+/// A frame's planes, each row by row
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub y: Vec<u32>,
+    pub u: Vec<u32>,
+    pub v: Vec<u32>
+}
+
+/// A YUV video
+#[derive(Debug, Clone, PartialEq)]
+pub struct Video {
+    pub width: u32,
+    pub height: u32,
+    pub chroma_format: ChromaFormat,
+    /// The bits of each sample, 8 or from 9 to 16
+    pub bit_depth: u32,
+    /// The header's other parameters, such as the frame rate `F30:1`, kept as they are
+    pub params: Vec<String>,
+    pub frames: Vec<Frame>
+}
+
+/// The options for making a video from Sequence values
+#[derive(Debug, Clone, PartialEq)]
+pub struct VideoConfig {
+    pub width: u32,
+    pub height: u32,
+    pub chroma_format: ChromaFormat,
+    pub bit_depth: u32,
+    /// The planes in each frame's value. Planes not in the values are mid-gray.
+    pub chroma_layout: ChromaLayout,
+    pub params: Vec<String>
+}
+
+impl Default for VideoConfig {
+    fn default() -> Self {
+        VideoConfig {
+            width: 0,
+            height: 0,
+            chroma_format: ChromaFormat::C420,
+            bit_depth: 8,
+            chroma_layout: ChromaLayout::Interleaved,
+            params: vec!(String::from("F30:1"), String::from("Ip"), String::from("A1:1"))
+        }
+    }
+}
+
+impl Video {
+    /// Get the type of a sample, `UInt8` for 8-bit videos and `UInt16` for deeper videos
+    pub fn sample_type(&self) -> SeqType {
+        if self.bit_depth <= 8 { SeqType::UInt8 } else { SeqType::UInt16 }
+    }
+
+    /// Get the type of each frame's Sequence value
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use aetherling::formats::y4m::{read_y4m, ChromaLayout};
+    /// use aetherling::formats::pnm::ImageLayout;
+    /// let video = read_y4m(b"YUV4MPEG2 W4 H2 C420jpeg\nFRAME\n01234567abcd").unwrap();
+    ///
+    /// assert_eq!(video.frame_seq_type(ChromaLayout::Luma, ImageLayout::Rows).unwrap().to_string(),
+    ///            "Seq 2 (Seq 4 UInt8)");
+    /// assert_eq!(video.frame_seq_type(ChromaLayout::Chroma, ImageLayout::Flat).unwrap().to_string(),
+    ///            "Seq 2 (ATuple UInt8 UInt8)");
+    /// ```
+    pub fn frame_seq_type(&self, chroma_layout: ChromaLayout, layout: ImageLayout) -> Result<SeqType, Box<dyn Error>> {
+        let sample = || Box::new(self.sample_type());
+        let uv = SeqType::ATuple {left: sample(), right: sample()};
+        let (width, height) = self.layout_dims(chroma_layout)?;
+        let pixel = match chroma_layout {
+            ChromaLayout::Luma => self.sample_type(),
+            ChromaLayout::Chroma => uv,
+            ChromaLayout::Interleaved => SeqType::ATuple {left: sample(), right: Box::new(uv)}
+        };
+        Ok(lay_out_type(pixel, width, height, layout))
+    }
+
+    /// Get the Sequence value of each frame, of type `frame_seq_type(chroma_layout, layout)`.
+    /// Stream them with `convert_seq_vals_to_st_stream`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use aetherling::formats::y4m::{read_y4m, ChromaLayout};
+    /// use aetherling::formats::pnm::ImageLayout;
+    /// use aetherling::languages::seq_value_to_st_value_and_valid_strings::{
+    ///     convert_seq_vals_to_st_stream, StreamConfig
+    /// };
+    /// use aetherling::languages::space_time::types::Type;
+    /// let video = read_y4m(b"YUV4MPEG2 W2 H1 Cmono\nFRAME\n\x01\x02FRAME\n\x03\x04").unwrap();
+    /// let frames = video.frame_seq_values(ChromaLayout::Luma, ImageLayout::Flat).unwrap();
+    /// let st_type = Type::TSeq {n: 2, i: 0, elem_type: Box::from(Type::UInt8)};
+    /// let stream = convert_seq_vals_to_st_stream(frames, &st_type, &StreamConfig {latency: 0, frame_gap: 1});
+    ///
+    /// assert_eq!(stream.valids, vec!(true, true, false, true, true));
+    /// ```
+    pub fn frame_seq_values(&self, chroma_layout: ChromaLayout,
+                            layout: ImageLayout) -> Result<Vec<Box<dyn SerializableSeqValue>>, Box<dyn Error>> {
+        let (width, height) = self.layout_dims(chroma_layout)?;
+        let (chroma_width, _) = self.chroma_format.chroma_dims(self.width, self.height);
+        let (sx, sy) = self.chroma_format.subsampling();
+        Ok(self.frames.iter().map(|frame| {
+            let pixels: Vec<Box<dyn SerializableSeqValue>> = match chroma_layout {
+                ChromaLayout::Luma => frame.y.iter().map(|y| Box::new(*y) as Box<dyn SerializableSeqValue>).collect(),
+                ChromaLayout::Chroma => frame.u.iter().zip(frame.v.iter())
+                    .map(|(u, v)| Box::new((*u, *v)) as Box<dyn SerializableSeqValue>).collect(),
+                ChromaLayout::Interleaved => (0..self.height).flat_map(|row| (0..self.width).map(move |col| (row, col)))
+                    .map(|(row, col)| {
+                        let c = ((row / sy) * chroma_width + col / sx) as usize;
+                        let y = frame.y[(row * self.width + col) as usize];
+                        Box::new((y, (frame.u[c], frame.v[c]))) as Box<dyn SerializableSeqValue>
+                    }).collect()
+            };
+            lay_out_pixels(pixels, width, height, layout)
+        }).collect())
+    }
+
+    /// Get the width and height of the pixels in a layout's values
+    fn layout_dims(&self, chroma_layout: ChromaLayout) -> Result<(u32, u32), Box<dyn Error>> {
+        match (chroma_layout, self.chroma_format) {
+            (ChromaLayout::Luma, _) => Ok((self.width, self.height)),
+            (_, ChromaFormat::Mono) => Err("a mono video has no chroma".into()),
+            (ChromaLayout::Chroma, _) => Ok(self.chroma_format.chroma_dims(self.width, self.height)),
+            (ChromaLayout::Interleaved, _) => Ok((self.width, self.height))
+        }
+    }
+
+    /// Make a video from each frame's Sequence value, clamping samples to the bit depth.
+    /// Interleaved chroma is averaged over the pixels each chroma sample covers.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use aetherling::formats::y4m::{ChromaFormat, ChromaLayout, Video, VideoConfig};
+    /// let conf = VideoConfig {width: 2, height: 1, chroma_format: ChromaFormat::C422,
+    ///                         chroma_layout: ChromaLayout::Interleaved, ..VideoConfig::default()};
+    /// let video = Video::from_seq_values(vec!(vec!((1, (10, 20)), (300, (11, 20)))), &conf).unwrap();
+    ///
+    /// assert_eq!(video.frames[0].y, vec!(1, 255));
+    /// assert_eq!(video.frames[0].u, vec!(11));
+    /// ```
+    pub fn from_seq_values<T: SerializableSeqValue>(frame_vals: Vec<T>, conf: &VideoConfig) -> Result<Video, Box<dyn Error>> {
+        if conf.bit_depth != 8 && !(9..=16).contains(&conf.bit_depth) {
+            return Err(format!("the bit depth {} isn't 8 or from 9 to 16", conf.bit_depth).into());
+        }
+        let mut video = Video {
+            width: conf.width,
+            height: conf.height,
+            chroma_format: conf.chroma_format,
+            bit_depth: conf.bit_depth,
+            params: conf.params.clone(),
+            frames: Vec::with_capacity(frame_vals.len())
+        };
+        let (width, height) = video.layout_dims(conf.chroma_layout)?;
+        let (chroma_width, chroma_height) = conf.chroma_format.chroma_dims(conf.width, conf.height);
+        let pixels = plane_len(width, height)?;
+        let luma_len = plane_len(conf.width, conf.height)?;
+        let chroma_len = plane_len(chroma_width, chroma_height)?;
+        let (sx, sy) = conf.chroma_format.subsampling();
+        let max_val = (1i64 << conf.bit_depth) - 1;
+        let mid_gray = 1u32 << (conf.bit_depth - 1);
+        let sample = |atom: &Atom| -> Result<u32, Box<dyn Error>> {
+            match atom {
+                Atom::Bit(b) => Ok(*b as u32),
+                Atom::Int(i) => Ok((*i as i64).clamp(0, max_val) as u32),
+                Atom::UInt(u) => Ok((*u as i64).clamp(0, max_val) as u32),
+                _ => Err(format!("{:?} isn't a sample", atom).into())
+            }
+        };
+        let pair = |atom: &Atom| -> Result<(u32, u32), Box<dyn Error>> {
+            match atom {
+                Atom::Tuple(left, right) => Ok((sample(left)?, sample(right)?)),
+                _ => Err(format!("{:?} isn't an ATuple", atom).into())
+            }
+        };
+
+        for (idx, frame_val) in frame_vals.into_iter().enumerate() {
+            let mut atoms = Vec::new();
+            frame_val.convert_to_flat_atoms(&mut atoms);
+            if atoms.len() != pixels {
+                return Err(format!("frame {} has {} pixels, but needs {}", idx, atoms.len(), pixels).into());
+            }
+            let mut frame = Frame {
+                y: vec![mid_gray; luma_len],
+                u: vec![mid_gray; chroma_len],
+                v: vec![mid_gray; chroma_len]
+            };
+            match conf.chroma_layout {
+                ChromaLayout::Luma => {
+                    frame.y = atoms.iter().map(sample).collect::<Result<_, _>>()?;
+                }
+                ChromaLayout::Chroma => {
+                    let uvs = atoms.iter().map(pair).collect::<Result<Vec<_>, _>>()?;
+                    frame.u = uvs.iter().map(|(u, _)| *u).collect();
+                    frame.v = uvs.iter().map(|(_, v)| *v).collect();
+                }
+                ChromaLayout::Interleaved => {
+                    let mut sums = vec![(0u64, 0u64, 0u64); chroma_len];
+                    for (i, atom) in atoms.iter().enumerate() {
+                        let (y, (u, v)) = match atom {
+                            Atom::Tuple(y, uv) => (sample(y)?, pair(uv)?),
+                            _ => return Err(format!("{:?} isn't an ATuple y (ATuple u v)", atom).into())
+                        };
+                        frame.y[i] = y;
+                        let (row, col) = (i as u32 / conf.width, i as u32 % conf.width);
+                        let c = ((row / sy) * chroma_width + col / sx) as usize;
+                        if c < chroma_len {
+                            sums[c] = (sums[c].0 + u as u64, sums[c].1 + v as u64, sums[c].2 + 1);
+                        }
+                    }
+                    frame.u = sums.iter().map(|(u, _, n)| ((u + n / 2) / n) as u32).collect();
+                    frame.v = sums.iter().map(|(_, v, n)| ((v + n / 2) / n) as u32).collect();
+                }
+            }
+            video.frames.push(frame);
+        }
+        Ok(video)
+    }
+}
+
+/// Read a Y4M video. Samples deeper than 8 bits are two little-endian bytes.
+/// Videos without a `C` parameter are 4:2:0.
+///
+/// # Examples
+///
+/// ```
+/// use aetherling::formats::y4m::{read_y4m, ChromaFormat};
+/// let video = read_y4m(b"YUV4MPEG2 W2 H1 F25:1 C444p10\nFRAME\n\x01\x00\x02\x00\x03\x00\x04\x00\xff\x03\x00\x01").unwrap();
+///
+/// assert_eq!((video.chroma_format, video.bit_depth), (ChromaFormat::C444, 10));
+/// assert_eq!(video.frames[0].v, vec!(0x3ff, 0x100));
+/// assert_eq!(video.params, vec!("F25:1"));
+/// ```
+pub fn read_y4m(src: &[u8]) -> Result<Video, Box<dyn Error>> {
+    let header_end = src.iter().position(|b| *b == b'\n').ok_or("the video has no header line")?;
+    let header = std::str::from_utf8(&src[..header_end]).map_err(|_| "the video's header isn't ASCII")?;
+    let mut tokens = header.split(' ');
+    if tokens.next() != Some("YUV4MPEG2") {
+        return Err("not a YUV4MPEG2 video".into());
+    }
+    let (mut width, mut height) = (None, None);
+    let (mut chroma_format, mut bit_depth) = (ChromaFormat::C420, 8);
+    let mut params = Vec::new();
+    for token in tokens.filter(|t| !t.is_empty()) {
+        let mut chars = token.chars();
+        let tag = chars.next();
+        let val = chars.as_str();
+        match tag {
+            Some('W') => width = Some(val.parse::<u32>().map_err(|_| format!("bad width {}", val))?),
+            Some('H') => height = Some(val.parse::<u32>().map_err(|_| format!("bad height {}", val))?),
+            Some('C') => {
+                let (format, depth) = parse_colorspace(val)?;
+                chroma_format = format;
+                bit_depth = depth;
+            }
+            _ => params.push(String::from(token))
+        }
+    }
+    let (width, height) = match (width, height) {
+        (Some(w), Some(h)) => (w, h),
+        _ => return Err("the video's header needs a width and height".into())
+    };
+    let sample_bytes = if bit_depth > 8 { 2 } else { 1 };
+    let luma_len = plane_len(width, height)?;
+    let (chroma_width, chroma_height) = chroma_format.chroma_dims(width, height);
+    let chroma_len = plane_len(chroma_width, chroma_height)?;
+    let frame_bytes = chroma_len.checked_mul(2).and_then(|c| c.checked_add(luma_len))
+        .and_then(|samples| samples.checked_mul(sample_bytes))
+        .ok_or("the video's frames are too big")?;
+
+    let mut frames = Vec::new();
+    let mut pos = header_end + 1;
+    while pos < src.len() {
+        let line_end = src[pos..].iter().position(|b| *b == b'\n').map(|end| pos + end)
+            .ok_or("a frame has no header line")?;
+        if !src[pos..line_end].starts_with(b"FRAME") {
+            return Err(format!("expected FRAME at byte {} of the video", pos).into());
+        }
+        let data = src.get(line_end + 1..).and_then(|rest| rest.get(..frame_bytes))
+            .ok_or_else(|| format!("frame {} is cut off", frames.len()))?;
+        let samples: Vec<u32> = data.chunks(sample_bytes)
+            .map(|b| b.iter().rev().fold(0, |acc, byte| (acc << 8) | *byte as u32)).collect();
+        frames.push(Frame {
+            y: samples[..luma_len].to_vec(),
+            u: samples[luma_len..luma_len + chroma_len].to_vec(),
+            v: samples[luma_len + chroma_len..].to_vec()
+        });
+        pos = line_end + 1 + frame_bytes;
+    }
+    Ok(Video { width, height, chroma_format, bit_depth, params, frames })
+}
+
+/// Get the number of samples in a plane. A flat frame is a Seq of all its pixels,
+/// so the count must fit in a u32.
+fn plane_len(width: u32, height: u32) -> Result<usize, Box<dyn Error>> {
+    width.checked_mul(height).map(|len| len as usize)
+        .ok_or_else(|| format!("the video's {}x{} frames have too many pixels", width, height).into())
+}
+
+/// Parse a colorspace such as `420jpeg`, `422p10`, or `mono16`
+fn parse_colorspace(colorspace: &str) -> Result<(ChromaFormat, u32), Box<dyn Error>> {
+    let unsupported = || format!("the colorspace {} isn't supported", colorspace);
+    let (format, rest) = if let Some(rest) = colorspace.strip_prefix("mono") {
+        (ChromaFormat::Mono, rest)
+    } else {
+        let format = ChromaFormat::from_name(colorspace.get(..3).ok_or_else(unsupported)?).ok_or_else(unsupported)?;
+        (format, &colorspace[3..])
+    };
+    let depth = match rest {
+        "" | "jpeg" | "paldv" | "mpeg2" => 8,
+        _ => rest.strip_prefix('p').unwrap_or(rest).parse().map_err(|_| unsupported())?
+    };
+    if depth != 8 && !(9..=16).contains(&depth) {
+        return Err(unsupported().into());
+    }
+    Ok((format, depth))
+}
+
+/// Write a Y4M video
+///
+/// # Examples
+///
+/// ```
+/// use aetherling::formats::y4m::{read_y4m, write_y4m};
+/// let video = read_y4m(b"YUV4MPEG2 W2 H2 F30:1 C420p12\nFRAME\n\x01\x00\x02\x00\x03\x00\x04\x00\x05\x00\x06\x00").unwrap();
+/// let mut builder = Vec::new();
+/// write_y4m(&video, &mut builder).unwrap();
+///
+/// assert!(builder.starts_with(b"YUV4MPEG2 W2 H2 C420p12 F30:1\nFRAME\n"));
+/// assert_eq!(read_y4m(&builder).unwrap(), video);
+/// ```
+pub fn write_y4m<W: Write>(video: &Video, sink: &mut W) -> Result<(), Box<dyn Error>> {
+    let format = match video.chroma_format {
+        ChromaFormat::Mono => "mono",
+        ChromaFormat::C420 => "420",
+        ChromaFormat::C422 => "422",
+        ChromaFormat::C444 => "444"
+    };
+    let colorspace = match (video.chroma_format, video.bit_depth) {
+        (ChromaFormat::C420, 8) => String::from("420jpeg"),
+        (_, 8) => String::from(format),
+        (ChromaFormat::Mono, depth) => format!("mono{}", depth),
+        (_, depth) => format!("{}p{}", format, depth)
+    };
+    write!(sink, "YUV4MPEG2 W{} H{} C{}", video.width, video.height, colorspace)?;
+    for param in video.params.iter() {
+        write!(sink, " {}", param)?;
+    }
+    sink.write_all(b"\n")?;
+    for frame in video.frames.iter() {
+        sink.write_all(b"FRAME\n")?;
+        let bytes: Vec<u8> = frame.y.iter().chain(frame.u.iter()).chain(frame.v.iter()).flat_map(|s| {
+            if video.bit_depth > 8 { vec!(*s as u8, (*s >> 8) as u8) } else { vec!(*s as u8) }
+        }).collect();
+        sink.write_all(&bytes)?;
+    }
+    sink.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::languages::sequence::serialize_values::save_value;
+
+    #[test]
+    fn test_420_interleaved_round_trip() {
+        // a 2x2 frame with one chroma sample
+        let video = read_y4m(b"YUV4MPEG2 W2 H2 F30:1 Ip A1:1\nFRAME\n\x01\x02\x03\x04\x80\x90").unwrap();
+        let frames = video.frame_seq_values(ChromaLayout::Interleaved, ImageLayout::Rows).unwrap();
+        assert_eq!(save_value(&frames[0]), save_value(&vec!(
+            vec!((1u32, (0x80u32, 0x90u32)), (2, (0x80, 0x90))),
+            vec!((3u32, (0x80u32, 0x90u32)), (4, (0x80, 0x90))))));
+        let conf = VideoConfig {width: 2, height: 2, ..VideoConfig::default()};
+        assert_eq!(Video::from_seq_values(frames, &conf).unwrap(), video);
+    }
+
+    #[test]
+    fn test_chroma_of_mono_video() {
+        let video = read_y4m(b"YUV4MPEG2 W1 H1 Cmono\nFRAME\n\x07").unwrap();
+        assert_eq!(video.frame_seq_values(ChromaLayout::Chroma, ImageLayout::Flat).err().unwrap().to_string(),
+                   "a mono video has no chroma");
+    }
+
+    #[test]
+    fn test_cut_off_frame() {
+        assert_eq!(read_y4m(b"YUV4MPEG2 W2 H2 Cmono\nFRAME\n\x01\x02\x03\x04FRAME\n\x01").unwrap_err().to_string(),
+                   "frame 1 is cut off");
+    }
+
+    #[test]
+    fn test_huge_frames_and_non_ascii_tokens() {
+        assert_eq!(read_y4m(b"YUV4MPEG2 W70000 H70000 Cmono
+").unwrap_err().to_string(),
+                   "the video's 70000x70000 frames have too many pixels");
+        let video = read_y4m("YUV4MPEG2 W1 H1 Cmono \u{e9}\nFRAME\n\x07".as_bytes()).unwrap();
+        assert_eq!(video.params, vec!("\u{e9}"));
+        let conf = VideoConfig {width: 70000, height: 70000, chroma_format: ChromaFormat::Mono,
+                                chroma_layout: ChromaLayout::Luma, ..VideoConfig::default()};
+        assert_eq!(Video::from_seq_values(vec!(vec!(1u32)), &conf).unwrap_err().to_string(),
+                   "the video's 70000x70000 frames have too many pixels");
+    }
+}
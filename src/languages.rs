@@ -1,4 +1,11 @@
 pub mod space_time;
 pub mod sequence;
 pub mod seq_value_to_st_value_and_valid_strings;
-mod util;
\ No newline at end of file
+pub mod inject_stalls;
+pub mod ports;
+pub mod atom_format;
+pub mod st_clocks;
+pub mod parallel;
+pub mod type_files;
+pub mod value_files;
+mod util;
@@ -0,0 +1,185 @@
+//! print atoms in the syntax of the tools that read the output
+use super::sequence::atoms::Atom;
+use super::space_time::types::Type;
+
+/// How to print atoms
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum AtomFormat {
+    /// `true`, `-3`, and tuples as `[left,right]`
+    #[default]
+    Rust,
+    /// `True`, `-3`, and tuples as `(left,right)`, readable by Haskell's `read`
+    Haskell,
+    /// `True`, `-3`, and tuples as `(left, right)`, readable by Python's `ast.literal_eval`
+    Python,
+    /// two's complement hex with a fixed number of digits per atom type,
+    /// with tuples packed like Verilog's `{left, right}`
+    Hex,
+    /// two's complement binary with one digit per bit of the atom type,
+    /// with tuples packed like Verilog's `{left, right}`
+    Bits
+}
+
+impl AtomFormat {
+    /// The names of the formats in command lines and manifests
+    pub const NAMES: [&'static str; 5] = ["rust", "haskell", "python", "hex", "bits"];
+
+    pub fn from_name(name: &str) -> Option<AtomFormat> {
+        match name {
+            "rust" => Some(AtomFormat::Rust),
+            "haskell" => Some(AtomFormat::Haskell),
+            "python" => Some(AtomFormat::Python),
+            "hex" => Some(AtomFormat::Hex),
+            "bits" => Some(AtomFormat::Bits),
+            _ => None
+        }
+    }
+}
+
+/// A marker printed in place of the atoms of invalid clocks, so they can't be
+/// mistaken for real data. Checkers must treat a marker as matching any atom.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DontCare {
+    /// `x`
+    LowerX,
+    /// `X`
+    UpperX,
+    /// nothing
+    Empty
+}
+
+impl DontCare {
+    /// The names of the markers in command lines and manifests
+    pub const NAMES: [&'static str; 3] = ["x", "X", "empty"];
+
+    pub fn from_name(name: &str) -> Option<DontCare> {
+        match name {
+            "x" => Some(DontCare::LowerX),
+            "X" => Some(DontCare::UpperX),
+            "empty" => Some(DontCare::Empty),
+            _ => None
+        }
+    }
+
+    pub fn marker(&self) -> &'static str {
+        match self {
+            DontCare::LowerX => "x",
+            DontCare::UpperX => "X",
+            DontCare::Empty => ""
+        }
+    }
+}
+
+/// Check if an expected atom's string is a don't-care marker that matches any atom
+pub fn is_dont_care(expected: &str) -> bool {
+    expected == DontCare::LowerX.marker() || expected == DontCare::UpperX.marker() ||
+        expected == DontCare::Empty.marker()
+}
+
+/// The options for printing Space-Time values as text
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TextConfig {
+    pub atom_format: AtomFormat,
+    /// The marker for the atoms of invalid clocks.
+    /// If `None`, invalid clocks have the type's default atoms.
+    pub dont_care: Option<DontCare>
+}
+
+impl TextConfig {
+    /// Print an atom in a lane of a clock that may be invalid
+    pub fn format_lane(&self, atom: &Atom, atom_type: &Type, valid: bool) -> String {
+        match self.dont_care {
+            Some(dont_care) if !valid => dont_care.marker().to_string(),
+            _ => format_atom(atom, atom_type, self.atom_format)
+        }
+    }
+
+    /// Print whether a clock is valid
+    pub fn format_valid(&self, valid: bool) -> String {
+        format_atom(&Atom::Bit(valid), &Type::Bit, self.atom_format)
+    }
+}
+
+/// Print an atom of an atom type
+///
+/// # Examples
+///
+/// ```
+/// use aetherling::languages::atom_format::{format_atom, AtomFormat};
+/// use aetherling::languages::sequence::atoms::Atom;
+/// use aetherling::languages::space_time::types::Type;
+/// let t = Type::ATuple {left: Box::from(Type::Int8), right: Box::from(Type::Bit)};
+/// let a = Atom::Tuple(Box::from(Atom::Int(-1)), Box::from(Atom::Bit(false)));
+///
+/// assert_eq!(format_atom(&a, &t, AtomFormat::Rust), "[-1,false]");
+/// assert_eq!(format_atom(&a, &t, AtomFormat::Haskell), "(-1,False)");
+/// assert_eq!(format_atom(&a, &t, AtomFormat::Python), "(-1, False)");
+/// assert_eq!(format_atom(&a, &t, AtomFormat::Hex), "1fe");
+/// assert_eq!(format_atom(&a, &t, AtomFormat::Bits), "111111110");
+/// ```
+pub fn format_atom(atom: &Atom, atom_type: &Type, format: AtomFormat) -> String {
+    match format {
+        AtomFormat::Rust => atom.to_string(),
+        AtomFormat::Haskell => format_atom_literal(atom, ","),
+        AtomFormat::Python => format_atom_literal(atom, ", "),
+        AtomFormat::Hex => bits_to_digits(&atom_bits(atom, atom_type), 4),
+        AtomFormat::Bits => bits_to_digits(&atom_bits(atom, atom_type), 1)
+    }
+}
+
+/// Print an atom with Haskell and Python's shared syntax for bools and tuples
+fn format_atom_literal(atom: &Atom, tuple_sep: &str) -> String {
+    match atom {
+        Atom::Bit(true) => String::from("True"),
+        Atom::Bit(false) => String::from("False"),
+        Atom::Tuple(left, right) => format!("({}{}{})", format_atom_literal(left, tuple_sep), tuple_sep,
+                                            format_atom_literal(right, tuple_sep)),
+        _ => atom.to_string()
+    }
+}
+
+/// Get an atom's bits, least significant bit first, with tuples packed like Verilog's `{left, right}`
+pub fn atom_bits(atom: &Atom, atom_type: &Type) -> Vec<bool> {
+    let mut bits = Vec::with_capacity(atom_type.size() as usize);
+    for (leaf, (_, field_type)) in atom.leaves().iter().zip(atom_type.atom_fields().iter()).rev() {
+        let width = field_type.size();
+        let leaf_bits = leaf.to_bits(width);
+        for b in 0..width {
+            bits.push(b < 64 && (leaf_bits >> b) & 1 == 1);
+        }
+    }
+    bits
+}
+
+/// Print a bit vector, stored least significant bit first, most significant digit first.
+/// Each digit is `digit_bits` bits, so 4 for hex and 1 for binary.
+/// A zero-width vector prints as a single `0`.
+pub fn bits_to_digits(bits: &[bool], digit_bits: usize) -> String {
+    let num_digits = std::cmp::max(1, bits.len().div_ceil(digit_bits));
+    (0..num_digits).rev().map(|d| {
+        let digit = (0..digit_bits)
+            .filter(|b| bits.get(d * digit_bits + b) == Some(&true))
+            .fold(0, |acc, b| acc | (1 << b));
+        std::char::from_digit(digit, 16).unwrap()
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hex_fixed_width() {
+        assert_eq!(format_atom(&Atom::UInt(1), &Type::UInt16, AtomFormat::Hex), "0001");
+        assert_eq!(format_atom(&Atom::Int(-2), &Type::Int32, AtomFormat::Hex), "fffffffe");
+        assert_eq!(format_atom(&Atom::Bit(true), &Type::Bit, AtomFormat::Hex), "1");
+        assert_eq!(format_atom(&Atom::Unit, &Type::Unit, AtomFormat::Hex), "0");
+    }
+
+    #[test]
+    fn test_nested_tuple_literals() {
+        let a = Atom::Tuple(Box::from(Atom::Bit(true)),
+                            Box::from(Atom::Tuple(Box::from(Atom::UInt(2)), Box::from(Atom::Int(-3)))));
+        assert_eq!(format_atom_literal(&a, ", "), "(True, (2, -3))");
+    }
+}
@@ -0,0 +1,127 @@
+//! insert random invalid clocks into a Space-Time value to check that
+//! latency-insensitive hardware handles invalids anywhere in a stream
+use super::seq_value_to_st_value_and_valid_strings::STAtomsAndValids;
+use super::space_time::types::Type;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use std::error::Error;
+
+/// The options for injecting stalls
+#[derive(Debug, Clone, PartialEq)]
+pub struct StallConfig {
+    /// The seed of the random number generator, so the same stalls can be recreated
+    pub seed: u64,
+    /// The probability of adding each stall clock before a clock, from 0 to 1
+    pub probability: f64,
+    /// The most stall clocks in a row
+    pub max_stall: u32
+}
+
+/// Where stalls were inserted into a Space-Time value
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stalls {
+    /// `before_clock[c]` is the number of invalid clocks inserted right before original clock `c`
+    pub before_clock: Vec<u32>
+}
+
+impl Stalls {
+    /// Randomly choose the stalls for a value with `clocks` clocks.
+    /// Errors if the probability isn't from 0 to 1.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use aetherling::languages::inject_stalls::{Stalls, StallConfig};
+    /// let conf = StallConfig {seed: 7, probability: 0.5, max_stall: 3};
+    ///
+    /// assert_eq!(Stalls::random(10, &conf).unwrap(), Stalls::random(10, &conf).unwrap());
+    /// ```
+    pub fn random(clocks: usize, conf: &StallConfig) -> Result<Stalls, Box<dyn Error>> {
+        if !(0.0..=1.0).contains(&conf.probability) {
+            return Err(format!("stall probability {} isn't from 0 to 1", conf.probability).into());
+        }
+        let mut rng = StdRng::seed_from_u64(conf.seed);
+        let before_clock = (0..clocks).map(|_| {
+            let mut stall = 0;
+            while stall < conf.max_stall && rng.gen_bool(conf.probability) {
+                stall += 1;
+            }
+            stall
+        }).collect();
+        Ok(Stalls { before_clock })
+    }
+
+    /// Get the clock in the stalled value of an original clock
+    pub fn stalled_clock(&self, clock: usize) -> usize {
+        clock + self.before_clock[..=clock].iter().map(|s| *s as usize).sum::<usize>()
+    }
+
+    /// Insert these stalls into a Space-Time value with the same number of clocks,
+    /// such as to adjust an expected output the same way as its input.
+    pub fn apply(&self, st_vals: &STAtomsAndValids, st_type: &Type) -> STAtomsAndValids {
+        assert_eq!(self.before_clock.len(), st_vals.valids.len(),
+                   "stalls are for a value with a different number of clocks");
+        let mut stalled = STAtomsAndValids::invalid_clocks(st_type, 0);
+        for (t, stall) in self.before_clock.iter().enumerate() {
+            stalled.append(STAtomsAndValids::invalid_clocks(st_type, *stall));
+            stalled.atoms.push(st_vals.atoms[t].clone());
+            stalled.valids.push(st_vals.valids[t]);
+        }
+        stalled
+    }
+}
+
+/// Insert seeded, random invalid clocks into a Space-Time value.
+/// Returns the stalled value and where the stalls went,
+/// or an error if the probability isn't from 0 to 1.
+///
+/// # Examples
+///
+/// ```
+/// use aetherling::languages::inject_stalls::{inject_stalls, StallConfig};
+/// use aetherling::languages::seq_value_to_st_value_and_valid_strings::convert_seq_val_to_st_atoms_and_valids;
+/// use aetherling::languages::space_time::types::Type;
+/// let st_type = Type::TSeq {n: 4, i: 0, elem_type: Box::from(Type::UInt8)};
+/// let st_vals = convert_seq_val_to_st_atoms_and_valids(vec!(1, 3, 2, 4), &st_type);
+/// let (stalled, stalls) = inject_stalls(&st_vals, &st_type,
+///                                       &StallConfig {seed: 0, probability: 0.5, max_stall: 2}).unwrap();
+///
+/// assert_eq!(stalled.valids.iter().filter(|v| **v).count(), 4);
+/// assert_eq!(stalled.atoms[stalls.stalled_clock(3)], st_vals.atoms[3]);
+/// ```
+pub fn inject_stalls(st_vals: &STAtomsAndValids, st_type: &Type,
+                     conf: &StallConfig) -> Result<(STAtomsAndValids, Stalls), Box<dyn Error>> {
+    let stalls = Stalls::random(st_vals.valids.len(), conf)?;
+    Ok((stalls.apply(st_vals, st_type), stalls))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::seq_value_to_st_value_and_valid_strings::convert_seq_val_to_st_atoms_and_valids;
+
+    #[test]
+    fn test_apply_stalls() {
+        let st_type = Type::TSeq {n: 2, i: 1, elem_type: Box::from(Type::UInt8)};
+        let st_vals = convert_seq_val_to_st_atoms_and_valids(vec!(1, 3), &st_type);
+        let stalls = Stalls { before_clock: vec!(1, 0, 2) };
+        let stalled = stalls.apply(&st_vals, &st_type);
+        assert_eq!(stalled.valids, vec!(false, true, true, false, false, false));
+        assert_eq!(stalls.stalled_clock(0), 1);
+        assert_eq!(stalls.stalled_clock(1), 2);
+        assert_eq!(stalls.stalled_clock(2), 5);
+    }
+
+    #[test]
+    fn test_no_stalls_with_zero_probability() {
+        let stalls = Stalls::random(5, &StallConfig {seed: 3, probability: 0.0, max_stall: 4}).unwrap();
+        assert_eq!(stalls.before_clock, vec!(0, 0, 0, 0, 0));
+    }
+
+    #[test]
+    fn test_probability_out_of_range() {
+        let err = Stalls::random(5, &StallConfig {seed: 3, probability: 1.5, max_stall: 4}).unwrap_err();
+        assert_eq!(err.to_string(), "stall probability 1.5 isn't from 0 to 1");
+        assert!(Stalls::random(5, &StallConfig {seed: 3, probability: f64::NAN, max_stall: 4}).is_err());
+    }
+}
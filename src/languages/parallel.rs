@@ -0,0 +1,187 @@
+//! convert large Sequence values and batches of Sequence values to Space-Time values
+//! on many threads. The results are always in the same order as a serial conversion.
+use super::sequence::atoms::Atom;
+use super::sequence::serialize_values::SerializableSeqValue;
+use super::seq_value_to_st_value_and_valid_strings::{
+    convert_seq_val_to_st_atoms_and_valids, convert_seq_vals_to_st_stream, STAtomsAndValids, StreamConfig
+};
+use super::space_time::types::Type;
+use super::st_clocks::seq_idxs_of_st_clock;
+use std::thread;
+
+/// The number of threads the machine can run at once, or 1 if that's unknown
+pub fn available_threads() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+/// Convert a Sequence value to a Space-Time value's atoms and valid clocks,
+/// splitting the clocks into one contiguous chunk per thread.
+///
+/// # Examples
+///
+/// ```
+/// use aetherling::languages::parallel::convert_seq_val_to_st_atoms_and_valids_parallel;
+/// use aetherling::languages::seq_value_to_st_value_and_valid_strings::convert_seq_val_to_st_atoms_and_valids;
+/// use aetherling::languages::space_time::types::Type;
+/// let st_type = Type::TSeq {n: 4, i: 2, elem_type: Box::from(
+///     Type::SSeq {n: 2, elem_type: Box::from(Type::UInt8)})};
+/// let seq_val: Vec<i32> = (0..8).collect();
+///
+/// assert_eq!(convert_seq_val_to_st_atoms_and_valids_parallel(seq_val.clone(), &st_type, 4),
+///            convert_seq_val_to_st_atoms_and_valids(seq_val, &st_type));
+/// ```
+pub fn convert_seq_val_to_st_atoms_and_valids_parallel<T: SerializableSeqValue>(
+    seq_val: T, st_type: &Type, threads: usize) -> STAtomsAndValids {
+    let mut flat_atoms: Vec<Atom> = Vec::new();
+    seq_val.convert_to_flat_atoms(&mut flat_atoms);
+
+    let total_time = st_type.clocks() as usize;
+    let lanes = st_type.atoms_per_valid() as usize;
+    let def_atom = st_type.def_atom();
+    let mut atoms: Vec<Vec<Atom>> = vec![Vec::new(); total_time];
+    let mut valids: Vec<bool> = vec![false; total_time];
+    let chunk_clocks = std::cmp::max(1, total_time.div_ceil(std::cmp::max(1, threads)));
+    thread::scope(|scope| {
+        for (chunk, (atoms_chunk, valids_chunk)) in atoms.chunks_mut(chunk_clocks)
+            .zip(valids.chunks_mut(chunk_clocks)).enumerate() {
+            let flat_atoms = &flat_atoms;
+            let def_atom = &def_atom;
+            scope.spawn(move || {
+                let mut seq_idxs = vec![0; lanes];
+                for (offset, (clock_atoms, valid)) in atoms_chunk.iter_mut()
+                    .zip(valids_chunk.iter_mut()).enumerate() {
+                    let clock = (chunk * chunk_clocks + offset) as u32;
+                    *valid = seq_idxs_of_st_clock(st_type, clock, &mut seq_idxs);
+                    *clock_atoms = if *valid {
+                        seq_idxs.iter().map(|idx| flat_atoms[*idx].clone()).collect()
+                    } else {
+                        vec![def_atom.clone(); lanes]
+                    };
+                }
+            });
+        }
+    });
+    STAtomsAndValids { atoms, valids }
+}
+
+/// Convert many independent Sequence values, such as the frames of a video, to Space-Time values,
+/// splitting the values into one contiguous batch per thread.
+/// The results are in the same order as `seq_vals`.
+///
+/// # Examples
+///
+/// ```
+/// use aetherling::languages::parallel::convert_seq_vals_to_st_atoms_and_valids_parallel;
+/// use aetherling::languages::space_time::types::Type;
+/// let st_type = Type::TSeq {n: 2, i: 0, elem_type: Box::from(Type::UInt8)};
+/// let st_vals = convert_seq_vals_to_st_atoms_and_valids_parallel(
+///     vec!(vec!(1, 3), vec!(2, 4), vec!(5, 6)), &st_type, 2);
+///
+/// assert_eq!(st_vals.len(), 3);
+/// assert_eq!(st_vals[2].atoms[1][0].to_string(), "6");
+/// ```
+pub fn convert_seq_vals_to_st_atoms_and_valids_parallel<T: SerializableSeqValue>(
+    seq_vals: Vec<T>, st_type: &Type, threads: usize) -> Vec<STAtomsAndValids> {
+    let batch_size = std::cmp::max(1, seq_vals.len().div_ceil(std::cmp::max(1, threads)));
+    let mut seq_vals = seq_vals.into_iter();
+    let mut batches: Vec<Vec<T>> = Vec::new();
+    loop {
+        let batch: Vec<T> = seq_vals.by_ref().take(batch_size).collect();
+        if batch.is_empty() {
+            break;
+        }
+        batches.push(batch);
+    }
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = batches.into_iter().map(|batch| scope.spawn(move || {
+            batch.into_iter()
+                .map(|seq_val| convert_seq_val_to_st_atoms_and_valids(seq_val, st_type))
+                .collect::<Vec<STAtomsAndValids>>()
+        })).collect();
+        // join in spawn order so the results are in the inputs' order
+        handles.into_iter().flat_map(|handle| handle.join().expect("conversion thread panicked")).collect()
+    })
+}
+
+/// Convert many Sequence values, such as the frames of a video, to one stream of clocks
+/// like `convert_seq_vals_to_st_stream`, converting the values on `threads` threads.
+/// One thread converts them serially.
+///
+/// # Examples
+///
+/// ```
+/// use aetherling::languages::parallel::convert_seq_vals_to_st_stream_parallel;
+/// use aetherling::languages::seq_value_to_st_value_and_valid_strings::StreamConfig;
+/// use aetherling::languages::space_time::types::Type;
+/// let st = convert_seq_vals_to_st_stream_parallel(vec!(vec!(1, 3), vec!(2, 4)),
+///                                                 &Type::TSeq {n: 2, i: 0, elem_type: Box::from(Type::UInt8)},
+///                                                 &StreamConfig {latency: 1, frame_gap: 1}, 2);
+///
+/// assert_eq!(st.valids, vec!(false, true, true, false, true, true));
+/// ```
+pub fn convert_seq_vals_to_st_stream_parallel<T: SerializableSeqValue>(
+    seq_vals: Vec<T>, st_type: &Type, conf: &StreamConfig, threads: usize) -> STAtomsAndValids {
+    if threads <= 1 {
+        return convert_seq_vals_to_st_stream(seq_vals, st_type, conf);
+    }
+    let mut stream = STAtomsAndValids::invalid_clocks(st_type, conf.latency);
+    for (idx, st_vals) in convert_seq_vals_to_st_atoms_and_valids_parallel(seq_vals, st_type, threads)
+        .into_iter().enumerate() {
+        if idx > 0 {
+            stream.append(STAtomsAndValids::invalid_clocks(st_type, conf.frame_gap));
+        }
+        stream.append(st_vals);
+    }
+    stream
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::sequence::serialize_values::{load_value, save_value};
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn test_values_and_types_are_send_sync() {
+        assert_send_sync::<Box<dyn SerializableSeqValue>>();
+        assert_send_sync::<Type>();
+        assert_send_sync::<STAtomsAndValids>();
+    }
+
+    #[test]
+    fn test_parallel_matches_serial() {
+        let st_type = Type::SSeq {n: 2, elem_type: Box::from(
+            Type::TSeq {n: 5, i: 3, elem_type: Box::from(
+                Type::STuple {n: 2, elem_type: Box::from(Type::Int8)})})};
+        let seq_val: Vec<i32> = (0..20).collect();
+        let serial = convert_seq_val_to_st_atoms_and_valids(seq_val.clone(), &st_type);
+        for threads in [1, 3, 8, 100].iter() {
+            assert_eq!(convert_seq_val_to_st_atoms_and_valids_parallel(seq_val.clone(), &st_type, *threads),
+                       serial);
+        }
+    }
+
+    #[test]
+    fn test_parallel_stream_matches_serial() {
+        let st_type = Type::TSeq {n: 2, i: 1, elem_type: Box::from(Type::UInt8)};
+        let frames: Vec<Vec<u32>> = (0..5).map(|f| vec!(f, f + 10)).collect();
+        let conf = StreamConfig {latency: 3, frame_gap: 2};
+        let serial = convert_seq_vals_to_st_stream(frames.clone(), &st_type, &conf);
+        for threads in [1, 2, 8].iter() {
+            assert_eq!(convert_seq_vals_to_st_stream_parallel(frames.clone(), &st_type, &conf, *threads), serial);
+        }
+    }
+
+    #[test]
+    fn test_parallel_batch_of_loaded_values() {
+        let st_type = Type::TSeq {n: 3, i: 1, elem_type: Box::from(Type::UInt8)};
+        let seq_vals: Vec<Vec<u32>> = (0..7).map(|f| vec!(f, f + 1, f + 2)).collect();
+        let loaded_vals = seq_vals.iter().map(|v| load_value(&save_value(v))).collect();
+        let st_vals = convert_seq_vals_to_st_atoms_and_valids_parallel(loaded_vals, &st_type, 3);
+        for (seq_val, st_val) in seq_vals.into_iter().zip(st_vals) {
+            assert_eq!(st_val, convert_seq_val_to_st_atoms_and_valids(seq_val, &st_type));
+        }
+    }
+}
@@ -0,0 +1,139 @@
+//! convert the Sequence values on each port of a module with multiple inputs,
+//! such as `Map2S` or `Map2T`, to Space-Time values that share one clock axis
+use super::sequence::serialize_values::SerializableSeqValue;
+use super::seq_value_to_st_value_and_valid_strings::{
+    convert_seq_val_to_st_atoms_and_valids, STAtomsAndValids
+};
+use super::space_time::types::Type;
+use std::error::Error;
+
+/// A Sequence value on a named port with a Space-Time type
+#[derive(Debug, Clone, PartialEq)]
+pub struct SeqPort<T> {
+    pub name: String,
+    pub seq_val: T,
+    pub st_type: Type
+}
+
+/// A Space-Time value on a named port
+#[derive(Debug, Clone, PartialEq)]
+pub struct STPort {
+    pub name: String,
+    pub st_type: Type,
+    pub st_vals: STAtomsAndValids
+}
+
+impl STPort {
+    /// Split a port with tuple atoms into one port per non-tuple field, named with the
+    /// field's path such as `in0_left_right`. Each field port has its field's type,
+    /// so it has the field's width and default atom.
+    /// A port without tuple atoms is returned unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use aetherling::languages::ports::{convert_seq_ports_to_st_ports, SeqPort};
+    /// use aetherling::languages::sequence::atoms::Atom;
+    /// use aetherling::languages::space_time::types::Type;
+    /// let st_ports = convert_seq_ports_to_st_ports(vec!(
+    ///     SeqPort {name: String::from("in0"), seq_val: vec!((1, true), (3, false)),
+    ///              st_type: Type::SSeq {n: 2, elem_type: Box::from(
+    ///                  Type::ATuple {left: Box::from(Type::UInt8), right: Box::from(Type::Bit)})}}
+    /// )).unwrap();
+    /// let fields = st_ports[0].split_tuple_fields();
+    ///
+    /// assert_eq!(fields[1].name, "in0_right");
+    /// assert_eq!(fields[1].st_type, Type::SSeq {n: 2, elem_type: Box::from(Type::Bit)});
+    /// assert_eq!(fields[1].st_vals.atoms, vec!(vec!(Atom::Bit(true), Atom::Bit(false))));
+    /// ```
+    pub fn split_tuple_fields(&self) -> Vec<STPort> {
+        let fields = self.st_type.atom_type().atom_fields();
+        if fields.len() == 1 {
+            return vec!(self.clone());
+        }
+        fields.iter().enumerate().map(|(field, (suffix, field_type))| {
+            let atoms = self.st_vals.atoms.iter().map(|clock_atoms| {
+                clock_atoms.iter().map(|atom| atom.leaves()[field].clone()).collect()
+            }).collect();
+            STPort {
+                name: format!("{}{}", self.name, suffix),
+                st_type: self.st_type.replace_atom_type(field_type),
+                st_vals: STAtomsAndValids { atoms, valids: self.st_vals.valids.clone() }
+            }
+        }).collect()
+    }
+}
+
+/// Convert the Sequence values on a module's ports to Space-Time values.
+/// All the ports' Space-Time types must take the same number of clocks.
+///
+/// # Examples
+///
+/// ```
+/// use aetherling::languages::ports::{convert_seq_ports_to_st_ports, SeqPort};
+/// use aetherling::languages::space_time::types::Type;
+/// let st_type = Type::TSeq {n: 2, i: 0, elem_type: Box::from(Type::UInt8)};
+/// let st_ports = convert_seq_ports_to_st_ports(vec!(
+///     SeqPort {name: String::from("in0"), seq_val: vec!(1, 3), st_type: st_type.clone()},
+///     SeqPort {name: String::from("in1"), seq_val: vec!(2, 4), st_type: st_type.clone()}
+/// )).unwrap();
+///
+/// assert_eq!(st_ports[1].st_vals.valids, vec!(true, true));
+/// ```
+pub fn convert_seq_ports_to_st_ports<T: SerializableSeqValue>(
+    seq_ports: Vec<SeqPort<T>>) -> Result<Vec<STPort>, Box<dyn Error>> {
+    check_same_clocks(seq_ports.iter().map(|p| (p.name.as_str(), &p.st_type)))?;
+    Ok(seq_ports.into_iter().map(|SeqPort { name, seq_val, st_type }| {
+        let st_vals = convert_seq_val_to_st_atoms_and_valids(seq_val, &st_type);
+        STPort { name, st_type, st_vals }
+    }).collect())
+}
+
+/// Check that all the ports' Space-Time types take the same number of clocks
+pub fn check_same_clocks<'a, I: Iterator<Item=(&'a str, &'a Type)>>(ports: I) -> Result<(), Box<dyn Error>> {
+    let mut first: Option<(&str, u32)> = None;
+    for (name, st_type) in ports {
+        match first {
+            None => first = Some((name, st_type.clocks())),
+            Some((first_name, first_clocks)) if first_clocks != st_type.clocks() =>
+                return Err(format!("port {} takes {} clocks but port {} takes {} clocks",
+                                   name, st_type.clocks(), first_name, first_clocks).into()),
+            _ => ()
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::sequence::atoms::Atom;
+
+    #[test]
+    fn test_ports_with_different_clocks() {
+        let result = convert_seq_ports_to_st_ports(vec!(
+            SeqPort {name: String::from("in0"), seq_val: vec!(1, 3),
+                     st_type: Type::TSeq {n: 2, i: 0, elem_type: Box::from(Type::UInt8)}},
+            SeqPort {name: String::from("in1"), seq_val: vec!(2, 4),
+                     st_type: Type::SSeq {n: 2, elem_type: Box::from(Type::UInt8)}}
+        ));
+        assert_eq!(result.unwrap_err().to_string(),
+                   "port in1 takes 1 clocks but port in0 takes 2 clocks");
+    }
+
+    #[test]
+    fn test_split_nested_tuple_fields() {
+        let st_ports = convert_seq_ports_to_st_ports(vec!(
+            SeqPort {name: String::from("in0"), seq_val: vec!((1, (true, -2))),
+                     st_type: Type::TSeq {n: 1, i: 1, elem_type: Box::from(
+                         Type::ATuple {left: Box::from(Type::UInt8), right: Box::from(
+                             Type::ATuple {left: Box::from(Type::Bit), right: Box::from(Type::Int16)})})}}
+        )).unwrap();
+        let fields = st_ports[0].split_tuple_fields();
+        let names: Vec<&str> = fields.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, vec!("in0_left", "in0_right_left", "in0_right_right"));
+        assert_eq!(fields[2].st_type.size(), 16);
+        assert_eq!(fields[2].st_vals.atoms, vec!(vec!(Atom::Int(-2)), vec!(Atom::Int(0))));
+        assert_eq!(fields[2].st_vals.valids, vec!(true, false));
+    }
+}
@@ -1,81 +1,232 @@
 //! convert a Sequence value to a Space-Time value and save that value as a string to a buffer
 //! along with the valid and invalid clocks
 use super::sequence::serialize_values::SerializableSeqValue;
+use super::sequence::atoms::Atom;
 use super::space_time::types::Type;
-use std::collections::HashMap;
+use super::atom_format::TextConfig;
 use std::error::Error;
-use std::rc::Rc;
 use std::io::Write;
 
-pub fn convert_seq_val_to_st_val_and_valid_strings<T: SerializableSeqValue, W: Write>(
-    seq_val: T, st_type: Type, vals_sink: &mut W, valids_sink: &mut W) -> Result<(), Box<dyn Error>> {
-    let mut flat_val_strs: Vec<Rc<String>> = Vec::new();
-    seq_val.convert_to_flat_atom_list(&mut flat_val_strs, true);
-    let mut flat_val_idx_to_str: HashMap<usize, Rc<String>> = flat_val_strs.into_iter().enumerate().collect();
+pub fn convert_seq_val_to_st_val_and_valid_strings<T: SerializableSeqValue, V: Write, D: Write>(
+    seq_val: T, st_type: Type, vals_sink: &mut V, valids_sink: &mut D) -> Result<(), Box<dyn Error>> {
+    let st_vals = convert_seq_val_to_st_atoms_and_valids(seq_val, &st_type);
+    write_st_val_and_valid_strings(&st_vals, &st_type, &TextConfig::default(), vals_sink, valids_sink)
+}
 
+/// Write a Space-Time value's atoms and valid clocks as strings.
+/// Both the atoms and the valids are printed with `conf`'s atom format. The lanes
+/// of invalid clocks are `conf`'s don't-care marker, or the type's default atoms if there is no marker.
+///
+/// # Examples
+///
+/// ```
+/// use aetherling::languages::seq_value_to_st_value_and_valid_strings::{
+///     convert_seq_val_to_st_atoms_and_valids, write_st_val_and_valid_strings
+/// };
+/// use aetherling::languages::atom_format::{AtomFormat, DontCare, TextConfig};
+/// use aetherling::languages::space_time::types::Type;
+/// let st_type = Type::TSeq {n: 1, i: 1, elem_type: Box::from(Type::Bit)};
+/// let st_vals = convert_seq_val_to_st_atoms_and_valids(vec!(true), &st_type);
+/// let mut vals_builder = Vec::new();
+/// let mut valids_builder = Vec::new();
+/// let conf = TextConfig {atom_format: AtomFormat::Python, dont_care: None};
+/// write_st_val_and_valid_strings(&st_vals, &st_type, &conf, &mut vals_builder, &mut valids_builder).unwrap();
+///
+/// assert_eq!(String::from_utf8(vals_builder).unwrap(), "[True,False]");
+/// assert_eq!(String::from_utf8(valids_builder).unwrap(), "[True,False]");
+///
+/// let mut vals_builder = Vec::new();
+/// let mut valids_builder = Vec::new();
+/// let conf = TextConfig {atom_format: AtomFormat::Python, dont_care: Some(DontCare::LowerX)};
+/// write_st_val_and_valid_strings(&st_vals, &st_type, &conf, &mut vals_builder, &mut valids_builder).unwrap();
+///
+/// assert_eq!(String::from_utf8(vals_builder).unwrap(), "[True,x]");
+/// assert_eq!(String::from_utf8(valids_builder).unwrap(), "[True,False]");
+/// ```
+pub fn write_st_val_and_valid_strings<V: Write, D: Write>(st_vals: &STAtomsAndValids, st_type: &Type, conf: &TextConfig,
+                                                          vals_sink: &mut V, valids_sink: &mut D) -> Result<(), Box<dyn Error>> {
     let total_width = st_type.atoms_per_valid();
-    let total_time = st_type.clocks();
-    let mut st_vals: Vec<Vec<Rc<String>>> = Vec::with_capacity(total_time as usize);
-    let mut st_valids: Vec<bool> = Vec::with_capacity(total_time as usize);
-    convert_seq_idxs_to_vals_to_time_space_vecs(&mut flat_val_idx_to_str, &mut st_vals,
-                                                &mut st_valids, st_type);
+    let atom_type = st_type.atom_type();
+    let atom_str = |t: usize, s: usize| conf.format_lane(&st_vals.atoms[t][s], atom_type, st_vals.valids[t]);
 
     // write a csv array where only wrap the space dimension if it has more than 1 element
-    vals_sink.write("[".as_ref())?;
-    valids_sink.write("[".as_ref())?;
-    for t in 0..total_time {
+    vals_sink.write_all("[".as_ref())?;
+    valids_sink.write_all("[".as_ref())?;
+    for t in 0..st_vals.valids.len() {
         if t > 0 {
-            vals_sink.write(",".as_ref())?;
-            valids_sink.write(",".as_ref())?;
+            vals_sink.write_all(",".as_ref())?;
+            valids_sink.write_all(",".as_ref())?;
         }
         if total_width == 1 {
-            vals_sink.write(st_vals[t as usize][0].as_ref().as_bytes())?;
+            vals_sink.write_all(atom_str(t, 0).as_bytes())?;
         }
         else {
-            vals_sink.write("[".as_ref())?;
+            vals_sink.write_all("[".as_ref())?;
             for s in 0..total_width {
-                vals_sink.write(st_vals[t as usize][s as usize].as_ref().as_bytes())?;
+                vals_sink.write_all(atom_str(t, s as usize).as_bytes())?;
                 if s < total_width - 1 {
-                    vals_sink.write(",".as_ref())?;
+                    vals_sink.write_all(",".as_ref())?;
                 }
             }
-            vals_sink.write("]".as_ref())?;
+            vals_sink.write_all("]".as_ref())?;
         }
-        valids_sink.write(st_valids[t as usize].to_string().as_ref())?;
+        valids_sink.write_all(conf.format_valid(st_vals.valids[t]).as_bytes())?;
     }
-    vals_sink.write("]".as_ref())?;
-    valids_sink.write("]".as_ref())?;
+    vals_sink.write_all("]".as_ref())?;
+    valids_sink.write_all("]".as_ref())?;
     vals_sink.flush()?;
     valids_sink.flush()?;
     Ok(())
 }
 
-fn convert_seq_idxs_to_vals_to_time_space_vecs(seq_idxs_to_vals: &mut HashMap<usize, Rc<String>>,
-                                               time_space_values_vec: &mut Vec<Vec<Rc<String>>>,
-                                               time_valids_vec: &mut Vec<bool>,
-                                               st_type: Type) {
+/// Write a Space-Time value's valid clocks and atoms to one sink, one line per clock.
+/// Each line is the clock's valid, a space, and its atoms as `write_st_val_and_valid_strings`
+/// prints them.
+///
+/// # Examples
+///
+/// ```
+/// use aetherling::languages::seq_value_to_st_value_and_valid_strings::{
+///     convert_seq_val_to_st_atoms_and_valids, write_st_interleaved_strings
+/// };
+/// use aetherling::languages::atom_format::TextConfig;
+/// use aetherling::languages::space_time::types::Type;
+/// let st_type = Type::TSeq {n: 1, i: 1, elem_type: Box::from(
+///     Type::SSeq {n: 2, elem_type: Box::from(Type::UInt8)})};
+/// let st_vals = convert_seq_val_to_st_atoms_and_valids(vec!(vec!(3, 4)), &st_type);
+/// let mut builder = Vec::new();
+/// write_st_interleaved_strings(&st_vals, &st_type, &TextConfig::default(), &mut builder).unwrap();
+///
+/// assert_eq!(String::from_utf8(builder).unwrap(), "true [3,4]\nfalse [0,0]\n");
+/// ```
+pub fn write_st_interleaved_strings<W: Write>(st_vals: &STAtomsAndValids, st_type: &Type, conf: &TextConfig,
+                                              sink: &mut W) -> Result<(), Box<dyn Error>> {
+    let atom_type = st_type.atom_type();
+    for (clock_atoms, valid) in st_vals.atoms.iter().zip(st_vals.valids.iter()) {
+        let lanes: Vec<String> = clock_atoms.iter().map(|a| conf.format_lane(a, atom_type, *valid)).collect();
+        let vals = if st_type.atoms_per_valid() == 1 {
+            lanes[0].clone()
+        } else {
+            format!("[{}]", lanes.join(","))
+        };
+        writeln!(sink, "{} {}", conf.format_valid(*valid), vals)?;
+    }
+    sink.flush()?;
+    Ok(())
+}
+
+/// A Space-Time value as a grid of atoms, indexed by `atoms[clock][lane]`,
+/// along with whether each clock is valid.
+/// Every lane of an invalid clock holds the Space-Time type's default atom.
+#[derive(Debug, Clone, PartialEq)]
+pub struct STAtomsAndValids {
+    pub atoms: Vec<Vec<Atom>>,
+    pub valids: Vec<bool>
+}
+
+impl STAtomsAndValids {
+    /// Get `clocks` invalid clocks of a Space-Time type
+    pub fn invalid_clocks(st_type: &Type, clocks: u32) -> STAtomsAndValids {
+        let lanes = vec![st_type.def_atom(); st_type.atoms_per_valid() as usize];
+        STAtomsAndValids {
+            atoms: vec![lanes; clocks as usize],
+            valids: vec![false; clocks as usize]
+        }
+    }
+
+    /// Add another value's clocks after this value's clocks
+    pub fn append(&mut self, other: STAtomsAndValids) {
+        self.atoms.extend(other.atoms);
+        self.valids.extend(other.valids);
+    }
+}
+
+/// The options for laying out multiple Space-Time values as one stream of clocks
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StreamConfig {
+    /// The number of invalid clocks before the first value, such as a module's pipeline delay
+    pub latency: u32,
+    /// The number of invalid clocks between each pair of back-to-back values
+    pub frame_gap: u32
+}
+
+/// Convert Sequence values, such as the frames of a video, to Space-Time values
+/// and concatenate them into one stream of clocks.
+///
+/// # Examples
+///
+/// ```
+/// use aetherling::languages::seq_value_to_st_value_and_valid_strings::{
+///     convert_seq_vals_to_st_stream, StreamConfig
+/// };
+/// use aetherling::languages::space_time::types::Type;
+/// let st = convert_seq_vals_to_st_stream(vec!(vec!(1, 3), vec!(2, 4)),
+///                                        &Type::TSeq {n: 2, i: 0, elem_type: Box::from(Type::UInt8)},
+///                                        &StreamConfig {latency: 2, frame_gap: 1});
+///
+/// assert_eq!(st.valids, vec!(false, false, true, true, false, true, true));
+/// ```
+pub fn convert_seq_vals_to_st_stream<T: SerializableSeqValue>(
+    seq_vals: Vec<T>, st_type: &Type, conf: &StreamConfig) -> STAtomsAndValids {
+    let mut stream = STAtomsAndValids::invalid_clocks(st_type, conf.latency);
+    for (idx, seq_val) in seq_vals.into_iter().enumerate() {
+        if idx > 0 {
+            stream.append(STAtomsAndValids::invalid_clocks(st_type, conf.frame_gap));
+        }
+        stream.append(convert_seq_val_to_st_atoms_and_valids(seq_val, st_type));
+    }
+    stream
+}
+
+/// Convert a Sequence value to a Space-Time value's atoms and valid clocks
+///
+/// # Examples
+///
+/// ```
+/// use aetherling::languages::seq_value_to_st_value_and_valid_strings::convert_seq_val_to_st_atoms_and_valids;
+/// use aetherling::languages::sequence::atoms::Atom;
+/// use aetherling::languages::space_time::types::Type;
+/// let st = convert_seq_val_to_st_atoms_and_valids(
+///     vec!(1u32, 3), &Type::TSeq {n: 2, i: 1, elem_type: Box::from(Type::UInt8)});
+///
+/// assert_eq!(st.atoms, vec!(vec!(Atom::UInt(1)), vec!(Atom::UInt(3)), vec!(Atom::UInt(0))));
+/// assert_eq!(st.valids, vec!(true, true, false));
+/// ```
+pub fn convert_seq_val_to_st_atoms_and_valids<T: SerializableSeqValue>(
+    seq_val: T, st_type: &Type) -> STAtomsAndValids {
+    let mut flat_atoms: Vec<Atom> = Vec::new();
+    seq_val.convert_to_flat_atoms(&mut flat_atoms);
+
+    let total_time = st_type.clocks();
+    let mut atoms: Vec<Vec<Atom>> = Vec::with_capacity(total_time as usize);
+    let mut valids: Vec<bool> = Vec::with_capacity(total_time as usize);
+    convert_seq_idxs_to_vals_to_time_space_vecs(&flat_atoms, &mut atoms, &mut valids,
+                                                st_type, st_type.def_atom());
+    STAtomsAndValids { atoms, valids }
+}
+
+fn convert_seq_idxs_to_vals_to_time_space_vecs<A: Clone>(seq_idxs_to_vals: &[A],
+                                                         time_space_values_vec: &mut Vec<Vec<A>>,
+                                                         time_valids_vec: &mut Vec<bool>,
+                                                         st_type: &Type, def_val: A) {
     let total_width = st_type.atoms_per_valid();
     let total_time = st_type.clocks();
     let valid_time = st_type.valid_clocks();
-    let def_str = Rc::new(st_type.def_atom_str());
     for _ in 0..total_time {
-        let mut inner_vec = Vec::with_capacity(total_width as usize);
-        for _ in 0..total_width {
-           inner_vec.push(def_str.clone());
-        }
-        time_space_values_vec.push(inner_vec);
+        time_space_values_vec.push(vec![def_val.clone(); total_width as usize]);
         time_valids_vec.push(true);
     }
     set_val_in_time_space_vecs(seq_idxs_to_vals, time_space_values_vec, time_valids_vec,
-                               &st_type, total_width, total_time, valid_time, 0, 0, true, 0);
+                               st_type, total_width, total_time, valid_time, 0, 0, true, 0);
 }
 
-fn set_val_in_time_space_vecs(seq_idx_to_vals: &mut HashMap<usize, Rc<String>>,
-                              time_space_values_vec: &mut Vec<Vec<Rc<String>>>,
-                              time_valids_vec: &mut Vec<bool>,
-                              st_type: &Type, total_width: u32, total_time: u32,
-                              valid_time: u32, cur_space: u32, cur_time: u32,
-                              valid: bool, cur_idx: u32) {
+#[allow(clippy::too_many_arguments)]
+fn set_val_in_time_space_vecs<A: Clone>(seq_idx_to_vals: &[A],
+                                        time_space_values_vec: &mut Vec<Vec<A>>,
+                                        time_valids_vec: &mut Vec<bool>,
+                                        st_type: &Type, total_width: u32, total_time: u32,
+                                        valid_time: u32, cur_space: u32, cur_time: u32,
+                                        valid: bool, cur_idx: u32) {
     match st_type {
         Type::STuple { n, elem_type } => {
             let element_width = total_width / *n;
@@ -116,7 +267,7 @@ fn set_val_in_time_space_vecs(seq_idx_to_vals: &mut HashMap<usize, Rc<String>>,
         _ =>  {
             if valid {
                 time_space_values_vec[cur_time as usize][cur_space as usize] =
-                    seq_idx_to_vals.get_mut(&(cur_idx as usize)).expect("couldn't get atom string at index").clone();
+                    seq_idx_to_vals.get(cur_idx as usize).expect("couldn't get atom at index").clone();
             }
             else if cur_space == 0 {
                 time_valids_vec[cur_time as usize] = false;
@@ -181,6 +332,23 @@ mod tests {
         let valids_data = String::from_utf8(valids_builder).unwrap();
         assert_eq!(valids_data, String::from("[true,true,true]"));
     }
+
+    #[test]
+    fn test_write_st_stream_strings_with_latency_and_gap() {
+        let mut vals_builder = Vec::new();
+        let mut valids_builder = Vec::new();
+        let st_type = Type::TSeq {n: 1, i: 1, elem_type: Box::from(
+            Type::SSeq {n: 2, elem_type: Box::from(Type::Bit)})};
+        let st_vals = convert_seq_vals_to_st_stream(vec!(vec!(true, true), vec!(false, true)), &st_type,
+                                                    &StreamConfig {latency: 1, frame_gap: 1});
+        write_st_val_and_valid_strings(&st_vals, &st_type, &TextConfig::default(),
+                                       &mut vals_builder, &mut valids_builder).unwrap();
+        let vals_data = String::from_utf8(vals_builder).unwrap();
+        assert_eq!(vals_data, String::from(
+            "[[false,false],[true,true],[false,false],[false,false],[false,true],[false,false]]"));
+        let valids_data = String::from_utf8(valids_builder).unwrap();
+        assert_eq!(valids_data, String::from("[false,true,false,false,true,false]"));
+    }
 /*
     #[test]
     fn test_convert_seq_val_to_st_val_string_big() {
@@ -1,6 +1,8 @@
 pub mod types;
+pub mod atoms;
 pub mod serialize_types;
 pub mod serialize_values;
+pub mod gen_values;
 
 pub mod proto {
     include!(concat!(env!("OUT_DIR"), "/languages.sequence.proto.rs"));
@@ -0,0 +1,97 @@
+//! The atoms of Aetherling Sequence values, kept as Rust values rather than strings
+//! so they can be packed into bits for hardware formats
+use std::fmt;
+
+/// A single atom of a Sequence value.
+/// Tuples are of atoms only, matching `Type::ATuple`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Atom {
+    Unit,
+    Bit(bool),
+    Int(i32),
+    UInt(u32),
+    Tuple(Box<Atom>, Box<Atom>)
+}
+
+impl Atom {
+    /// Get the non-tuple atoms in this atom, left to right
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use aetherling::languages::sequence::atoms::Atom;
+    /// let a = Atom::Tuple(Box::from(Atom::Int(3)), Box::from(Atom::Bit(true)));
+    ///
+    /// assert_eq!(a.leaves(), vec!(&Atom::Int(3), &Atom::Bit(true)));
+    /// ```
+    pub fn leaves(&self) -> Vec<&Atom> {
+        let mut leaves = Vec::new();
+        self.add_leaves(&mut leaves);
+        leaves
+    }
+
+    fn add_leaves<'a>(&'a self, leaves: &mut Vec<&'a Atom>) {
+        match self {
+            Atom::Tuple(left, right) => {
+                left.add_leaves(leaves);
+                right.add_leaves(leaves);
+            }
+            _ => leaves.push(self)
+        }
+    }
+
+    /// Get the low `width` bits of a non-tuple atom.
+    /// Signed atoms are in two's complement.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use aetherling::languages::sequence::atoms::Atom;
+    ///
+    /// assert_eq!(Atom::Int(-1).to_bits(8), 0xff);
+    /// assert_eq!(Atom::Bit(true).to_bits(1), 1);
+    /// ```
+    pub fn to_bits(&self, width: u32) -> u64 {
+        let bits = match self {
+            Atom::Unit => 0,
+            Atom::Bit(b) => *b as u64,
+            Atom::Int(i) => *i as i64 as u64,
+            Atom::UInt(u) => *u as u64,
+            Atom::Tuple(..) => panic!("can't get bits of a tuple, get bits of its leaves")
+        };
+        if width >= 64 {
+            bits
+        } else {
+            bits & ((1u64 << width) - 1)
+        }
+    }
+}
+
+impl fmt::Display for Atom {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Atom::Unit => write!(f, "()"),
+            Atom::Bit(b) => write!(f, "{}", b),
+            Atom::Int(i) => write!(f, "{}", i),
+            Atom::UInt(u) => write!(f, "{}", u),
+            Atom::Tuple(left, right) => write!(f, "[{},{}]", left, right)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_matches_flat_atom_strings() {
+        let a = Atom::Tuple(Box::from(Atom::Int(3)), Box::from(Atom::Bit(false)));
+        assert_eq!(a.to_string(), "[3,false]");
+    }
+
+    #[test]
+    fn test_to_bits_negative_int() {
+        assert_eq!(Atom::Int(-2).to_bits(16), 0xfffe);
+        assert_eq!(Atom::Int(-2).to_bits(32), 0xffff_fffe);
+    }
+}
@@ -0,0 +1,179 @@
+//! generate Sequence values of a type to use as test vectors,
+//! with every atom in its type's range
+use super::types::Type;
+use super::serialize_values::SerializableSeqValue;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use std::error::Error;
+
+/// How to choose the atoms of a generated value
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    /// Uniformly random atoms from a seeded generator
+    Random { seed: u64 },
+    /// The `k`th atom, counting each side of a tuple, is `start + k * step`,
+    /// wrapped around the atom's range
+    Ramp { start: i64, step: i64 },
+    /// Every atom is the same value
+    Constant(i64),
+    /// Atoms alternate between their type's smallest and largest values,
+    /// switching when any Seq index changes by one
+    Checkerboard,
+    /// Every atom is the row-major index of its element, wrapped around the atom's range
+    Index
+}
+
+/// Generate a value of a Sequence type in a pattern
+///
+/// # Examples
+///
+/// ```
+/// use aetherling::languages::sequence::gen_values::{gen_value, Pattern};
+/// use aetherling::languages::sequence::serialize_values::save_value;
+/// use aetherling::languages::sequence::types::Type;
+/// let t = Type::Seq {n: 2, elem_type: Box::from(Type::Seq {n: 2, elem_type: Box::from(Type::Int8)})};
+///
+/// assert_eq!(save_value(&gen_value(&t, &Pattern::Checkerboard).unwrap()),
+///            save_value(&vec!(vec!(-128, 127), vec!(127, -128))));
+/// assert_eq!(save_value(&gen_value(&t, &Pattern::Ramp {start: 126, step: 1}).unwrap()),
+///            save_value(&vec!(vec!(126, 127), vec!(-128, -127))));
+/// ```
+pub fn gen_value(seq_type: &Type, pattern: &Pattern) -> Result<Box<dyn SerializableSeqValue>, Box<dyn Error>> {
+    match pattern {
+        Pattern::Random { seed } => {
+            let mut rng = StdRng::seed_from_u64(*seed);
+            gen_value_per_index(seq_type, |_, atom_type| {
+                let (min, max) = atom_type.range().unwrap();
+                rng.gen_range(min, max + 1)
+            })
+        }
+        Pattern::Ramp { start, step } => {
+            let mut k: i64 = 0;
+            gen_value_per_index(seq_type, |_, atom_type| {
+                let atom = wrap(start.wrapping_add(k.wrapping_mul(*step)), atom_type);
+                k += 1;
+                atom
+            })
+        }
+        Pattern::Constant(atom) => gen_value_per_index(seq_type, |_, _| *atom),
+        Pattern::Checkerboard => gen_value_per_index(seq_type, |idx, atom_type| {
+            let (min, max) = atom_type.range().unwrap();
+            if idx.iter().sum::<usize>() % 2 == 0 { min } else { max }
+        }),
+        Pattern::Index => {
+            let dims = seq_type.dims();
+            gen_value_per_index(seq_type, |idx, atom_type| {
+                let flat = idx.iter().zip(dims.iter()).fold(0i64, |acc, (i, n)| acc * *n as i64 + *i as i64);
+                wrap(flat, atom_type)
+            })
+        }
+    }
+}
+
+/// Generate a value of a Sequence type with `atom(idx, atom_type)` as each atom,
+/// where `idx` is the atom's index in each Seq, outermost first.
+/// Tuples call `atom` once per side, left first. Bits are 0 or 1.
+/// Returns an error if an atom is out of its type's range.
+///
+/// # Examples
+///
+/// ```
+/// use aetherling::languages::sequence::gen_values::gen_value_per_index;
+/// use aetherling::languages::sequence::serialize_values::save_value;
+/// use aetherling::languages::sequence::types::Type;
+/// let t = Type::Seq {n: 3, elem_type: Box::from(Type::UInt16)};
+///
+/// assert_eq!(save_value(&gen_value_per_index(&t, |idx, _| 100 * idx[0] as i64).unwrap()),
+///            save_value(&vec!(0u32, 100, 200)));
+/// assert!(gen_value_per_index(&t, |idx, _| idx[0] as i64 - 1).is_err());
+/// ```
+pub fn gen_value_per_index<F>(seq_type: &Type, mut atom: F) -> Result<Box<dyn SerializableSeqValue>, Box<dyn Error>>
+    where F: FnMut(&[usize], &Type) -> i64 {
+    gen_at(seq_type, &mut Vec::new(), &mut atom)
+}
+
+fn gen_at<F>(seq_type: &Type, idx: &mut Vec<usize>, atom: &mut F) -> Result<Box<dyn SerializableSeqValue>, Box<dyn Error>>
+    where F: FnMut(&[usize], &Type) -> i64 {
+    match seq_type {
+        Type::Unit => Err("Unit values can't be generated".into()),
+        Type::ATuple {left, right} => Ok(Box::new((gen_at(left, idx, atom)?, gen_at(right, idx, atom)?))),
+        Type::Seq {n, elem_type} => {
+            let mut elems: Vec<Box<dyn SerializableSeqValue>> = Vec::with_capacity(*n as usize);
+            for i in 0..*n as usize {
+                idx.push(i);
+                elems.push(gen_at(elem_type, idx, atom)?);
+                idx.pop();
+            }
+            Ok(Box::new(elems))
+        }
+        _ => {
+            let value = atom(idx, seq_type);
+            let (min, max) = seq_type.range().unwrap();
+            if value < min || value > max {
+                Err(format!("{} is out of range for {}", value, seq_type).into())
+            } else if *seq_type == Type::Bit {
+                Ok(Box::new(value == 1))
+            } else if min < 0 {
+                Ok(Box::new(value as i32))
+            } else {
+                Ok(Box::new(value as u32))
+            }
+        }
+    }
+}
+
+/// Wrap a number around an atom type's range, like two's complement overflow
+fn wrap(value: i64, atom_type: &Type) -> i64 {
+    let (min, max) = atom_type.range().unwrap();
+    let span = (max - min + 1) as i128;
+    ((value as i128 - min as i128).rem_euclid(span) + min as i128) as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::serialize_values::save_value;
+    use super::super::atoms::Atom;
+
+    fn atoms_of(value: &dyn SerializableSeqValue) -> Vec<Atom> {
+        let mut atoms = Vec::new();
+        value.convert_to_flat_atoms(&mut atoms);
+        atoms
+    }
+
+    #[test]
+    fn test_random_is_seeded_and_in_range() {
+        let t = Type::Seq {n: 50, elem_type: Box::from(
+            Type::ATuple {left: Box::from(Type::Int8), right: Box::from(Type::UInt16)})};
+        let gen = |seed| save_value(&gen_value(&t, &Pattern::Random {seed}).unwrap());
+        assert_eq!(gen(7), gen(7));
+        assert_ne!(gen(7), gen(8));
+        for atom in atoms_of(gen_value(&t, &Pattern::Random {seed: 7}).unwrap().as_ref()) {
+            match atom {
+                Atom::Tuple(left, right) => match (*left, *right) {
+                    (Atom::Int(l), Atom::UInt(r)) => assert!((-128..=127).contains(&l) && r <= 0xffff),
+                    other => panic!("unexpected atoms {:?}", other)
+                },
+                other => panic!("unexpected atom {:?}", other)
+            }
+        }
+    }
+
+    #[test]
+    fn test_index_and_bits() {
+        let t = Type::Seq {n: 2, elem_type: Box::from(Type::Seq {n: 3, elem_type: Box::from(Type::Bit)})};
+        assert_eq!(save_value(&gen_value(&t, &Pattern::Index).unwrap()),
+                   save_value(&vec!(vec!(false, true, false), vec!(true, false, true))));
+        let t = Type::Seq {n: 2, elem_type: Box::from(Type::Seq {n: 3, elem_type: Box::from(Type::UInt32)})};
+        assert_eq!(save_value(&gen_value(&t, &Pattern::Index).unwrap()),
+                   save_value(&vec!(vec!(0u32, 1, 2), vec!(3u32, 4, 5))));
+    }
+
+    #[test]
+    fn test_constant_out_of_range() {
+        let t = Type::Seq {n: 2, elem_type: Box::from(Type::UInt8)};
+        assert_eq!(gen_value(&t, &Pattern::Constant(256)).err().unwrap().to_string(),
+                   "256 is out of range for UInt8");
+        assert!(gen_value(&Type::Unit, &Pattern::Constant(0)).is_err());
+    }
+}
@@ -1,9 +1,10 @@
 use std::io::Cursor;
+use std::error::Error;
 use prost::Message;
 use std::fmt::Write;
-use std::rc::Rc;
 use super::proto::{ValueSerialized, TupleValue, SeqValue};
 use super::proto::value_serialized::Elems;
+use super::atoms::Atom;
 
 /// Convert a buffer with a protobuf representation of a Sequence value
 /// to a Rust, Aetherling Sequence value
@@ -14,46 +15,58 @@ use super::proto::value_serialized::Elems;
 /// let saved_value = save_value(&vec!(true,false));
 /// let loaded_value = load_value(&saved_value);
 ///
-/// use std::rc::Rc;
-/// let mut result_builder: Vec<Rc<String>> = Vec::new();
+/// let mut result_builder: Vec<String> = Vec::new();
 /// loaded_value.convert_to_flat_atom_list(&mut result_builder, true);
-/// let mut test_builder: Vec<Rc<String>> = Vec::new();
+/// let mut test_builder: Vec<String> = Vec::new();
 /// vec!(true,false).convert_to_flat_atom_list(&mut test_builder, true);
 /// assert_eq!(result_builder, test_builder)
 /// ```
 pub fn load_value<T: AsRef<[u8]>>(src: &T) -> Box<dyn SerializableSeqValue> {
+    try_load_value(src).unwrap()
+}
+
+/// Like `load_value`, but return an error instead of panicking if the buffer
+/// isn't a well-formed protobuf Sequence value
+///
+/// # Examples
+/// ```
+/// use aetherling::languages::sequence::serialize_values::{try_load_value, save_value};
+/// assert!(try_load_value(&save_value(&vec!(true,false))).is_ok());
+/// assert!(try_load_value(&[0xffu8, 0xff, 0xff]).is_err());
+/// ```
+pub fn try_load_value<T: AsRef<[u8]>>(src: &T) -> Result<Box<dyn SerializableSeqValue>, Box<dyn Error>> {
     let serialized_value = ValueSerialized::decode(&mut Cursor::new(src))
-        .unwrap();
+        .map_err(|e| format!("bad protobuf value: {}", e))?;
     deserialize_value(&serialized_value)
 }
 
-fn deserialize_value( serialized_value : &ValueSerialized) -> Box<dyn SerializableSeqValue> {
+fn deserialize_value( serialized_value : &ValueSerialized) -> Result<Box<dyn SerializableSeqValue>, Box<dyn Error>> {
     match &serialized_value.elems {
         Some(elem) => {
-            match elem {
+            Ok(match elem {
                 Elems::Int(e) => Box::new(*e),
                 Elems::Uint(e) => Box::new(*e),
                 Elems::Bit(e) => Box::new(*e),
                 Elems::Tuple(e_box) => {
                     let e_left = match &e_box.left {
-                        Some(e_left_elem) => deserialize_value(&e_left_elem),
-                        None => panic!("tuple with no left element")
+                        Some(e_left_elem) => deserialize_value(e_left_elem)?,
+                        None => return Err("tuple with no left element".into())
                     };
                     let e_right = match &e_box.right {
-                        Some(e_right_elem) => deserialize_value(&e_right_elem),
-                        None => panic!("tuple with no right element")
+                        Some(e_right_elem) => deserialize_value(e_right_elem)?,
+                        None => return Err("tuple with no right element".into())
                     };
                     Box::new((e_left, e_right))
                 }
                 Elems::Seq(e_vec) => {
                     let e_vec_deserialized: Vec<Box<dyn SerializableSeqValue>> =
                         e_vec.values.iter()
-                            .map(|e| deserialize_value(e)).collect();
+                            .map(|e| deserialize_value(e)).collect::<Result<_, _>>()?;
                     Box::new(e_vec_deserialized)
                 }
-            }
+            })
         },
-        None => panic!("deserializing empty value")
+        None => Err("deserializing empty value".into())
     }
 }
 /// Convert a Rust, Aetherling Sequence value to a buffer with a
@@ -65,23 +78,41 @@ fn deserialize_value( serialized_value : &ValueSerialized) -> Box<dyn Serializab
 /// let saved_value = save_value(&vec!(1,2));
 /// let loaded_value = load_value(&saved_value);
 ///
-/// use std::rc::Rc;
-/// let mut result_builder: Vec<Rc<String>> = Vec::new();
+/// let mut result_builder: Vec<String> = Vec::new();
 /// loaded_value.convert_to_flat_atom_list(&mut result_builder, true);
-/// let mut test_builder: Vec<Rc<String>> = Vec::new();
+/// let mut test_builder: Vec<String> = Vec::new();
 /// vec!(1,2).convert_to_flat_atom_list(&mut test_builder, true);
 /// assert_eq!(result_builder, test_builder)
 /// ```
 pub fn save_value<T: SerializableSeqValue>(src: &T) -> Vec<u8> {
-    let mut buffer = Vec::new();
-    buffer.reserve(buffer.encoded_len());
     let proto_value = src.convert_to_rust_proto();
+    let mut buffer = Vec::with_capacity(proto_value.encoded_len());
     // Unwrap is safe, since we have reserved sufficient capacity in the vector.
     proto_value.encode(&mut buffer).unwrap();
     buffer
 }
 
-pub trait SerializableSeqValue {
+/// Split a Seq value into its elements, such as a list of frames into the frames.
+/// Returns `None` if the value isn't a Seq.
+///
+/// # Examples
+/// ```
+/// use aetherling::languages::sequence::serialize_values::{save_value, split_seq_value};
+/// let frames = split_seq_value(&vec!(vec!(1, 2), vec!(3, 4))).unwrap();
+///
+/// assert_eq!(save_value(&frames[1]), save_value(&vec!(3, 4)));
+/// assert!(split_seq_value(&5).is_none());
+/// ```
+pub fn split_seq_value<T: SerializableSeqValue + ?Sized>(src: &T) -> Option<Vec<Box<dyn SerializableSeqValue>>> {
+    match src.convert_to_rust_proto().elems {
+        // Unwrap is safe, since the elements were just serialized from a value.
+        Some(Elems::Seq(seq)) => Some(seq.values.iter().map(|v| deserialize_value(v).unwrap()).collect()),
+        _ => None
+    }
+}
+
+/// Sequence values are `Send + Sync` so they can be converted on many threads.
+pub trait SerializableSeqValue: Send + Sync {
     /// Convert a sequence value to a Rust struct that can be serialized
     /// by protobuf
     fn convert_to_rust_proto(&self) -> ValueSerialized;
@@ -91,7 +122,10 @@ pub trait SerializableSeqValue {
     ///
     /// Call this with an empty `builder` and `top` as True, it will recur and
     /// update those values
-    fn convert_to_flat_atom_list(&self, builder: &mut Vec<Rc<String>>, top: bool);
+    fn convert_to_flat_atom_list(&self, builder: &mut Vec<String>, top: bool);
+    /// Convert a sequence value to a 1D Vec of its atoms.
+    /// The atom vec argument stores the result.
+    fn convert_to_flat_atoms(&self, builder: &mut Vec<Atom>);
 }
 
 impl SerializableSeqValue for i32 {
@@ -99,13 +133,17 @@ impl SerializableSeqValue for i32 {
         ValueSerialized { elems: Some(Elems::Int(*self)) }
     }
 
-    fn convert_to_flat_atom_list(&self, builder: &mut Vec<Rc<String>>, _: bool) {
+    fn convert_to_flat_atoms(&self, builder: &mut Vec<Atom>) {
+        builder.push(Atom::Int(*self))
+    }
+
+    fn convert_to_flat_atom_list(&self, builder: &mut Vec<String>, _: bool) {
         match builder.last_mut() {
-            Some(s) => write!(Rc::get_mut(s).unwrap(), "{}", self),
+            Some(s) => write!(s, "{}", self),
             None => {
                 let mut s = String::new();
                 let write_result = write!(s, "{}", self);
-                builder.push(Rc::from(s));
+                builder.push(s);
                 write_result
             }
         }.unwrap();
@@ -117,13 +155,17 @@ impl SerializableSeqValue for u32 {
         ValueSerialized { elems: Some(Elems::Uint(*self)) }
     }
 
-    fn convert_to_flat_atom_list(&self, builder: &mut Vec<Rc<String>>, _: bool) {
+    fn convert_to_flat_atoms(&self, builder: &mut Vec<Atom>) {
+        builder.push(Atom::UInt(*self))
+    }
+
+    fn convert_to_flat_atom_list(&self, builder: &mut Vec<String>, _: bool) {
         match builder.last_mut() {
-            Some(s) => write!(Rc::get_mut(s).unwrap(), "{}", self),
+            Some(s) => write!(s, "{}", self),
             None => {
                 let mut s = String::new();
                 let write_result = write!(s, "{}", self);
-                builder.push(Rc::from(s));
+                builder.push(s);
                 write_result
             }
         }.unwrap();
@@ -135,13 +177,17 @@ impl SerializableSeqValue for bool {
         ValueSerialized { elems: Some(Elems::Bit(*self)) }
     }
 
-    fn convert_to_flat_atom_list(&self, builder: &mut Vec<Rc<String>>, _: bool) {
+    fn convert_to_flat_atoms(&self, builder: &mut Vec<Atom>) {
+        builder.push(Atom::Bit(*self))
+    }
+
+    fn convert_to_flat_atom_list(&self, builder: &mut Vec<String>, _: bool) {
         match builder.last_mut() {
-            Some(s) => write!(Rc::get_mut(s).unwrap(), "{}", self),
+            Some(s) => write!(s, "{}", self),
             None => {
                 let mut s = String::new();
                 let write_result = write!(s, "{}", self);
-                builder.push(Rc::from(s));
+                builder.push(s);
                 write_result
             }
         }.unwrap();
@@ -158,12 +204,12 @@ impl<A: SerializableSeqValue, B: SerializableSeqValue> SerializableSeqValue for
         ValueSerialized { elems: Some(Elems::Tuple(Box::new(tuple_value))) }
     }
 
-    fn convert_to_flat_atom_list(&self, builder: &mut Vec<Rc<String>>, _: bool) {
+    fn convert_to_flat_atom_list(&self, builder: &mut Vec<String>, _: bool) {
         // ensure builder isn't empty
         match builder.last_mut() {
             Some(s) => s,
             None => {
-                builder.push(Rc::from(String::new()));
+                builder.push(String::new());
                 // the compiler doesn't know this is safe, but I do
                 builder.last_mut().unwrap()
             }
@@ -171,11 +217,21 @@ impl<A: SerializableSeqValue, B: SerializableSeqValue> SerializableSeqValue for
         let (a,b) = self;
         // now I know builder isn't empty and I'm trusting
         // that tuples are only of atoms.
-        write!(Rc::get_mut(builder.last_mut().unwrap()).unwrap(), "[").unwrap();
+        write!(builder.last_mut().unwrap(), "[").unwrap();
         a.convert_to_flat_atom_list(builder, false);
-        write!(Rc::get_mut(builder.last_mut().unwrap()).unwrap(), ",").unwrap();
+        write!(builder.last_mut().unwrap(), ",").unwrap();
         b.convert_to_flat_atom_list(builder, false);
-        write!(Rc::get_mut(builder.last_mut().unwrap()).unwrap(), "]").unwrap();
+        write!(builder.last_mut().unwrap(), "]").unwrap();
+    }
+
+    fn convert_to_flat_atoms(&self, builder: &mut Vec<Atom>) {
+        let (a,b) = self;
+        // tuples are only of atoms, so each side adds exactly one atom
+        a.convert_to_flat_atoms(builder);
+        b.convert_to_flat_atoms(builder);
+        let right = builder.pop().expect("tuple with no right atom");
+        let left = builder.pop().expect("tuple with no left atom");
+        builder.push(Atom::Tuple(Box::new(left), Box::new(right)))
     }
 }
 
@@ -186,27 +242,37 @@ impl<A: SerializableSeqValue> SerializableSeqValue for Vec<A> {
         ValueSerialized { elems: Some(Elems::Seq(SeqValue { values: seq_values_serialized })) }
     }
 
-    fn convert_to_flat_atom_list(&self, builder: &mut Vec<Rc<String>>, top: bool) {
+    fn convert_to_flat_atom_list(&self, builder: &mut Vec<String>, top: bool) {
         for (idx, elem) in self.iter().enumerate() {
             // if this is the first element, only add a vec if this is the top vector
             // otherwise on first index let parent vector create string
             // always insert string otherwise
             if (idx == 0 && top) || (idx > 0) {
-                builder.push(Rc::from(String::new()))
+                builder.push(String::new())
             }
             elem.convert_to_flat_atom_list(builder, false)
         }
     }
+
+    fn convert_to_flat_atoms(&self, builder: &mut Vec<Atom>) {
+        for elem in self.iter() {
+            elem.convert_to_flat_atoms(builder)
+        }
+    }
 }
 
 impl<A: SerializableSeqValue + ?Sized > SerializableSeqValue for Box<A> {
     fn convert_to_rust_proto(&self) -> ValueSerialized {
-        unimplemented!()
+        self.as_ref().convert_to_rust_proto()
     }
 
-    fn convert_to_flat_atom_list(&self, builder: &mut Vec<Rc<String>>, top: bool) {
+    fn convert_to_flat_atom_list(&self, builder: &mut Vec<String>, top: bool) {
         self.as_ref().convert_to_flat_atom_list(builder, top)
     }
+
+    fn convert_to_flat_atoms(&self, builder: &mut Vec<Atom>) {
+        self.as_ref().convert_to_flat_atoms(builder)
+    }
 }
 
 #[cfg(test)]
@@ -215,39 +281,65 @@ mod tests {
 
     #[test]
     fn test_convert_to_flat_atom_list_int() {
-        let mut builder: Vec<Rc<String>> = Vec::new();
+        let mut builder: Vec<String> = Vec::new();
         1.convert_to_flat_atom_list(&mut builder, true);
-        assert_eq!(builder, vec!(Rc::from(String::from("1"))))
+        assert_eq!(builder, vec!(String::from("1")))
     }
 
     #[test]
     fn test_convert_to_flat_atom_list_bool() {
-        let mut builder: Vec<Rc<String>> = Vec::new();
+        let mut builder: Vec<String> = Vec::new();
         true.convert_to_flat_atom_list(&mut builder, true);
-        assert_eq!(builder, vec!(Rc::from(String::from("true"))))
+        assert_eq!(builder, vec!(String::from("true")))
     }
 
     #[test]
     fn test_convert_to_flat_atom_list_tuple() {
-        let mut builder: Vec<Rc<String>> = Vec::new();
+        let mut builder: Vec<String> = Vec::new();
         (3, false).convert_to_flat_atom_list(&mut builder, true);
-        assert_eq!(builder, vec!(Rc::from(String::from("[3,false]"))))
+        assert_eq!(builder, vec!(String::from("[3,false]")))
     }
 
     #[test]
     fn test_convert_to_flat_atom_list_array() {
-        let mut builder: Vec<Rc<String>> = Vec::new();
+        let mut builder: Vec<String> = Vec::new();
         vec!(4,2,1,5).convert_to_flat_atom_list(&mut builder, true);
-        assert_eq!(builder, vec!(Rc::from(String::from("4")), Rc::from(String::from("2")),
-                                 Rc::from(String::from("1")), Rc::from(String::from("5"))))
+        assert_eq!(builder, vec!(String::from("4"), String::from("2"),
+                                 String::from("1"), String::from("5")))
     }
 
     #[test]
     fn test_convert_to_flat_atom_list_nested_array() {
-        let mut builder: Vec<Rc<String>> = Vec::new();
+        let mut builder: Vec<String> = Vec::new();
         vec!(vec!(4,2),vec!(1,5)).convert_to_flat_atom_list(&mut builder, true);
-        assert_eq!(builder, vec!(Rc::from(String::from("4")), Rc::from(String::from("2")),
-                                 Rc::from(String::from("1")), Rc::from(String::from("5"))))
+        assert_eq!(builder, vec!(String::from("4"), String::from("2"),
+                                 String::from("1"), String::from("5")))
     }
-}
 
+    #[test]
+    fn test_convert_to_flat_atoms_nested_array_of_tuples() {
+        let mut builder: Vec<Atom> = Vec::new();
+        vec!(vec!((4,true)),vec!((1,false))).convert_to_flat_atoms(&mut builder);
+        assert_eq!(builder, vec!(Atom::Tuple(Box::new(Atom::Int(4)), Box::new(Atom::Bit(true))),
+                                 Atom::Tuple(Box::new(Atom::Int(1)), Box::new(Atom::Bit(false)))))
+    }
+
+    #[test]
+    fn test_save_load_boxed_value() {
+        let value: Box<dyn SerializableSeqValue> = Box::new(vec!((4, true), (1, false)));
+        let saved = save_value(&value);
+        assert_eq!(save_value(&load_value(&saved)), saved)
+    }
+
+    #[test]
+    fn test_try_load_value_tuple_with_no_right_element() {
+        let mut tuple = (3, false).convert_to_rust_proto();
+        if let Some(Elems::Tuple(t)) = &mut tuple.elems {
+            t.right = None;
+        }
+        let mut buffer = Vec::new();
+        tuple.encode(&mut buffer).unwrap();
+        let err = try_load_value(&buffer).err().unwrap();
+        assert_eq!(err.to_string(), "tuple with no right element");
+    }
+}
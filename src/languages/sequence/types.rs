@@ -1,8 +1,10 @@
 //! The deep embedding of Aetherling's Sequence types in Rust
 use super::super::util::*;
+use serde::{Deserialize, Serialize};
+use std::fmt;
 
 /// A type of a Sequence Languages expression's input or output.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Type {
     Unit,
     Bit,
@@ -16,3 +18,86 @@ pub enum Type {
     Seq{n: SeqLen, elem_type: Box<Type>},
 }
 
+impl Type {
+    /// Get the lengths of the nested Seqs in a type, outermost first.
+    /// An atom has no dimensions.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use aetherling::languages::sequence::types::Type;
+    /// let t = Type::Seq {n: 4, elem_type: Box::from(
+    ///     Type::Seq {n: 2, elem_type: Box::from(Type::UInt8)})};
+    ///
+    /// assert_eq!(t.dims(), vec!(4, 2));
+    /// assert_eq!(Type::Bit.dims(), Vec::<u32>::new());
+    /// ```
+    pub fn dims(&self) -> Vec<SeqLen> {
+        match self {
+            Type::Seq {n, elem_type} => {
+                let mut dims = vec!(*n);
+                dims.extend(elem_type.dims());
+                dims
+            }
+            _ => Vec::new()
+        }
+    }
+
+    /// Get the type of the atoms in a type, looking through all the Seq layers.
+    pub fn atom_type(&self) -> &Type {
+        match self {
+            Type::Seq {n: _, elem_type} => elem_type.atom_type(),
+            _ => self
+        }
+    }
+
+    /// Get the smallest and largest values of a bit or integer atom type.
+    /// Bits are 0 and 1. Other types have no range.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use aetherling::languages::sequence::types::Type;
+    ///
+    /// assert_eq!(Type::Int8.range(), Some((-128, 127)));
+    /// assert_eq!(Type::Unit.range(), None);
+    /// ```
+    pub fn range(&self) -> Option<(i64, i64)> {
+        match self {
+            Type::Bit => Some((0, 1)),
+            Type::Int8 => Some((i8::MIN as i64, i8::MAX as i64)),
+            Type::UInt8 => Some((0, u8::MAX as i64)),
+            Type::Int16 => Some((i16::MIN as i64, i16::MAX as i64)),
+            Type::UInt16 => Some((0, u16::MAX as i64)),
+            Type::Int32 => Some((i32::MIN as i64, i32::MAX as i64)),
+            Type::UInt32 => Some((0, u32::MAX as i64)),
+            _ => None
+        }
+    }
+}
+
+/// Print a type in the Haskell syntax of Aetherling's Sequence types, without the `T` suffixes
+///
+/// # Examples
+///
+/// ```
+/// use aetherling::languages::sequence::types::Type;
+/// let t = Type::Seq {n: 4, elem_type: Box::from(
+///     Type::ATuple {left: Box::from(Type::UInt8), right: Box::from(Type::Bit)})};
+///
+/// assert_eq!(t.to_string(), "Seq 4 (ATuple UInt8 Bit)")
+/// ```
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // nested types with parameters are parenthesized
+        let arg = |t: &Type| match t {
+            Type::ATuple {..} | Type::Seq {..} => format!("({})", t),
+            _ => t.to_string()
+        };
+        match self {
+            Type::ATuple {left, right} => write!(f, "ATuple {} {}", arg(left), arg(right)),
+            Type::Seq {n, elem_type} => write!(f, "Seq {} {}", n, arg(elem_type)),
+            _ => write!(f, "{:?}", self)
+        }
+    }
+}
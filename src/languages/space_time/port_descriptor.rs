@@ -0,0 +1,106 @@
+//! describe the wires of a port with a Space-Time type, for wrapper generators
+//! and documentation that need the port's layout
+use super::types::Type;
+use serde::Serialize;
+
+/// The wires of a port with a Space-Time type. Each clock, the port carries one
+/// packed vector of `port_width` bits, with lane 0 in the least significant bits,
+/// and a one bit valid signal.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PortDescriptor {
+    /// The number of atoms on the port each clock
+    pub lanes: u32,
+    /// The width in bits of each lane's atom
+    pub lane_width: u32,
+    /// The non-tuple fields of each lane's atom, packed like Verilog's `{left, right}`
+    pub fields: Vec<FieldDescriptor>,
+    /// The width in bits of all the lanes together
+    pub port_width: u32,
+    /// The width in bits of the valid signal
+    pub valid_width: u32,
+    /// The number of clocks in one period of the type
+    pub clocks: u32,
+    /// The number of valid clocks in each period
+    pub valid_clocks: u32,
+    /// Whether every clock of the period is valid
+    pub always_valid: bool
+}
+
+/// One non-tuple field of a lane's atom
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FieldDescriptor {
+    /// The field's path through the tuples, such as `right_left`, or empty if the atom isn't a tuple
+    pub path: String,
+    /// The width in bits of the field
+    pub width: u32,
+    /// The offset of the field's least significant bit in its lane
+    pub lsb: u32,
+    /// Whether the field is a two's complement integer
+    pub signed: bool
+}
+
+impl PortDescriptor {
+    /// Describe the wires of a port with a Space-Time type
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use aetherling::languages::space_time::port_descriptor::PortDescriptor;
+    /// use aetherling::languages::space_time::types::Type;
+    /// let d = PortDescriptor::new(&Type::TSeq {n: 2, i: 1, elem_type: Box::from(
+    ///     Type::SSeq {n: 4, elem_type: Box::from(
+    ///         Type::ATuple {left: Box::from(Type::Int8), right: Box::from(Type::Bit)})})});
+    ///
+    /// assert_eq!(d.lanes, 4);
+    /// assert_eq!(d.lane_width, 9);
+    /// assert_eq!(d.port_width, 36);
+    /// assert_eq!((d.fields[0].lsb, d.fields[1].lsb), (1, 0));
+    /// assert_eq!((d.clocks, d.valid_clocks), (3, 2));
+    /// ```
+    pub fn new(st_type: &Type) -> PortDescriptor {
+        let atom_type = st_type.atom_type();
+        let field_types = atom_type.atom_fields();
+        let mut lsb = atom_type.size();
+        let fields = field_types.iter().map(|(suffix, field_type)| {
+            lsb -= field_type.size();
+            FieldDescriptor {
+                path: suffix.trim_start_matches('_').to_string(),
+                width: field_type.size(),
+                lsb,
+                signed: matches!(field_type, Type::Int8 | Type::Int16 | Type::Int32)
+            }
+        }).collect();
+        PortDescriptor {
+            lanes: st_type.atoms_per_valid(),
+            lane_width: atom_type.size(),
+            fields,
+            port_width: st_type.size(),
+            valid_width: 1,
+            clocks: st_type.clocks(),
+            valid_clocks: st_type.valid_clocks(),
+            always_valid: st_type.clocks() == st_type.valid_clocks()
+        }
+    }
+
+    /// Print the descriptor as pretty JSON
+    pub fn to_json(&self) -> String {
+        // serializing plain structs of numbers, strings, and bools can't fail
+        serde_json::to_string_pretty(self).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_of_scalar_port() {
+        let d = PortDescriptor::new(&Type::TSeq {n: 1, i: 0, elem_type: Box::from(Type::Int16)});
+        let json: serde_json::Value = serde_json::from_str(&d.to_json()).unwrap();
+        assert_eq!(json["lanes"], 1);
+        assert_eq!(json["port_width"], 16);
+        assert_eq!(json["always_valid"], true);
+        assert_eq!(json["fields"][0]["path"], "");
+        assert_eq!(json["fields"][0]["signed"], true);
+    }
+}
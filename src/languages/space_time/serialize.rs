@@ -1,5 +1,6 @@
 use super::types::Type;
 use std::io::Cursor;
+use std::error::Error;
 use prost::Message;
 use super::proto::{TypeSerialized, TypeVersion};
 
@@ -16,42 +17,63 @@ use super::proto::{TypeSerialized, TypeVersion};
 /// assert_eq!(loaded_type, Type::UInt8)
 /// ```
 pub fn load_type<T: AsRef<[u8]>>(src: &T) -> Type {
+    try_load_type(src).unwrap()
+}
+
+/// Like `load_type`, but return an error instead of panicking if the buffer
+/// isn't a well-formed protobuf Space-Time type
+///
+/// # Examples
+/// ```
+/// use aetherling::languages::space_time::serialize::{ try_load_type, save_type };
+/// use aetherling::languages::space_time::types::Type;
+/// assert_eq!(try_load_type(&save_type(&Type::Bit)).unwrap(), Type::Bit);
+/// assert!(try_load_type(&[0xffu8, 0xff, 0xff]).is_err());
+/// ```
+pub fn try_load_type<T: AsRef<[u8]>>(src: &T) -> Result<Type, Box<dyn Error>> {
     let serialized_type = TypeSerialized::decode(&mut Cursor::new(src))
-        .unwrap();
+        .map_err(|e| format!("bad protobuf type: {}", e))?;
     deserialize_type(&serialized_type)
 }
 
-fn deserialize_type(TypeSerialized {v, n, i, children} : &TypeSerialized) -> Type {
+fn child(children: &[TypeSerialized], idx: usize) -> Result<Type, Box<dyn Error>> {
+    match children.get(idx) {
+        Some(c) => deserialize_type(c),
+        None => Err(format!("protobuf type is missing child {}", idx).into())
+    }
+}
+
+fn deserialize_type(TypeSerialized {v, n, i, children} : &TypeSerialized) -> Result<Type, Box<dyn Error>> {
     // can't convert int to enum in match statement easily when using prost
     if *v == TypeVersion::Unit as i32 {
-        Type::Unit
+        Ok(Type::Unit)
     } else if *v == TypeVersion::Bit as i32 {
-        Type::Bit
+        Ok(Type::Bit)
     } else if *v == TypeVersion::Int8 as i32 {
-        Type::Int8
+        Ok(Type::Int8)
     } else if *v == TypeVersion::UInt8 as i32 {
-        Type::UInt8
+        Ok(Type::UInt8)
     } else if *v == TypeVersion::Int16 as i32 {
-        Type::Int16
+        Ok(Type::Int16)
     } else if *v == TypeVersion::UInt16 as i32 {
-        Type::UInt16
+        Ok(Type::UInt16)
     } else if *v == TypeVersion::Int32 as i32 {
-        Type::Int32
+        Ok(Type::Int32)
     } else if *v == TypeVersion::UInt32 as i32 {
-        Type::UInt32
+        Ok(Type::UInt32)
     } else if *v == TypeVersion::ATuple as i32 {
-        let left = deserialize_type(&children[0]);
-        let right = deserialize_type(&children[1]);
-        Type::ATuple { left: Box::new(left), right: Box::new(right) }
+        let left = child(children, 0)?;
+        let right = child(children, 1)?;
+        Ok(Type::ATuple { left: Box::new(left), right: Box::new(right) })
     } else if *v == TypeVersion::STuple as i32 {
-        let elem_type = deserialize_type(&children[0]);
-        Type::STuple { n: *n, elem_type: Box::new(elem_type) }
+        let elem_type = child(children, 0)?;
+        Ok(Type::STuple { n: *n, elem_type: Box::new(elem_type) })
     } else if *v == TypeVersion::SSeq as i32 {
-        let elem_type = deserialize_type(&children[0]);
-        Type::SSeq { n: *n, elem_type: Box::new(elem_type) }
+        let elem_type = child(children, 0)?;
+        Ok(Type::SSeq { n: *n, elem_type: Box::new(elem_type) })
     } else {
-        let elem_type = deserialize_type(&children[0]);
-        Type::TSeq { n: *n, i: *i, elem_type: Box::new(elem_type) }
+        let elem_type = child(children, 0)?;
+        Ok(Type::TSeq { n: *n, i: *i, elem_type: Box::new(elem_type) })
     }
 }
 
@@ -68,9 +90,8 @@ fn deserialize_type(TypeSerialized {v, n, i, children} : &TypeSerialized) -> Typ
 /// assert_eq!(loaded_type, Type::Bit)
 /// ```
 pub fn save_type(t: &Type) -> Vec<u8> {
-    let mut buffer = Vec::new();
-    buffer.reserve(buffer.encoded_len());
     let proto_type = serialize_type(t);
+    let mut buffer = Vec::with_capacity(proto_type.encoded_len());
     // Unwrap is safe, since we have reserved sufficient capacity in the vector.
     proto_type.encode(&mut buffer).unwrap();
     buffer
@@ -94,22 +115,34 @@ fn serialize_type(t: &Type) -> TypeSerialized {
             TypeSerialized {v: TypeVersion::Int32 as i32, n: 0, i: 0, children: Vec::new()},
         Type::UInt32 =>
             TypeSerialized {v: TypeVersion::UInt32 as i32, n: 0, i: 0, children: Vec::new()},
-        Type::ATuple { .. } =>
-            TypeSerialized {v: TypeVersion::ATuple as i32, n: 0, i: 0, children: Vec::new()},
+        Type::ATuple { left, right } => {
+            let children = vec![serialize_type(left), serialize_type(right)];
+            TypeSerialized {v: TypeVersion::ATuple as i32, n: 0, i: 0, children}
+        }
         Type::STuple { n, elem_type} => {
-            let mut children = Vec::new();
-            children.push(serialize_type(elem_type));
+            let children = vec![serialize_type(elem_type)];
             TypeSerialized {v: TypeVersion::STuple as i32, n: *n, i: 0, children}
         }
         Type::SSeq { n, elem_type} => {
-            let mut children = Vec::new();
-            children.push(serialize_type(elem_type));
+            let children = vec![serialize_type(elem_type)];
             TypeSerialized {v: TypeVersion::SSeq as i32, n: *n, i: 0, children}
         }
         Type::TSeq { n, i, elem_type} => {
-            let mut children = Vec::new();
-            children.push(serialize_type(elem_type));
-            TypeSerialized {v: TypeVersion::SSeq as i32, n: *n, i: *i, children}
+            let children = vec![serialize_type(elem_type)];
+            TypeSerialized {v: TypeVersion::TSeq as i32, n: *n, i: *i, children}
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_load_tseq_of_atuples() {
+        let t = Type::TSeq {n: 3, i: 1, elem_type: Box::new(
+            Type::SSeq {n: 2, elem_type: Box::new(
+                Type::ATuple {left: Box::new(Type::Int8), right: Box::new(Type::Bit)})})};
+        assert_eq!(load_type(&save_type(&t)), t)
+    }
+}
@@ -1,8 +1,12 @@
 //! The deep embedding of Aetherling's Space-Time types in Rust
 use super::super::util::*;
+use super::super::sequence::atoms::Atom;
+use super::super::sequence::types::Type as SeqType;
+use serde::{Deserialize, Serialize};
+use std::fmt;
 
 /// A type of a Space-Time IR expression's input or output.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Type {
     Unit,
     Bit,
@@ -19,6 +23,66 @@ pub enum Type {
 }
 
 impl Type {
+    /// Get the Space-Time type that fully parallelizes a Sequence type,
+    /// with every Seq as an SSeq. The atoms are in the same order in both types.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use aetherling::languages::space_time::types::Type;
+    /// use aetherling::languages::sequence::types::Type as SeqType;
+    /// let t = Type::from_seq_type(&SeqType::Seq {n: 2, elem_type: Box::from(SeqType::UInt8)});
+    ///
+    /// assert_eq!(t, Type::SSeq {n: 2, elem_type: Box::from(Type::UInt8)})
+    /// ```
+    pub fn from_seq_type(seq_type: &SeqType) -> Type {
+        match seq_type {
+            SeqType::Unit => Type::Unit,
+            SeqType::Bit => Type::Bit,
+            SeqType::Int8 => Type::Int8,
+            SeqType::UInt8 => Type::UInt8,
+            SeqType::Int16 => Type::Int16,
+            SeqType::UInt16 => Type::UInt16,
+            SeqType::Int32 => Type::Int32,
+            SeqType::UInt32 => Type::UInt32,
+            SeqType::ATuple {left, right} =>
+                Type::ATuple {left: Box::new(Type::from_seq_type(left)),
+                              right: Box::new(Type::from_seq_type(right))},
+            SeqType::Seq {n, elem_type} =>
+                Type::SSeq {n: *n, elem_type: Box::new(Type::from_seq_type(elem_type))}
+        }
+    }
+
+    /// Get the Sequence type of a Space-Time type's values, erasing how they're spread
+    /// over space and time. Every STuple, SSeq, and TSeq is a Seq.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use aetherling::languages::space_time::types::Type;
+    /// use aetherling::languages::sequence::types::Type as SeqType;
+    /// let t = Type::TSeq {n: 2, i: 1, elem_type: Box::from(Type::SSeq {n: 3, elem_type: Box::from(Type::Bit)})};
+    ///
+    /// assert_eq!(t.to_seq_type(), SeqType::Seq {n: 2, elem_type: Box::from(
+    ///     SeqType::Seq {n: 3, elem_type: Box::from(SeqType::Bit)})})
+    /// ```
+    pub fn to_seq_type(&self) -> SeqType {
+        match self {
+            Type::Unit => SeqType::Unit,
+            Type::Bit => SeqType::Bit,
+            Type::Int8 => SeqType::Int8,
+            Type::UInt8 => SeqType::UInt8,
+            Type::Int16 => SeqType::Int16,
+            Type::UInt16 => SeqType::UInt16,
+            Type::Int32 => SeqType::Int32,
+            Type::UInt32 => SeqType::UInt32,
+            Type::ATuple {left, right} =>
+                SeqType::ATuple {left: Box::new(left.to_seq_type()), right: Box::new(right.to_seq_type())},
+            Type::STuple {n, elem_type} | Type::SSeq {n, elem_type} | Type::TSeq {n, i: _, elem_type} =>
+                SeqType::Seq {n: *n, elem_type: Box::new(elem_type.to_seq_type())}
+        }
+    }
+
     /// Compute the size in bits of a type.
     ///
     /// # Examples
@@ -131,6 +195,37 @@ impl Type {
         }
     }
 
+    /// Compute the number of clock cycles for a type, or `None` if it doesn't fit in a u32
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use aetherling::languages::space_time::types::Type;
+    /// let t = Type::TSeq {n:4, i:2, elem_type:Box::from(Type::UInt8)};
+    /// let huge = Type::TSeq {n:1, i:u32::MAX, elem_type:Box::from(Type::UInt8)};
+    ///
+    /// assert_eq!(t.checked_clocks(), Some(6));
+    /// assert_eq!(huge.checked_clocks(), None);
+    /// ```
+    pub fn checked_clocks(&self) -> Option<u32> {
+        match self {
+            Type::STuple { n: _, elem_type } => elem_type.checked_clocks(),
+            Type::SSeq { n: _, elem_type } => elem_type.checked_clocks(),
+            Type::TSeq { n, i, elem_type } => n.checked_add(*i)?.checked_mul(elem_type.checked_clocks()?),
+            _ => Some(1)
+        }
+    }
+
+    /// Compute the number of atoms per valid clock, or `None` if it doesn't fit in a u32
+    pub fn checked_atoms_per_valid(&self) -> Option<u32> {
+        match self {
+            Type::STuple { n, elem_type } => n.checked_mul(elem_type.checked_atoms_per_valid()?),
+            Type::SSeq { n, elem_type } => n.checked_mul(elem_type.checked_atoms_per_valid()?),
+            Type::TSeq { n: _, i: _, elem_type } => elem_type.checked_atoms_per_valid(),
+            _ => Some(1)
+        }
+    }
+
     pub fn def_atom_str(&self) -> String {
         match self {
             Type::Unit => "()".to_string(),
@@ -147,44 +242,184 @@ impl Type {
             Type::TSeq {n: _, i: _, elem_type} => elem_type.def_atom_str()
         }
     }
+
+    /// Get the default atom that fills a type's invalid clocks.
+    pub fn def_atom(&self) -> Atom {
+        match self {
+            Type::Unit => Atom::Unit,
+            Type::Bit => Atom::Bit(false),
+            Type::Int8 => Atom::Int(0),
+            Type::UInt8 => Atom::UInt(0),
+            Type::Int16 => Atom::Int(0),
+            Type::UInt16 => Atom::UInt(0),
+            Type::Int32 => Atom::Int(0),
+            Type::UInt32 => Atom::UInt(0),
+            Type::ATuple {left, right } => Atom::Tuple(Box::new(left.def_atom()), Box::new(right.def_atom())),
+            Type::STuple {n: _, elem_type} => elem_type.def_atom(),
+            Type::SSeq {n: _, elem_type} => elem_type.def_atom(),
+            Type::TSeq {n: _, i: _, elem_type} => elem_type.def_atom()
+        }
+    }
+
+    /// Get the type of the atoms in a type, looking through all the
+    /// STuple, SSeq, and TSeq layers.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use aetherling::languages::space_time::types::Type;
+    /// let t = Type::TSeq {n:4, i:2, elem_type:Box::from(
+    ///     Type::SSeq {n:2, elem_type:Box::from(Type::Int16)})};
+    ///
+    /// assert_eq!(t.atom_type(), &Type::Int16)
+    /// ```
+    pub fn atom_type(&self) -> &Type {
+        match self {
+            Type::STuple {n: _, elem_type} => elem_type.atom_type(),
+            Type::SSeq {n: _, elem_type} => elem_type.atom_type(),
+            Type::TSeq {n: _, i: _, elem_type} => elem_type.atom_type(),
+            _ => self
+        }
+    }
+
+    /// Get a type with the same STuple, SSeq, and TSeq layers but a different atom type
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use aetherling::languages::space_time::types::Type;
+    /// let t = Type::TSeq {n:4, i:2, elem_type:Box::from(Type::UInt8)};
+    ///
+    /// assert_eq!(t.replace_atom_type(&Type::Bit), Type::TSeq {n:4, i:2, elem_type:Box::from(Type::Bit)})
+    /// ```
+    pub fn replace_atom_type(&self, atom_type: &Type) -> Type {
+        match self {
+            Type::STuple {n, elem_type} =>
+                Type::STuple {n: *n, elem_type: Box::new(elem_type.replace_atom_type(atom_type))},
+            Type::SSeq {n, elem_type} =>
+                Type::SSeq {n: *n, elem_type: Box::new(elem_type.replace_atom_type(atom_type))},
+            Type::TSeq {n, i, elem_type} =>
+                Type::TSeq {n: *n, i: *i, elem_type: Box::new(elem_type.replace_atom_type(atom_type))},
+            _ => atom_type.clone()
+        }
+    }
+
+    /// Get the non-tuple fields of an atom type, left to right, along with
+    /// the suffix that names each field's path through the tuples.
+    /// The fields are in the same order as `Atom::leaves`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use aetherling::languages::space_time::types::Type;
+    /// let t = Type::ATuple {left: Box::from(Type::UInt8), right: Box::from(
+    ///     Type::ATuple {left: Box::from(Type::Bit), right: Box::from(Type::Int16)})};
+    ///
+    /// assert_eq!(t.atom_fields(), vec!((String::from("_left"), &Type::UInt8),
+    ///                                  (String::from("_right_left"), &Type::Bit),
+    ///                                  (String::from("_right_right"), &Type::Int16)));
+    /// assert_eq!(Type::Bit.atom_fields(), vec!((String::new(), &Type::Bit)));
+    /// ```
+    pub fn atom_fields(&self) -> Vec<(String, &Type)> {
+        match self {
+            Type::ATuple {left, right} => {
+                let mut fields: Vec<(String, &Type)> = left.atom_fields().into_iter()
+                    .map(|(suffix, t)| (format!("_left{}", suffix), t)).collect();
+                fields.extend(right.atom_fields().into_iter()
+                    .map(|(suffix, t)| (format!("_right{}", suffix), t)));
+                fields
+            }
+            _ => vec!((String::new(), self))
+        }
+    }
+
+    /// Normalize a type so that types with the same order of valid and invalid clocks
+    /// and the same lanes are equal. STuples become SSeqs, layers of length 1 are removed,
+    /// and nested SSeqs, and nested TSeqs whose inner TSeq has no invalid clocks, are merged.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use aetherling::languages::space_time::types::Type;
+    /// let t = Type::TSeq {n: 2, i: 1, elem_type: Box::from(
+    ///     Type::TSeq {n: 3, i: 0, elem_type: Box::from(
+    ///         Type::SSeq {n: 1, elem_type: Box::from(
+    ///             Type::STuple {n: 2, elem_type: Box::from(Type::UInt8)})})})};
+    ///
+    /// assert_eq!(t.normalize(), Type::TSeq {n: 6, i: 3, elem_type: Box::from(
+    ///     Type::SSeq {n: 2, elem_type: Box::from(Type::UInt8)})})
+    /// ```
+    pub fn normalize(&self) -> Type {
+        self.replace_stuple_with_sseq().strip_empty_layers().merge_layers()
+    }
+
+    fn replace_stuple_with_sseq(&self) -> Type {
+        match self {
+            Type::STuple {n, elem_type} | Type::SSeq {n, elem_type} =>
+                Type::SSeq {n: *n, elem_type: Box::new(elem_type.replace_stuple_with_sseq())},
+            Type::TSeq {n, i, elem_type} =>
+                Type::TSeq {n: *n, i: *i, elem_type: Box::new(elem_type.replace_stuple_with_sseq())},
+            _ => self.clone()
+        }
+    }
+
+    fn strip_empty_layers(&self) -> Type {
+        match self {
+            Type::SSeq {n: 1, elem_type} | Type::STuple {n: 1, elem_type} |
+            Type::TSeq {n: 1, i: 0, elem_type} => elem_type.strip_empty_layers(),
+            Type::SSeq {n, elem_type} =>
+                Type::SSeq {n: *n, elem_type: Box::new(elem_type.strip_empty_layers())},
+            Type::STuple {n, elem_type} =>
+                Type::STuple {n: *n, elem_type: Box::new(elem_type.strip_empty_layers())},
+            Type::TSeq {n, i, elem_type} =>
+                Type::TSeq {n: *n, i: *i, elem_type: Box::new(elem_type.strip_empty_layers())},
+            _ => self.clone()
+        }
+    }
+
+    /// Merge layers where it doesn't change the order of valid and invalid clocks
+    fn merge_layers(&self) -> Type {
+        match self {
+            Type::SSeq {n: no, elem_type} | Type::STuple {n: no, elem_type} => match elem_type.as_ref() {
+                Type::SSeq {n: ni, elem_type: inner} | Type::STuple {n: ni, elem_type: inner} =>
+                    Type::SSeq {n: no * ni, elem_type: inner.clone()}.merge_layers(),
+                _ => Type::SSeq {n: *no, elem_type: Box::new(elem_type.merge_layers())}
+            },
+            Type::TSeq {n: no, i: io, elem_type} => match elem_type.as_ref() {
+                Type::TSeq {n: ni, i: 0, elem_type: inner} =>
+                    Type::TSeq {n: no * ni, i: io * ni, elem_type: inner.clone()}.merge_layers(),
+                _ => Type::TSeq {n: *no, i: *io, elem_type: Box::new(elem_type.merge_layers())}
+            },
+            _ => self.clone()
+        }
+    }
 }
 
-/*
-
-normalize_type = merge_layers . strip_empty_layers . replace_stuple_with_sseq
-
-strip_empty_layers :: AST_Type -> AST_Type
-strip_empty_layers (SSeqT 1 t) = strip_empty_layers t
-strip_empty_layers (SSeqT n t) = SSeqT n $ strip_empty_layers t
-strip_empty_layers (TSeqT 1 0 t) = strip_empty_layers t
-strip_empty_layers (TSeqT n i t) = TSeqT n i $ strip_empty_layers t
-strip_empty_layers (STupleT 1 t) = strip_empty_layers t
-strip_empty_layers (STupleT n t) = STupleT n $ strip_empty_layers t
-strip_empty_layers t = t
-
--- merge types where it doesn't change ordering of valids/invalids
-merge_layers :: AST_Type -> AST_Type
-merge_layers (SSeqT no (SSeqT ni t)) = merge_layers $ SSeqT (no*ni) t
-merge_layers (SSeqT no (STupleT ni t)) = merge_layers $ SSeqT (no*ni) t
-merge_layers (TSeqT no io (TSeqT ni 0 t)) = merge_layers $ TSeqT (no*ni) (io*ni) t
-merge_layers (STupleT no (STupleT ni t)) = merge_layers $ SSeqT (no*ni) t
-merge_layers (STupleT no (SSeqT ni t)) = merge_layers $ SSeqT (no*ni) t
-merge_layers (SSeqT no t) = SSeqT no $ merge_layers t
-merge_layers (TSeqT no io t) = TSeqT no io $ merge_layers t
-merge_layers (STupleT no t) = SSeqT no $ merge_layers t
-merge_layers t = t
-
-replace_stuple_with_sseq :: AST_Type -> AST_Type
-replace_stuple_with_sseq (STupleT n t) = SSeqT n $ replace_stuple_with_sseq t
-replace_stuple_with_sseq (SSeqT n t) = SSeqT n $ replace_stuple_with_sseq t
-replace_stuple_with_sseq (TSeqT n i t) = TSeqT n i $ replace_stuple_with_sseq t
-replace_stuple_with_sseq t = t
-
-diff_types :: AST_Type -> AST_Type -> Maybe AST_Type
-diff_types a b | a == b = Nothing
-diff_types (SSeqT na a) (SSeqT nb b) | na == nb = diff_types a b
-diff_types (TSeqT na ia a) (TSeqT nb ib b) | (na == nb) && (ia == ib) = diff_types a b
-diff_types (STupleT na a) (STupleT nb b) | na == nb = diff_types a b
-diff_types a b = Just a
-
-*/
\ No newline at end of file
+/// Print a type in the Haskell syntax of Aetherling's Space-Time types, without the `T` suffixes
+///
+/// # Examples
+///
+/// ```
+/// use aetherling::languages::space_time::types::Type;
+/// let t = Type::TSeq {n: 2, i: 1, elem_type: Box::from(
+///     Type::SSeq {n: 2, elem_type: Box::from(
+///         Type::ATuple {left: Box::from(Type::Int8), right: Box::from(Type::Bit)})})};
+///
+/// assert_eq!(t.to_string(), "TSeq 2 1 (SSeq 2 (ATuple Int8 Bit))")
+/// ```
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // nested types with parameters are parenthesized
+        let arg = |t: &Type| match t {
+            Type::ATuple {..} | Type::STuple {..} | Type::SSeq {..} | Type::TSeq {..} => format!("({})", t),
+            _ => t.to_string()
+        };
+        match self {
+            Type::ATuple {left, right} => write!(f, "ATuple {} {}", arg(left), arg(right)),
+            Type::STuple {n, elem_type} => write!(f, "STuple {} {}", n, arg(elem_type)),
+            Type::SSeq {n, elem_type} => write!(f, "SSeq {} {}", n, arg(elem_type)),
+            Type::TSeq {n, i, elem_type} => write!(f, "TSeq {} {} {}", n, i, arg(elem_type)),
+            _ => write!(f, "{:?}", self)
+        }
+    }
+}
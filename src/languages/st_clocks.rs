@@ -0,0 +1,150 @@
+//! iterate over a Space-Time value clock by clock, without building the whole
+//! grid of atoms, for code that consumes streams directly
+use super::sequence::atoms::Atom;
+use super::sequence::serialize_values::SerializableSeqValue;
+use super::space_time::types::Type;
+
+/// The clocks of a Space-Time value, made lazily from a Sequence value's atoms
+pub struct STClocks {
+    st_type: Type,
+    flat_atoms: Vec<Atom>,
+    def_atom: Atom,
+    seq_idxs: Vec<usize>,
+    lanes: Vec<Atom>,
+    clock: u32
+}
+
+impl STClocks {
+    /// Iterate over the clocks of a Sequence value converted to a Space-Time type
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use aetherling::languages::st_clocks::STClocks;
+    /// use aetherling::languages::sequence::atoms::Atom;
+    /// use aetherling::languages::space_time::types::Type;
+    /// let mut clocks = STClocks::new(vec!(1, 3, 2, 4), Type::SSeq {n: 2, elem_type: Box::from(
+    ///     Type::TSeq {n: 2, i: 1, elem_type: Box::from(Type::UInt8)})});
+    ///
+    /// assert_eq!(clocks.next_clock(), Some((true, &[Atom::Int(1), Atom::Int(2)][..])));
+    /// assert_eq!(clocks.next_clock(), Some((true, &[Atom::Int(3), Atom::Int(4)][..])));
+    /// assert_eq!(clocks.next_clock(), Some((false, &[Atom::UInt(0), Atom::UInt(0)][..])));
+    /// assert_eq!(clocks.next_clock(), None);
+    /// ```
+    pub fn new<T: SerializableSeqValue>(seq_val: T, st_type: Type) -> STClocks {
+        let mut flat_atoms: Vec<Atom> = Vec::new();
+        seq_val.convert_to_flat_atoms(&mut flat_atoms);
+        let lanes = st_type.atoms_per_valid() as usize;
+        STClocks {
+            def_atom: st_type.def_atom(),
+            st_type,
+            flat_atoms,
+            seq_idxs: vec![0; lanes],
+            lanes: Vec::with_capacity(lanes),
+            clock: 0
+        }
+    }
+
+    /// Get the next clock's valid and the atoms in its lanes.
+    /// The lanes of invalid clocks hold the Space-Time type's default atom.
+    /// Unlike `next`, this reuses one buffer for every clock's lanes.
+    pub fn next_clock(&mut self) -> Option<(bool, &[Atom])> {
+        if self.clock >= self.st_type.clocks() {
+            return None;
+        }
+        let valid = seq_idxs_of_st_clock(&self.st_type, self.clock, &mut self.seq_idxs);
+        self.clock += 1;
+        self.lanes.clear();
+        if valid {
+            let flat_atoms = &self.flat_atoms;
+            self.lanes.extend(self.seq_idxs.iter().map(|idx| flat_atoms[*idx].clone()));
+        } else {
+            self.lanes.resize(self.seq_idxs.len(), self.def_atom.clone());
+        }
+        Some((valid, &self.lanes))
+    }
+}
+
+impl Iterator for STClocks {
+    type Item = (bool, Vec<Atom>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_clock().map(|(valid, lanes)| (valid, lanes.to_vec()))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = (self.st_type.clocks() - self.clock) as usize;
+        (remaining, Some(remaining))
+    }
+}
+
+/// Find the index in a Sequence value's flat atom list of the atom in each lane of a
+/// Space-Time type's clock. `seq_idxs` must have one element per lane.
+/// Returns whether the clock is valid. If it isn't, `seq_idxs` is unchanged.
+///
+/// # Examples
+///
+/// ```
+/// use aetherling::languages::st_clocks::seq_idxs_of_st_clock;
+/// use aetherling::languages::space_time::types::Type;
+/// let t = Type::TSeq {n: 3, i: 0, elem_type: Box::from(
+///     Type::SSeq {n: 2, elem_type: Box::from(Type::UInt8)})};
+/// let mut seq_idxs = vec!(0, 0);
+///
+/// assert!(seq_idxs_of_st_clock(&t, 1, &mut seq_idxs));
+/// assert_eq!(seq_idxs, vec!(2, 3));
+/// ```
+pub fn seq_idxs_of_st_clock(st_type: &Type, clock: u32, seq_idxs: &mut [usize]) -> bool {
+    set_seq_idxs_of_st_clock(st_type, clock, 0, 0, seq_idxs)
+}
+
+fn set_seq_idxs_of_st_clock(st_type: &Type, clock: u32, cur_space: u32, cur_idx: u32,
+                            seq_idxs: &mut [usize]) -> bool {
+    match st_type {
+        Type::STuple { n, elem_type } | Type::SSeq { n, elem_type } => {
+            let element_width = elem_type.atoms_per_valid();
+            let element_valid_time = elem_type.valid_clocks();
+            // every element has the same type, so they are all valid or all invalid
+            (0..*n).all(|i| set_seq_idxs_of_st_clock(elem_type, clock, cur_space + i * element_width,
+                                                     cur_idx + i * element_width * element_valid_time,
+                                                     seq_idxs))
+        }
+        Type::TSeq { n, i: _, elem_type } => {
+            let element_time = elem_type.clocks();
+            let element = clock / element_time;
+            element < *n &&
+                set_seq_idxs_of_st_clock(elem_type, clock % element_time, cur_space,
+                                         cur_idx + element * elem_type.atoms_per_valid() * elem_type.valid_clocks(),
+                                         seq_idxs)
+        }
+        _ => {
+            seq_idxs[cur_space as usize] = cur_idx as usize;
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::seq_value_to_st_value_and_valid_strings::convert_seq_val_to_st_atoms_and_valids;
+
+    #[test]
+    fn test_st_clocks_match_atoms_and_valids() {
+        let st_types = vec!(
+            Type::TSeq {n: 2, i: 1, elem_type: Box::from(
+                Type::SSeq {n: 2, elem_type: Box::from(
+                    Type::TSeq {n: 3, i: 0, elem_type: Box::from(Type::UInt8)})})},
+            Type::SSeq {n: 3, elem_type: Box::from(
+                Type::TSeq {n: 2, i: 2, elem_type: Box::from(
+                    Type::STuple {n: 2, elem_type: Box::from(Type::UInt8)})})}
+        );
+        for st_type in st_types {
+            let seq_val: Vec<i32> = (0..12).collect();
+            let st_vals = convert_seq_val_to_st_atoms_and_valids(seq_val.clone(), &st_type);
+            let (valids, atoms): (Vec<bool>, Vec<Vec<Atom>>) = STClocks::new(seq_val, st_type).unzip();
+            assert_eq!(valids, st_vals.valids);
+            assert_eq!(atoms, st_vals.atoms);
+        }
+    }
+}
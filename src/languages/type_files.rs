@@ -0,0 +1,252 @@
+//! load Sequence and Space-Time types from protobuf, JSON, or textual files,
+//! detecting the file's format from its contents
+use super::sequence::types::Type as SeqType;
+use super::sequence::serialize_types;
+use super::space_time::types::Type;
+use super::space_time::serialize;
+use std::error::Error;
+
+/// A Sequence or Space-Time type from a file
+#[derive(Debug, Clone, PartialEq)]
+pub enum AnyType {
+    Seq(SeqType),
+    SpaceTime(Type)
+}
+
+impl AnyType {
+    /// Get the Space-Time type, with a Sequence type fully parallelized
+    pub fn to_st_type(&self) -> Type {
+        match self {
+            AnyType::Seq(seq_type) => Type::from_seq_type(seq_type),
+            AnyType::SpaceTime(st_type) => st_type.clone()
+        }
+    }
+
+    /// Get the Sequence type, with a Space-Time type erased to its Sequence type
+    pub fn to_seq_type(&self) -> SeqType {
+        match self {
+            AnyType::Seq(seq_type) => seq_type.clone(),
+            AnyType::SpaceTime(st_type) => st_type.to_seq_type()
+        }
+    }
+}
+
+/// The language of a type file. Protobuf files don't say which language they're in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TypeLanguage {
+    Seq,
+    SpaceTime
+}
+
+/// Load a type from a protobuf, JSON, or textual file.
+/// JSON files are serde's form of the type, such as `{"SSeq": {"n": 2, "elem_type": "UInt8"}}`.
+/// Textual files are Aetherling's Haskell syntax, such as `TSeq 2 1 (SSeq 2 UInt8)`,
+/// with or without the `T` suffixes. JSON files are Space-Time types if they parse as one
+/// and Sequence types otherwise. Textual files are Sequence types if they have a `Seq`.
+/// Protobuf files are in `protobuf_language`.
+///
+/// # Examples
+///
+/// ```
+/// use aetherling::languages::type_files::{load_any_type, AnyType, TypeLanguage};
+/// use aetherling::languages::space_time::types::Type;
+/// use aetherling::languages::sequence::types::Type as SeqType;
+/// let st_type = Type::TSeq {n: 2, i: 1, elem_type: Box::from(Type::UInt8)};
+///
+/// assert_eq!(load_any_type(b"TSeqT 2 1 UInt8T", TypeLanguage::Seq).unwrap(),
+///            AnyType::SpaceTime(st_type.clone()));
+/// assert_eq!(load_any_type(br#"{"TSeq": {"n": 2, "i": 1, "elem_type": "UInt8"}}"#, TypeLanguage::Seq).unwrap(),
+///            AnyType::SpaceTime(st_type));
+/// assert_eq!(load_any_type(b"Seq 3 Bit", TypeLanguage::SpaceTime).unwrap(),
+///            AnyType::Seq(SeqType::Seq {n: 3, elem_type: Box::from(SeqType::Bit)}));
+/// ```
+pub fn load_any_type(src: &[u8], protobuf_language: TypeLanguage) -> Result<AnyType, Box<dyn Error>> {
+    let text = std::str::from_utf8(src).ok().map(str::trim_start);
+    match text.and_then(|t| t.chars().next()) {
+        Some('{') | Some('"') => {
+            let text = text.unwrap();
+            serde_json::from_str(text).map(AnyType::SpaceTime)
+                .or_else(|_| serde_json::from_str(text).map(AnyType::Seq))
+                .map_err(|e| format!("not a JSON Space-Time or Sequence type: {}", e).into())
+        }
+        Some(c) if c.is_ascii_alphabetic() || c == '(' => {
+            let term = parse_term(text.unwrap())?;
+            if mentions_seq(&term) {
+                term_to_seq_type(&term).map(AnyType::Seq)
+            } else {
+                term_to_st_type(&term).map(AnyType::SpaceTime)
+            }
+        }
+        _ => match protobuf_language {
+            TypeLanguage::Seq => serialize_types::try_load_type(&src).map(AnyType::Seq),
+            TypeLanguage::SpaceTime => serialize::try_load_type(&src).map(AnyType::SpaceTime)
+        }
+    }
+}
+
+/// A type in the textual syntax, before checking which language it's in
+#[derive(Debug, Clone, PartialEq)]
+enum Term {
+    Num(u32),
+    Named(String, Vec<Term>)
+}
+
+fn tokenize(text: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut cur = String::new();
+    for c in text.chars() {
+        if c.is_whitespace() || c == '(' || c == ')' {
+            if !cur.is_empty() {
+                tokens.push(std::mem::take(&mut cur));
+            }
+            if !c.is_whitespace() {
+                tokens.push(c.to_string());
+            }
+        } else {
+            cur.push(c);
+        }
+    }
+    if !cur.is_empty() {
+        tokens.push(cur);
+    }
+    tokens
+}
+
+fn parse_term(text: &str) -> Result<Term, Box<dyn Error>> {
+    let tokens = tokenize(text);
+    let mut pos = 0;
+    let term = parse_applied(&tokens, &mut pos)?;
+    match tokens.get(pos) {
+        None => Ok(term),
+        Some(t) => Err(format!("unexpected {} in type", t).into())
+    }
+}
+
+/// Parse a name applied to its arguments, or a parenthesized type
+fn parse_applied(tokens: &[String], pos: &mut usize) -> Result<Term, Box<dyn Error>> {
+    match parse_arg(tokens, pos)? {
+        Term::Named(name, _) => {
+            let mut args = Vec::new();
+            while *pos < tokens.len() && tokens[*pos] != ")" {
+                args.push(parse_arg(tokens, pos)?);
+            }
+            Ok(Term::Named(name, args))
+        }
+        num => Ok(num)
+    }
+}
+
+/// Parse a number, a name without arguments, or a parenthesized type
+fn parse_arg(tokens: &[String], pos: &mut usize) -> Result<Term, Box<dyn Error>> {
+    let token = tokens.get(*pos).ok_or("type ended early")?;
+    *pos += 1;
+    if token == "(" {
+        let term = parse_applied(tokens, pos)?;
+        match tokens.get(*pos) {
+            Some(t) if t == ")" => {
+                *pos += 1;
+                Ok(term)
+            }
+            _ => Err("missing ) in type".into())
+        }
+    } else if token == ")" {
+        Err("unexpected ) in type".into())
+    } else if let Ok(n) = token.parse() {
+        Ok(Term::Num(n))
+    } else {
+        // Haskell's constructors end in T, such as SSeqT
+        let name = token.strip_suffix('T').filter(|n| !n.is_empty()).unwrap_or(token);
+        Ok(Term::Named(name.to_string(), Vec::new()))
+    }
+}
+
+/// Check if a textual type has a Sequence language `Seq`
+fn mentions_seq(term: &Term) -> bool {
+    match term {
+        Term::Named(name, args) => name == "Seq" || args.iter().any(mentions_seq),
+        Term::Num(_) => false
+    }
+}
+
+fn arity_error(name: &str, args: &str) -> Box<dyn Error> {
+    format!("{} takes {}", name, args).into()
+}
+
+fn term_to_st_type(term: &Term) -> Result<Type, Box<dyn Error>> {
+    let (name, args) = match term {
+        Term::Named(name, args) => (name.as_str(), args.as_slice()),
+        Term::Num(n) => return Err(format!("expected a type but got {}", n).into())
+    };
+    let elem = |t: &Term| term_to_st_type(t).map(Box::new);
+    match (name, args) {
+        ("Unit", []) => Ok(Type::Unit),
+        ("Bit", []) => Ok(Type::Bit),
+        ("Int8", []) => Ok(Type::Int8),
+        ("UInt8", []) => Ok(Type::UInt8),
+        ("Int16", []) => Ok(Type::Int16),
+        ("UInt16", []) => Ok(Type::UInt16),
+        ("Int32", []) => Ok(Type::Int32),
+        ("UInt32", []) => Ok(Type::UInt32),
+        ("ATuple", [left, right]) => Ok(Type::ATuple {left: elem(left)?, right: elem(right)?}),
+        ("ATuple", _) => Err(arity_error(name, "two types")),
+        ("STuple", [Term::Num(n), t]) => Ok(Type::STuple {n: *n, elem_type: elem(t)?}),
+        ("SSeq", [Term::Num(n), t]) => Ok(Type::SSeq {n: *n, elem_type: elem(t)?}),
+        ("STuple", _) | ("SSeq", _) => Err(arity_error(name, "a length and a type")),
+        ("TSeq", [Term::Num(n), Term::Num(i), t]) => Ok(Type::TSeq {n: *n, i: *i, elem_type: elem(t)?}),
+        ("TSeq", _) => Err(arity_error(name, "a length, a number of invalid clocks, and a type")),
+        _ => Err(format!("{} isn't a Space-Time type", name).into())
+    }
+}
+
+fn term_to_seq_type(term: &Term) -> Result<SeqType, Box<dyn Error>> {
+    let (name, args) = match term {
+        Term::Named(name, args) => (name.as_str(), args.as_slice()),
+        Term::Num(n) => return Err(format!("expected a type but got {}", n).into())
+    };
+    let elem = |t: &Term| term_to_seq_type(t).map(Box::new);
+    match (name, args) {
+        ("Unit", []) => Ok(SeqType::Unit),
+        ("Bit", []) => Ok(SeqType::Bit),
+        ("Int8", []) => Ok(SeqType::Int8),
+        ("UInt8", []) => Ok(SeqType::UInt8),
+        ("Int16", []) => Ok(SeqType::Int16),
+        ("UInt16", []) => Ok(SeqType::UInt16),
+        ("Int32", []) => Ok(SeqType::Int32),
+        ("UInt32", []) => Ok(SeqType::UInt32),
+        ("ATuple", [left, right]) => Ok(SeqType::ATuple {left: elem(left)?, right: elem(right)?}),
+        ("ATuple", _) => Err(arity_error(name, "two types")),
+        ("Seq", [Term::Num(n), t]) => Ok(SeqType::Seq {n: *n, elem_type: elem(t)?}),
+        ("Seq", _) => Err(arity_error(name, "a length and a type")),
+        _ => Err(format!("{} isn't a Sequence type", name).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_parses_back() {
+        let st_type = Type::TSeq {n: 2, i: 1, elem_type: Box::from(
+            Type::STuple {n: 3, elem_type: Box::from(
+                Type::ATuple {left: Box::from(Type::Int8), right: Box::from(
+                    Type::ATuple {left: Box::from(Type::Bit), right: Box::from(Type::UInt32)})})})};
+        assert_eq!(load_any_type(st_type.to_string().as_bytes(), TypeLanguage::Seq).unwrap(),
+                   AnyType::SpaceTime(st_type));
+    }
+
+    #[test]
+    fn test_protobuf_language() {
+        let seq_type = SeqType::Seq {n: 4, elem_type: Box::from(SeqType::Int16)};
+        let saved = serialize_types::save_type(&seq_type);
+        assert_eq!(load_any_type(&saved, TypeLanguage::Seq).unwrap(), AnyType::Seq(seq_type));
+    }
+
+    #[test]
+    fn test_text_errors() {
+        let err = |text: &str| load_any_type(text.as_bytes(), TypeLanguage::SpaceTime).unwrap_err().to_string();
+        assert_eq!(err("TSeq 2 UInt8"), "TSeq takes a length, a number of invalid clocks, and a type");
+        assert_eq!(err("SSeq 2 (UInt8"), "missing ) in type");
+        assert_eq!(err("Float"), "Float isn't a Space-Time type");
+    }
+}
@@ -0,0 +1,385 @@
+//! load Sequence values from protobuf, JSON, textual, or container files,
+//! detecting the file's format from its contents
+use super::sequence::types::Type as SeqType;
+use super::sequence::atoms::Atom;
+use super::sequence::serialize_values::{try_load_value, save_value, split_seq_value, SerializableSeqValue};
+use super::type_files::{load_any_type, AnyType, TypeLanguage};
+use std::error::Error;
+use std::io::Write;
+
+/// The formats of a Sequence value file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ValueFormat {
+    Protobuf,
+    /// JSON numbers, booleans, and arrays, with tuples as two element arrays
+    Json,
+    /// A literal such as `[(1, True), (2, False)]`, with tuples in brackets or parentheses
+    Text,
+    /// A `#aetherling` header line with the value's type, followed by the value in another format
+    Container
+}
+
+const CONTAINER_MAGIC: &[u8] = b"#aetherling ";
+
+/// A Sequence value from a file, with the type from its header if it's a container
+pub struct ValueFile {
+    pub value: Box<dyn SerializableSeqValue>,
+    pub header_type: Option<AnyType>
+}
+
+/// Detect the format of a Sequence value file.
+/// Protobuf values never start with whitespace, brackets, digits, or letters.
+///
+/// # Examples
+///
+/// ```
+/// use aetherling::languages::value_files::{detect_value_format, ValueFormat};
+/// use aetherling::languages::sequence::serialize_values::save_value;
+///
+/// assert_eq!(detect_value_format(&save_value(&vec!(1, 2))), ValueFormat::Protobuf);
+/// assert_eq!(detect_value_format(b"[1, 2]"), ValueFormat::Json);
+/// assert_eq!(detect_value_format(b"[(1, True)]"), ValueFormat::Text);
+/// assert_eq!(detect_value_format(b"#aetherling Seq 2 UInt8\n[1, 2]"), ValueFormat::Container);
+/// ```
+pub fn detect_value_format(src: &[u8]) -> ValueFormat {
+    if src.starts_with(CONTAINER_MAGIC) {
+        return ValueFormat::Container;
+    }
+    let first = src.iter().find(|b| !b.is_ascii_whitespace());
+    match first {
+        Some(b) if b.is_ascii_alphanumeric() || b"[(-".contains(b) => {
+            if serde_json::from_slice::<serde_json::Value>(src).is_ok() {
+                ValueFormat::Json
+            } else {
+                ValueFormat::Text
+            }
+        }
+        _ => ValueFormat::Protobuf
+    }
+}
+
+/// Load a Sequence value from a file in any `ValueFormat`.
+/// JSON and textual values are checked against `seq_type`, or the container's type if
+/// there's no `seq_type`. Protobuf values carry their own atom types, so only their shape is checked.
+///
+/// # Examples
+///
+/// ```
+/// use aetherling::languages::value_files::load_any_value;
+/// use aetherling::languages::sequence::serialize_values::{save_value, SerializableSeqValue};
+/// use aetherling::languages::sequence::types::Type as SeqType;
+/// let seq_type = SeqType::Seq {n: 2, elem_type: Box::from(
+///     SeqType::ATuple {left: Box::from(SeqType::UInt8), right: Box::from(SeqType::Bit)})};
+/// let loaded = load_any_value(b"[(1, True), (2, False)]", Some(&seq_type)).unwrap();
+///
+/// assert_eq!(save_value(&loaded.value), save_value(&vec!((1u32, true), (2u32, false))));
+/// assert!(load_any_value(b"[(1, True), (256, False)]", Some(&seq_type)).is_err());
+/// ```
+pub fn load_any_value(src: &[u8], seq_type: Option<&SeqType>) -> Result<ValueFile, Box<dyn Error>> {
+    let format = detect_value_format(src);
+    if format == ValueFormat::Container {
+        let header_end = src.iter().position(|b| *b == b'\n').ok_or("container without a value")?;
+        let header = std::str::from_utf8(&src[CONTAINER_MAGIC.len()..header_end])
+            .map_err(|_| "container header isn't UTF-8")?;
+        let header_type = load_any_type(header.trim().as_bytes(), TypeLanguage::SpaceTime)?;
+        let body = &src[header_end + 1..];
+        if detect_value_format(body) == ValueFormat::Container {
+            return Err("container holds another container".into());
+        }
+        let container_seq_type = header_type.to_seq_type();
+        let loaded = load_any_value(body, Some(seq_type.unwrap_or(&container_seq_type)))?;
+        return Ok(ValueFile { value: loaded.value, header_type: Some(header_type) });
+    }
+
+    let value = match format {
+        ValueFormat::Protobuf => {
+            let value = try_load_value(&src)?;
+            if let Some(seq_type) = seq_type {
+                check_value_shape(&value, seq_type)?;
+            }
+            value
+        }
+        _ => {
+            let seq_type = seq_type.ok_or("JSON and textual values need a type")?;
+            literal_to_value(&load_literal(src, format)?, seq_type)?
+        }
+    };
+    Ok(ValueFile { value, header_type: None })
+}
+
+/// Load a Seq of frames, such as an imported video, from a file in any `ValueFormat`,
+/// checking each frame against `frame_type`. The number of frames comes from the file.
+///
+/// # Examples
+///
+/// ```
+/// use aetherling::languages::value_files::load_any_frames;
+/// use aetherling::languages::sequence::types::Type as SeqType;
+/// let frame_type = SeqType::Seq {n: 2, elem_type: Box::from(SeqType::UInt8)};
+///
+/// assert_eq!(load_any_frames(b"[[1, 2], [3, 4], [5, 6]]", &frame_type).unwrap().len(), 3);
+/// assert_eq!(load_any_frames(b"[[1, 2], [3, 4, 5]]", &frame_type).err().unwrap().to_string(),
+///            "expected 2 elements of a Seq 2 UInt8 but got 3");
+/// ```
+pub fn load_any_frames(src: &[u8], frame_type: &SeqType) -> Result<Vec<Box<dyn SerializableSeqValue>>, Box<dyn Error>> {
+    let not_frames = "the values aren't a Seq of frames";
+    let format = detect_value_format(src);
+    let value = match format {
+        ValueFormat::Json | ValueFormat::Text => {
+            let literal = load_literal(src, format)?;
+            let frames = match &literal {
+                Literal::List(elems) => elems.len() as u32,
+                _ => return Err(not_frames.into())
+            };
+            literal_to_value(&literal, &SeqType::Seq {n: frames, elem_type: Box::new(frame_type.clone())})?
+        }
+        // protobuf values and containers have their own types, so check each frame's shape
+        _ => load_any_value(src, None)?.value
+    };
+    let frames = split_seq_value(&value).ok_or(not_frames)?;
+    for (idx, frame) in frames.iter().enumerate() {
+        check_value_shape(frame, frame_type).map_err(|e| format!("frame {}: {}", idx, e))?;
+    }
+    Ok(frames)
+}
+
+/// Check that a value has a Sequence type's number of atoms, and that each atom has the
+/// type's tuple structure. Integer atoms aren't checked against their type's range.
+///
+/// # Examples
+///
+/// ```
+/// use aetherling::languages::value_files::check_value_shape;
+/// use aetherling::languages::sequence::types::Type as SeqType;
+/// let seq_type = SeqType::Seq {n: 2, elem_type: Box::from(
+///     SeqType::ATuple {left: Box::from(SeqType::UInt8), right: Box::from(SeqType::Bit)})};
+///
+/// assert!(check_value_shape(&vec!((1, true), (2, false)), &seq_type).is_ok());
+/// assert!(check_value_shape(&vec!((1, true)), &seq_type).is_err());
+/// assert!(check_value_shape(&vec!(1, 2), &seq_type).is_err());
+/// ```
+pub fn check_value_shape<T: SerializableSeqValue + ?Sized>(value: &T, seq_type: &SeqType) -> Result<(), Box<dyn Error>> {
+    let mut atoms = Vec::new();
+    value.convert_to_flat_atoms(&mut atoms);
+    let expected: usize = seq_type.dims().iter().map(|n| *n as usize).product();
+    if atoms.len() != expected {
+        return Err(format!("expected {} atoms of a {} but got {}", expected, seq_type, atoms.len()).into());
+    }
+    atoms.iter().try_for_each(|atom| check_atom_shape(atom, seq_type.atom_type()))
+}
+
+fn check_atom_shape(atom: &Atom, atom_type: &SeqType) -> Result<(), Box<dyn Error>> {
+    match (atom_type, atom) {
+        (SeqType::Unit, Atom::Unit) | (SeqType::Bit, Atom::Bit(_)) => Ok(()),
+        (SeqType::ATuple {left, right}, Atom::Tuple(l, r)) => {
+            check_atom_shape(l, left)?;
+            check_atom_shape(r, right)
+        }
+        (SeqType::Unit, _) | (SeqType::Bit, _) | (SeqType::ATuple {..}, _) | (_, Atom::Unit) |
+        (_, Atom::Bit(_)) | (_, Atom::Tuple(_, _)) =>
+            Err(format!("expected a {} but got {:?}", atom_type, atom).into()),
+        _ => Ok(())
+    }
+}
+
+/// Write a Sequence value as a container: a header with its type, then its protobuf
+///
+/// # Examples
+///
+/// ```
+/// use aetherling::languages::value_files::{load_any_value, write_container};
+/// use aetherling::languages::type_files::AnyType;
+/// use aetherling::languages::space_time::types::Type;
+/// let st_type = Type::TSeq {n: 2, i: 0, elem_type: Box::from(Type::Int8)};
+/// let mut builder = Vec::new();
+/// write_container(&AnyType::SpaceTime(st_type.clone()), &vec!(-1, 1), &mut builder).unwrap();
+///
+/// assert!(builder.starts_with(b"#aetherling TSeq 2 0 Int8\n"));
+/// assert_eq!(load_any_value(&builder, None).unwrap().header_type, Some(AnyType::SpaceTime(st_type)));
+/// ```
+pub fn write_container<T: SerializableSeqValue, W: Write>(any_type: &AnyType, value: &T,
+                                                          sink: &mut W) -> Result<(), Box<dyn Error>> {
+    let type_str = match any_type {
+        AnyType::Seq(t) => t.to_string(),
+        AnyType::SpaceTime(t) => t.to_string()
+    };
+    sink.write_all(CONTAINER_MAGIC)?;
+    writeln!(sink, "{}", type_str)?;
+    sink.write_all(&save_value(value))?;
+    sink.flush()?;
+    Ok(())
+}
+
+/// A JSON or textual value, before checking it against a type
+#[derive(Debug, Clone, PartialEq)]
+enum Literal {
+    Num(i64),
+    Bool(bool),
+    /// A bracketed list, which is a Seq or a tuple
+    List(Vec<Literal>),
+    /// A parenthesized pair, which is only a tuple
+    Tuple(Box<Literal>, Box<Literal>)
+}
+
+fn load_literal(src: &[u8], format: ValueFormat) -> Result<Literal, Box<dyn Error>> {
+    if format == ValueFormat::Json {
+        json_to_literal(&serde_json::from_slice(src)?)
+    } else {
+        parse_literal(std::str::from_utf8(src).map_err(|_| "textual value isn't UTF-8")?)
+    }
+}
+
+fn json_to_literal(json: &serde_json::Value) -> Result<Literal, Box<dyn Error>> {
+    match json {
+        serde_json::Value::Bool(b) => Ok(Literal::Bool(*b)),
+        serde_json::Value::Number(n) => n.as_i64().map(Literal::Num)
+            .ok_or_else(|| format!("{} isn't an integer", n).into()),
+        serde_json::Value::Array(elems) =>
+            elems.iter().map(json_to_literal).collect::<Result<_, _>>().map(Literal::List),
+        _ => Err(format!("{} isn't a number, boolean, or array", json).into())
+    }
+}
+
+fn tokenize(text: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut cur = String::new();
+    for c in text.chars() {
+        if c.is_whitespace() || "[](),".contains(c) {
+            if !cur.is_empty() {
+                tokens.push(std::mem::take(&mut cur));
+            }
+            if !c.is_whitespace() {
+                tokens.push(c.to_string());
+            }
+        } else {
+            cur.push(c);
+        }
+    }
+    if !cur.is_empty() {
+        tokens.push(cur);
+    }
+    tokens
+}
+
+fn parse_literal(text: &str) -> Result<Literal, Box<dyn Error>> {
+    let tokens = tokenize(text);
+    let mut pos = 0;
+    let literal = parse_literal_at(&tokens, &mut pos)?;
+    match tokens.get(pos) {
+        None => Ok(literal),
+        Some(t) => Err(format!("unexpected {} in value", t).into())
+    }
+}
+
+fn parse_literal_at(tokens: &[String], pos: &mut usize) -> Result<Literal, Box<dyn Error>> {
+    let token = tokens.get(*pos).ok_or("value ended early")?;
+    *pos += 1;
+    match token.as_str() {
+        "[" | "(" => {
+            let close = if token == "[" { "]" } else { ")" };
+            let mut elems = Vec::new();
+            while tokens.get(*pos).map(String::as_str) != Some(close) {
+                if !elems.is_empty() {
+                    match tokens.get(*pos) {
+                        Some(t) if t == "," => *pos += 1,
+                        _ => return Err(format!("missing , or {} in value", close).into())
+                    }
+                }
+                elems.push(parse_literal_at(tokens, pos)?);
+            }
+            *pos += 1;
+            match (close, elems.len()) {
+                ("]", _) => Ok(Literal::List(elems)),
+                (_, 2) => {
+                    let right = elems.pop().unwrap();
+                    let left = elems.pop().unwrap();
+                    Ok(Literal::Tuple(Box::new(left), Box::new(right)))
+                }
+                _ => Err("tuples have two elements".into())
+            }
+        }
+        "true" | "True" => Ok(Literal::Bool(true)),
+        "false" | "False" => Ok(Literal::Bool(false)),
+        _ => token.parse().map(Literal::Num).map_err(|_| format!("unexpected {} in value", token).into())
+    }
+}
+
+fn literal_to_value(literal: &Literal, seq_type: &SeqType) -> Result<Box<dyn SerializableSeqValue>, Box<dyn Error>> {
+    let mismatch = || -> Box<dyn Error> { format!("expected a {} but got {:?}", seq_type, literal).into() };
+    match (seq_type, literal) {
+        (SeqType::Bit, Literal::Bool(b)) => Ok(Box::new(*b)),
+        (SeqType::Bit, Literal::Num(n)) if *n == 0 || *n == 1 => Ok(Box::new(*n == 1)),
+        (SeqType::Int8, Literal::Num(n)) | (SeqType::Int16, Literal::Num(n)) | (SeqType::Int32, Literal::Num(n)) |
+        (SeqType::UInt8, Literal::Num(n)) | (SeqType::UInt16, Literal::Num(n)) | (SeqType::UInt32, Literal::Num(n)) => {
+            let (min, max) = seq_type.range().unwrap();
+            if *n < min || *n > max {
+                Err(format!("{} is out of range for {}", n, seq_type).into())
+            } else if min < 0 {
+                Ok(Box::new(*n as i32))
+            } else {
+                Ok(Box::new(*n as u32))
+            }
+        }
+        (SeqType::ATuple {left, right}, Literal::Tuple(l, r)) =>
+            Ok(Box::new((literal_to_value(l, left)?, literal_to_value(r, right)?))),
+        (SeqType::ATuple {left, right}, Literal::List(elems)) if elems.len() == 2 =>
+            Ok(Box::new((literal_to_value(&elems[0], left)?, literal_to_value(&elems[1], right)?))),
+        (SeqType::Seq {n, elem_type}, Literal::List(elems)) => {
+            if elems.len() != *n as usize {
+                return Err(format!("expected {} elements of a {} but got {}", n, seq_type, elems.len()).into());
+            }
+            let values: Vec<Box<dyn SerializableSeqValue>> =
+                elems.iter().map(|e| literal_to_value(e, elem_type)).collect::<Result<_, _>>()?;
+            Ok(Box::new(values))
+        }
+        (SeqType::Unit, _) => Err("Unit values can't be loaded".into()),
+        _ => Err(mismatch())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seq_of(n: u32, elem_type: SeqType) -> SeqType {
+        SeqType::Seq {n, elem_type: Box::from(elem_type)}
+    }
+
+    #[test]
+    fn test_json_and_text_match_protobuf() {
+        let seq_type = seq_of(2, seq_of(2, SeqType::Int16));
+        let expected = save_value(&vec!(vec!(-3, 4), vec!(5, -6)));
+        for src in [&b"[[-3, 4], [5, -6]]"[..], b"  [ [-3,4] , [5,-6] ]\n", &expected] {
+            assert_eq!(save_value(&load_any_value(src, Some(&seq_type)).unwrap().value), expected);
+        }
+    }
+
+    #[test]
+    fn test_value_errors() {
+        let err = |src: &[u8], seq_type: &SeqType| load_any_value(src, Some(seq_type)).err().unwrap().to_string();
+        assert_eq!(err(b"[1, 2, 3]", &seq_of(2, SeqType::UInt8)), "expected 2 elements of a Seq 2 UInt8 but got 3");
+        assert_eq!(err(b"[-1]", &seq_of(1, SeqType::UInt8)), "-1 is out of range for UInt8");
+        assert_eq!(err(b"[(1 2)]", &seq_of(1, SeqType::UInt8)), "missing , or ) in value");
+        assert_eq!(load_any_value(b"[1]", None).err().unwrap().to_string(), "JSON and textual values need a type");
+    }
+
+    #[test]
+    fn test_frames_of_protobuf_and_container() {
+        let frame_type = seq_of(2, SeqType::UInt8);
+        let frames = save_value(&vec!(vec!(1, 2, 3), vec!(4, 5, 6)));
+        assert_eq!(load_any_frames(&frames, &frame_type).err().unwrap().to_string(),
+                   "frame 0: expected 2 atoms of a Seq 2 UInt8 but got 3");
+        let frames = save_value(&vec!(vec!(1), vec!(4)));
+        assert!(load_any_frames(&frames, &frame_type).is_err());
+        let container = b"#aetherling Seq 2 (Seq 2 UInt8)\n[[1, 2], [3, 4]]";
+        assert_eq!(load_any_frames(container, &frame_type).unwrap().len(), 2);
+        assert_eq!(load_any_frames(b"7", &frame_type).err().unwrap().to_string(), "the values aren't a Seq of frames");
+    }
+
+    #[test]
+    fn test_container_with_text_body() {
+        let loaded = load_any_value(b"#aetherling TSeq 2 0 (ATuple Bit UInt8)\n[[true, 7], (False, 8)]\n", None)
+            .unwrap();
+        assert_eq!(save_value(&loaded.value), save_value(&vec!((true, 7u32), (false, 8u32))));
+    }
+}
@@ -1,28 +1,859 @@
 pub mod languages;
+pub mod formats;
+pub mod cli;
+pub mod batch;
 use languages::space_time::serialize;
+use languages::space_time::types::Type;
+use languages::space_time::port_descriptor::PortDescriptor;
 use languages::sequence::serialize_values;
+use languages::sequence::serialize_values::{split_seq_value, SerializableSeqValue};
 use languages::seq_value_to_st_value_and_valid_strings;
+use languages::seq_value_to_st_value_and_valid_strings::{
+    convert_seq_val_to_st_atoms_and_valids, STAtomsAndValids, StreamConfig
+};
+use languages::parallel::{convert_seq_val_to_st_atoms_and_valids_parallel, convert_seq_vals_to_st_stream_parallel};
+use languages::atom_format::TextConfig;
+use languages::type_files::{load_any_type, AnyType, TypeLanguage};
+use languages::value_files::{load_any_frames, load_any_value, write_container};
+use languages::sequence::gen_values::{gen_value, Pattern};
+use languages::sequence::types::Type as SeqType;
+use languages::ports::{convert_seq_ports_to_st_ports, SeqPort, STPort};
+use formats::readmem::ReadmemConfig;
+use formats::vcd::VcdConfig;
+use formats::fault::FaultConfig;
+use formats::testbench::TestbenchConfig;
+use formats::pnm::{ImageLayout, RenderConfig};
+use formats::y4m::{ChromaLayout, Video, VideoConfig};
+use formats::wav::{Audio, AudioConfig};
+use formats::raw::RawLayout;
+use prost::Message;
+use std::error::Error;
 use std::fs;
 use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Read, Write};
+use std::path::Path;
 
-pub fn run(conf: Config) {
-    let seq_values_proto = fs::read(conf.sequence_values_proto_path)
-        .expect("couldn't read seq values proto file");
-    let st_type_proto = fs::read(conf.space_time_type_proto_path)
-        .expect("couldn't read space time type proto file");
-    let seq_values = serialize_values::load_value(&seq_values_proto);
-    let st_type = serialize::load_type(&st_type_proto);
-    let mut output_values_file = File::create(conf.output_values_csv_path).unwrap();
-    let mut output_valids_file = File::create(conf.output_valids_csv_path).unwrap();
-    seq_value_to_st_value_and_valid_strings::convert_seq_val_to_st_val_and_valid_strings(
-        seq_values, st_type, &mut output_values_file, &mut output_valids_file
-    ).unwrap();
+/// Run a command of the `aetherling` binary.
+/// Returns whether the command's checks passed, or an error if it couldn't run.
+pub fn run(conf: Config) -> Result<Outcome, Box<dyn Error>> {
+    let mut files_read = FilesRead::default();
+    match conf.command {
+        Command::Convert(convert_conf) => run_convert(convert_conf, conf.verbose, &mut files_read),
+        Command::Check(check_conf) => run_check(check_conf, conf.verbose, &mut files_read),
+        Command::Inspect(inspect_conf) => run_inspect(inspect_conf, &mut files_read),
+        Command::Gen(gen_conf) => run_gen(gen_conf, conf.verbose, &mut files_read),
+        Command::GenValues(gen_values_conf) => run_gen_values(gen_values_conf, conf.verbose, &mut files_read),
+        Command::Roundtrip(roundtrip_conf) => run_roundtrip(roundtrip_conf, conf.verbose, &mut files_read),
+        Command::Batch(batch_conf) => run_batch(batch_conf, conf.verbose, &mut files_read),
+        Command::Import(import_conf) => run_import(import_conf, conf.verbose, &mut files_read),
+        Command::Export(export_conf) => run_export(export_conf, conf.verbose, &mut files_read)
+    }
 }
 
+/// The options for a run of the `aetherling` binary
 #[derive(Debug, PartialEq)]
 pub struct Config {
+    pub command: Command,
+    /// Print what the command did to stderr
+    pub verbose: bool
+}
+
+/// A command of the `aetherling` binary and its options
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Convert(ConvertConfig),
+    Check(CheckConfig),
+    Inspect(InspectConfig),
+    Gen(GenConfig),
+    GenValues(GenValuesConfig),
+    Roundtrip(RoundtripConfig),
+    Batch(BatchConfig),
+    Import(ImportConfig),
+    Export(ExportConfig)
+}
+
+/// Whether a command's checks passed. Commands without checks always pass.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outcome {
+    Pass,
+    Fail
+}
+
+/// The file formats that `convert` can write a Space-Time value as
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    /// a values string and a valids string, in `[...]` lists
+    Text,
+    /// a CSV table with one row per clock
+    Csv,
+    /// a Value Change Dump
+    Vcd,
+    /// `$readmemh`/`$readmemb` memory files for values and valids
+    Readmem,
+    /// NumPy `.npy` arrays for values and valids
+    Npy
+}
+
+impl OutputFormat {
+    /// The names of the formats on the command line
+    pub const NAMES: [&'static str; 5] = ["text", "csv", "vcd", "readmem", "npy"];
+
+    pub fn from_name(name: &str) -> Option<OutputFormat> {
+        match name {
+            "text" => Some(OutputFormat::Text),
+            "csv" => Some(OutputFormat::Csv),
+            "vcd" => Some(OutputFormat::Vcd),
+            "readmem" => Some(OutputFormat::Readmem),
+            "npy" => Some(OutputFormat::Npy),
+            _ => None
+        }
+    }
+
+    /// Whether the format writes the valids to a separate file from the values
+    pub fn has_valids_file(&self) -> bool {
+        matches!(self, OutputFormat::Text | OutputFormat::Readmem | OutputFormat::Npy)
+    }
+}
+
+/// Convert a Sequence value to a Space-Time value and write it in a file format
+#[derive(Debug, PartialEq)]
+pub struct ConvertConfig {
+    /// A protobuf, JSON, textual, or container value, or `-` for stdin
     pub sequence_values_proto_path: String,
-    pub space_time_type_proto_path: String,
-    pub output_values_csv_path: String,
-    pub output_valids_csv_path: String
+    /// A protobuf, JSON, or textual type. If missing, the values must be a container.
+    pub space_time_type_proto_path: Option<String>,
+    pub output_format: OutputFormat,
+    pub output_values_path: String,
+    /// The valids file, for formats that write the valids separately
+    pub output_valids_path: Option<String>,
+    /// Write the valids in the values file. The csv and vcd formats always do.
+    pub interleave: bool,
+    /// Treat the values as a Seq of frames, such as an imported video, and convert them
+    /// with the type one after another
+    pub stream: Option<StreamConfig>,
+    /// Split tuple atoms into one stream per field, such as `lane_left_right`. The text, readmem,
+    /// and npy formats write each field to its own file, named with the field's suffix.
+    pub split_tuples: bool,
+    /// The threads to convert on. If missing, one per available thread.
+    pub threads: Option<usize>,
+    /// The options for the text and CSV formats
+    pub text: TextConfig,
+    pub readmem: ReadmemConfig,
+    pub vcd: VcdConfig
+}
+
+/// Compare a simulation's output CSV table against an expected CSV table
+#[derive(Debug, PartialEq)]
+pub struct CheckConfig {
+    pub expected_csv_path: String,
+    pub actual_csv_path: String
+}
+
+/// Print the normalized form, widths, clocks, and port layout of a Sequence or Space-Time type
+#[derive(Debug, PartialEq)]
+pub struct InspectConfig {
+    /// A protobuf, JSON, or textual type
+    pub type_path: String,
+    /// The language of a protobuf type
+    pub protobuf_language: TypeLanguage,
+    /// Print everything as JSON rather than text
+    pub json: bool
+}
+
+/// Generate a test harness that drives a circuit's inputs and checks its outputs
+#[derive(Debug, PartialEq)]
+pub struct GenConfig {
+    pub harness: Harness,
+    pub inputs: Vec<PortFiles>,
+    pub outputs: Vec<PortFiles>,
+    /// The test file for a `fault` test, or the directory for a testbench and its memory files
+    pub output_path: String
+}
+
+/// A kind of test harness
+#[derive(Debug, PartialEq)]
+pub enum Harness {
+    Fault(FaultConfig),
+    Testbench(TestbenchConfig)
+}
+
+/// Generate a Sequence value of a type in a pattern, such as seeded random atoms
+#[derive(Debug, PartialEq)]
+pub struct GenValuesConfig {
+    /// A protobuf, JSON, or textual type. Space-Time types are erased to their Sequence type.
+    pub type_path: String,
+    /// The language of a protobuf type
+    pub protobuf_language: TypeLanguage,
+    pub pattern: Pattern,
+    /// Write a container with the type rather than a bare protobuf
+    pub container: bool,
+    pub output_path: String
+}
+
+/// The Sequence value and Space-Time type files of a named port
+#[derive(Debug, Clone, PartialEq)]
+pub struct PortFiles {
+    pub name: String,
+    pub sequence_values_proto_path: String,
+    pub space_time_type_proto_path: String
+}
+
+/// Load and save Sequence values and Space-Time types, and check that they're unchanged
+#[derive(Debug, PartialEq)]
+pub struct RoundtripConfig {
+    pub sequence_values_proto_path: Option<String>,
+    pub space_time_type_proto_path: Option<String>
+}
+
+/// Run the conversions in a TOML or JSON manifest in parallel
+#[derive(Debug, PartialEq)]
+pub struct BatchConfig {
+    pub manifest_path: String,
+    /// The number of jobs to run at once, overriding the manifest's
+    pub threads: Option<usize>,
+    /// A JSON file for every job's result
+    pub report_path: Option<String>
+}
+
+/// Read a media file as a Sequence value
+#[derive(Debug, PartialEq)]
+pub struct ImportConfig {
+    pub source: ImportSource,
+    /// The media file, or `-` for stdin
+    pub input_path: String,
+    /// The protobuf value file, or `-` for stdout
+    pub output_path: String,
+    /// Write a container with the value's type rather than a bare protobuf
+    pub container: bool,
+    /// A file for the value's Sequence type as text
+    pub type_output_path: Option<String>
+}
+
+/// A kind of media file and how to lay it out as a Sequence value
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImportSource {
+    /// A PGM or PPM image
+    Image(ImageLayout),
+    /// A Y4M video, as a Seq of frames
+    Video(ChromaLayout, ImageLayout),
+    /// A PCM WAV file, as a Seq of samples or of `ATuple left right` stereo samples
+    Audio,
+    /// A raw buffer laid out as the TOML or JSON descriptor at a path says
+    Raw { descriptor_path: String, layout: ImageLayout }
+}
+
+/// Write a Sequence value, such as a circuit's output, as a media file
+#[derive(Debug, PartialEq)]
+pub struct ExportConfig {
+    pub sink: ExportSink,
+    /// A protobuf, JSON, textual, or container value, or `-` for stdin
+    pub values_path: String,
+    /// A protobuf, JSON, or textual type. If missing, the values must be protobuf or a container.
+    pub type_path: Option<String>,
+    /// The media file, or `-` for stdout
+    pub output_path: String
+}
+
+/// A kind of media file and how to write a Sequence value as it
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExportSink {
+    /// A PGM or PPM image
+    Image(ImageExport),
+    /// A Y4M video, from a Seq of frames. A width or height of 0 comes from a
+    /// `Seq frames (Seq height (Seq width pixel))` type.
+    Video(VideoConfig),
+    /// A PCM WAV file, from a Seq of samples or of `ATuple left right` stereo samples
+    Audio(AudioConfig),
+    /// A raw buffer laid out as the TOML or JSON descriptor at a path says
+    Raw { descriptor_path: String }
+}
+
+/// The options for writing a Sequence value as an image
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageExport {
+    /// A width or height of 0 comes from the value's `Seq height (Seq width pixel)` type
+    pub render: RenderConfig,
+    /// Write a P2 or P3 image rather than a P5 or P6 image
+    pub ascii: bool,
+    /// A reference value or image to compare the value against
+    pub reference_path: Option<String>,
+    /// The image of the difference between the value and the reference
+    pub diff_output_path: Option<String>
+}
+
+/// The files one run of a command has read
+#[derive(Debug, Default)]
+struct FilesRead {
+    /// Set once stdin is read, since a second read would see an empty stream
+    stdin_read: bool
+}
+
+/// Read a file, or stdin if the path is `-`
+fn read_file(files_read: &mut FilesRead, path: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    if path == "-" {
+        if files_read.stdin_read {
+            return Err("only one input can be stdin".into());
+        }
+        files_read.stdin_read = true;
+        let mut src = Vec::new();
+        io::stdin().read_to_end(&mut src).map_err(|e| format!("couldn't read stdin: {}", e))?;
+        return Ok(src);
+    }
+    fs::read(path).map_err(|e| format!("couldn't read {}: {}", path, e).into())
+}
+
+/// Create a file, or write to stdout if the path is `-`
+fn create_file(path: &str) -> Result<BufWriter<Box<dyn Write>>, Box<dyn Error>> {
+    if path == "-" {
+        return Ok(BufWriter::new(Box::new(io::stdout())));
+    }
+    File::create(path).map(|f| BufWriter::new(Box::new(f) as Box<dyn Write>))
+        .map_err(|e| format!("couldn't create {}: {}", path, e).into())
+}
+
+/// Load a protobuf, JSON, or textual type, with a Sequence type fully parallelized
+fn load_st_type(files_read: &mut FilesRead, path: &str) -> Result<Type, Box<dyn Error>> {
+    let st_type = load_any_type(&read_file(files_read, path)?, TypeLanguage::SpaceTime)?.to_st_type();
+    check_st_type_size(&st_type)?;
+    Ok(st_type)
+}
+
+/// Check that a Space-Time type's clocks and lanes fit in a u32
+fn check_st_type_size(st_type: &Type) -> Result<(), Box<dyn Error>> {
+    if st_type.checked_clocks().is_none() || st_type.checked_atoms_per_valid().is_none() {
+        return Err(format!("{} has too many clocks or lanes", st_type).into());
+    }
+    Ok(())
+}
+
+fn run_convert(conf: ConvertConfig, verbose: bool, files_read: &mut FilesRead) -> Result<Outcome, Box<dyn Error>> {
+    // read the values first, so that they get stdin if both files are `-`
+    let seq_src = read_file(files_read, &conf.sequence_values_proto_path)?;
+    let st_type = match &conf.space_time_type_proto_path {
+        Some(path) => Some(load_st_type(files_read, path)?),
+        None => None
+    };
+    // when streaming frames, the type is each frame's type, and each frame is checked against it
+    let (st_type, seq_vals) = match (st_type, &conf.stream) {
+        (Some(st_type), Some(_)) => {
+            let frames = load_any_frames(&seq_src, &st_type.to_seq_type())?;
+            (st_type, frames)
+        }
+        (None, Some(_)) => return Err("streaming frames needs the frames' type file".into()),
+        (st_type, None) => {
+            let seq_file = load_any_value(&seq_src, st_type.as_ref().map(Type::to_seq_type).as_ref())?;
+            let st_type = match (st_type, &seq_file.header_type) {
+                (Some(st_type), _) => st_type,
+                (None, Some(header_type)) => {
+                    let st_type = header_type.to_st_type();
+                    check_st_type_size(&st_type)?;
+                    st_type
+                }
+                (None, None) => return Err("the values need a type file or a container header".into())
+            };
+            (st_type, vec!(seq_file.value))
+        }
+    };
+    if conf.interleave && !matches!(conf.output_format, OutputFormat::Text | OutputFormat::Csv | OutputFormat::Vcd) {
+        return Err("only the text, csv, and vcd formats can interleave the valids".into());
+    }
+    let valids_path = if conf.interleave || !conf.output_format.has_valids_file() {
+        None
+    } else {
+        match &conf.output_valids_path {
+            Some(path) if path == "-" && conf.output_values_path == "-" =>
+                return Err("the values and valids can't both be stdout, interleave them instead".into()),
+            Some(path) => Some(path),
+            None => return Err("this output format needs a valids file".into())
+        }
+    };
+    let split_fields = conf.split_tuples && st_type.atom_type().atom_fields().len() > 1;
+    if split_fields && conf.output_values_path == "-"
+        && !matches!(conf.output_format, OutputFormat::Csv | OutputFormat::Vcd) {
+        return Err("split tuple fields need a values file for each field, not stdout".into());
+    }
+
+    let threads = conf.threads.unwrap_or_else(languages::parallel::available_threads);
+    let st_vals = match &conf.stream {
+        Some(stream_conf) => convert_seq_vals_to_st_stream_parallel(seq_vals, &st_type, stream_conf, threads),
+        None => {
+            let seq_val = seq_vals.into_iter().next().expect("one value when not streaming");
+            if threads <= 1 {
+                convert_seq_val_to_st_atoms_and_valids(seq_val, &st_type)
+            } else {
+                convert_seq_val_to_st_atoms_and_valids_parallel(seq_val, &st_type, threads)
+            }
+        }
+    };
+    let clocks = st_vals.valids.len();
+    if split_fields {
+        let port = STPort { name: String::from("lane"), st_type, st_vals };
+        write_st_fields(&port.split_tuple_fields(), &conf, valids_path)?;
+    } else {
+        let mut output_values_file = create_file(&conf.output_values_path)?;
+        let mut output_valids_file = match valids_path {
+            Some(path) => Some(create_file(path)?),
+            None => None
+        };
+        write_st_output(&st_vals, &st_type, &conf, &mut output_values_file, output_valids_file.as_mut())?;
+    }
+    if verbose {
+        eprintln!("wrote {} clocks to {}", clocks, conf.output_values_path);
+    }
+    Ok(Outcome::Pass)
+}
+
+/// Write each tuple field's stream. The csv and vcd formats put the fields side by side,
+/// and the other formats write each field's values to a file named with the field's suffix,
+/// such as `out_left.hex`, with the shared valids in the valids file.
+fn write_st_fields(fields: &[STPort], conf: &ConvertConfig, valids_path: Option<&String>) -> Result<(), Box<dyn Error>> {
+    let mut output_valids_file = match valids_path {
+        Some(path) => Some(create_file(path)?),
+        None => None
+    };
+    match conf.output_format {
+        OutputFormat::Csv =>
+            formats::csv::write_st_ports_csv(fields, &conf.text, &mut create_file(&conf.output_values_path)?),
+        OutputFormat::Vcd =>
+            formats::vcd::write_st_ports_vcd(fields, &conf.vcd, &mut create_file(&conf.output_values_path)?),
+        _ => {
+            for (idx, field) in fields.iter().enumerate() {
+                let suffix = field.name.trim_start_matches("lane");
+                let mut values_file = create_file(&field_path(&conf.output_values_path, suffix))?;
+                // the fields share the valids, so only write them once
+                let mut no_valids = BufWriter::new(Box::new(io::sink()) as Box<dyn Write>);
+                let valids_file = match output_valids_file.as_mut() {
+                    Some(valids_file) if idx == 0 => Some(valids_file),
+                    Some(_) => Some(&mut no_valids),
+                    None => None
+                };
+                write_st_output(&field.st_vals, &field.st_type, conf, &mut values_file, valids_file)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Add a suffix to a path's file name before its extension, such as `out.hex` to `out_left.hex`
+fn field_path(path: &str, suffix: &str) -> String {
+    let path = Path::new(path);
+    let stem = path.file_stem().map_or(String::new(), |s| s.to_string_lossy().to_string());
+    let name = match path.extension() {
+        Some(ext) => format!("{}{}.{}", stem, suffix, ext.to_string_lossy()),
+        None => format!("{}{}", stem, suffix)
+    };
+    path.with_file_name(name).to_string_lossy().to_string()
+}
+
+/// Write a Space-Time value in the output format
+fn write_st_output(st_vals: &STAtomsAndValids, st_type: &Type, conf: &ConvertConfig,
+                   output_values_file: &mut BufWriter<Box<dyn Write>>,
+                   output_valids_file: Option<&mut BufWriter<Box<dyn Write>>>) -> Result<(), Box<dyn Error>> {
+    match (conf.output_format, output_valids_file) {
+        (OutputFormat::Text, None) =>
+            seq_value_to_st_value_and_valid_strings::write_st_interleaved_strings(
+                st_vals, st_type, &conf.text, output_values_file)?,
+        (OutputFormat::Text, Some(valids_file)) =>
+            seq_value_to_st_value_and_valid_strings::write_st_val_and_valid_strings(
+                st_vals, st_type, &conf.text, output_values_file, valids_file)?,
+        (OutputFormat::Readmem, Some(valids_file)) =>
+            formats::readmem::write_st_readmem(st_vals, st_type, &conf.readmem,
+                                               output_values_file, valids_file)?,
+        (OutputFormat::Npy, Some(valids_file)) =>
+            formats::npy::write_st_npy(st_vals, st_type, output_values_file, valids_file)?,
+        (OutputFormat::Csv, _) =>
+            formats::csv::write_st_csv(st_vals, st_type, &conf.text, output_values_file)?,
+        (OutputFormat::Vcd, _) =>
+            formats::vcd::write_st_vcd(st_vals, st_type, &conf.vcd, output_values_file)?,
+        _ => unreachable!("formats with a valids file have one unless they're interleaved")
+    }
+    Ok(())
+}
+
+fn run_check(conf: CheckConfig, verbose: bool, files_read: &mut FilesRead) -> Result<Outcome, Box<dyn Error>> {
+    let expected = read_file(files_read, &conf.expected_csv_path)?;
+    let actual = read_file(files_read, &conf.actual_csv_path)?;
+    let mismatches = formats::csv::check_st_csv(expected.as_slice(), actual.as_slice())?;
+    for m in mismatches.iter() {
+        println!("clock {} {}: expected {}, got {}", m.clock, m.column, m.expected, m.actual);
+    }
+    if verbose {
+        eprintln!("{} mismatches", mismatches.len());
+    }
+    Ok(if mismatches.is_empty() { Outcome::Pass } else { Outcome::Fail })
+}
+
+fn run_inspect(conf: InspectConfig, files_read: &mut FilesRead) -> Result<Outcome, Box<dyn Error>> {
+    let any_type = load_any_type(&read_file(files_read, &conf.type_path)?, conf.protobuf_language)?;
+    let seq_type = match &any_type {
+        AnyType::Seq(seq_type) => Some(seq_type.to_string()),
+        AnyType::SpaceTime(_) => None
+    };
+    let st_type = any_type.to_st_type();
+    let descriptor = PortDescriptor::new(&st_type);
+    let utilization = if st_type.clocks() == 0 { 1.0 } else {
+        st_type.valid_clocks() as f64 / st_type.clocks() as f64
+    };
+    if conf.json {
+        let report = serde_json::json!({
+            "sequence_type": seq_type,
+            "type": st_type.to_string(),
+            "normalized": st_type.normalize().to_string(),
+            "size": st_type.size(),
+            "clocks": st_type.clocks(),
+            "valid_clocks": st_type.valid_clocks(),
+            "atoms_per_valid": st_type.atoms_per_valid(),
+            "utilization": utilization,
+            "port": descriptor
+        });
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(Outcome::Pass);
+    }
+
+    if let Some(seq_type) = seq_type {
+        println!("sequence type: {}", seq_type);
+    }
+    println!("type: {}", st_type);
+    println!("normalized: {}", st_type.normalize());
+    println!("size: {} bits", st_type.size());
+    println!("clocks: {}", st_type.clocks());
+    println!("valid clocks: {}", st_type.valid_clocks());
+    println!("atoms per valid: {}", st_type.atoms_per_valid());
+    println!("utilization: {:.1}%", utilization * 100.0);
+    println!("port: {} lanes of {} bits, {} bits wide, and a {} bit valid",
+             descriptor.lanes, descriptor.lane_width, descriptor.port_width, descriptor.valid_width);
+    for field in descriptor.fields.iter() {
+        let name = if field.path.is_empty() { String::from("atom") } else { format!("field {}", field.path) };
+        println!("    {}: bits [{}:{}] of each lane, {}", name,
+                 (field.lsb + field.width).saturating_sub(1), field.lsb,
+                 if field.signed { "signed" } else { "unsigned" });
+    }
+    Ok(Outcome::Pass)
+}
+
+/// A port with a Sequence value loaded from a file
+type LoadedSeqPort = SeqPort<Box<dyn SerializableSeqValue>>;
+
+fn load_seq_ports(files_read: &mut FilesRead, ports: &[PortFiles]) -> Result<Vec<LoadedSeqPort>, Box<dyn Error>> {
+    ports.iter().map(|port| {
+        let st_type = load_st_type(files_read, &port.space_time_type_proto_path)?;
+        let seq_file = load_any_value(&read_file(files_read, &port.sequence_values_proto_path)?, Some(&st_type.to_seq_type()))?;
+        Ok(SeqPort { name: port.name.clone(), seq_val: seq_file.value, st_type })
+    }).collect()
+}
+
+fn run_gen(conf: GenConfig, verbose: bool, files_read: &mut FilesRead) -> Result<Outcome, Box<dyn Error>> {
+    let inputs = convert_seq_ports_to_st_ports(load_seq_ports(files_read, &conf.inputs)?)?;
+    let outputs = convert_seq_ports_to_st_ports(load_seq_ports(files_read, &conf.outputs)?)?;
+    match &conf.harness {
+        Harness::Fault(fault_conf) => {
+            let mut test_file = create_file(&conf.output_path)?;
+            formats::fault::write_fault_test(&inputs, &outputs, fault_conf, &mut test_file)?;
+        }
+        Harness::Testbench(tb_conf) => {
+            let dir = Path::new(&conf.output_path);
+            fs::create_dir_all(dir)?;
+            let mut tb_file = create_file(&dir.join(format!("{}.sv", tb_conf.module_name)).to_string_lossy())?;
+            formats::testbench::write_sv_testbench(&inputs, &outputs, tb_conf, &mut tb_file)?;
+            tb_file.flush()?;
+            formats::testbench::write_sv_testbench_memories(&inputs, &outputs, dir)?;
+        }
+    }
+    if verbose {
+        eprintln!("wrote a harness for {} inputs and {} outputs to {}",
+                  inputs.len(), outputs.len(), conf.output_path);
+    }
+    Ok(Outcome::Pass)
+}
+
+fn run_gen_values(conf: GenValuesConfig, verbose: bool, files_read: &mut FilesRead) -> Result<Outcome, Box<dyn Error>> {
+    let any_type = load_any_type(&read_file(files_read, &conf.type_path)?, conf.protobuf_language)?;
+    let seq_type = any_type.to_seq_type();
+    let value = gen_value(&seq_type, &conf.pattern)?;
+    let mut output_file = create_file(&conf.output_path)?;
+    if conf.container {
+        write_container(&any_type, &value, &mut output_file)?;
+    } else {
+        output_file.write_all(&serialize_values::save_value(&value))?;
+        output_file.flush()?;
+    }
+    if verbose {
+        eprintln!("wrote a {} value to {}", seq_type, conf.output_path);
+    }
+    Ok(Outcome::Pass)
+}
+
+fn run_roundtrip(conf: RoundtripConfig, verbose: bool, files_read: &mut FilesRead) -> Result<Outcome, Box<dyn Error>> {
+    let mut outcome = Outcome::Pass;
+    if let Some(path) = &conf.sequence_values_proto_path {
+        let saved = read_file(files_read, path)?;
+        let resaved = serialize_values::save_value(&serialize_values::try_load_value(&saved)?);
+        let same = languages::sequence::proto::ValueSerialized::decode(saved.as_slice())? ==
+            languages::sequence::proto::ValueSerialized::decode(resaved.as_slice())?;
+        report_roundtrip("Sequence value", path, same, verbose, &mut outcome);
+    }
+    if let Some(path) = &conf.space_time_type_proto_path {
+        let saved = read_file(files_read, path)?;
+        let resaved = serialize::save_type(&serialize::try_load_type(&saved)?);
+        let same = languages::space_time::proto::TypeSerialized::decode(saved.as_slice())? ==
+            languages::space_time::proto::TypeSerialized::decode(resaved.as_slice())?;
+        report_roundtrip("Space-Time type", path, same, verbose, &mut outcome);
+    }
+    Ok(outcome)
+}
+
+fn run_batch(conf: BatchConfig, verbose: bool, files_read: &mut FilesRead) -> Result<Outcome, Box<dyn Error>> {
+    let text = String::from_utf8(read_file(files_read, &conf.manifest_path)?)
+        .map_err(|_| format!("{} isn't UTF-8", conf.manifest_path))?;
+    let manifest = batch::parse_manifest(&text, &conf.manifest_path)?;
+    let dir = Path::new(&conf.manifest_path).parent().unwrap_or_else(|| Path::new(""));
+    let threads = conf.threads.or(manifest.threads).unwrap_or_else(languages::parallel::available_threads);
+    let reports = batch::run_manifest(&manifest, dir, threads);
+
+    let failed = reports.iter().filter(|r| !r.ok).count();
+    for r in reports.iter() {
+        match &r.error {
+            None => println!("ok     {} ({:.3}s)", r.name, r.seconds),
+            Some(e) => println!("FAILED {}: {}", r.name, e)
+        }
+    }
+    println!("{} jobs, {} ok, {} failed", reports.len(), reports.len() - failed, failed);
+    if let Some(path) = &conf.report_path {
+        let mut report_file = create_file(path)?;
+        serde_json::to_writer_pretty(&mut report_file, &reports)?;
+        report_file.flush()?;
+        if verbose {
+            eprintln!("wrote the report to {}", path);
+        }
+    }
+    Ok(if failed == 0 { Outcome::Pass } else { Outcome::Fail })
+}
+
+fn run_import(conf: ImportConfig, verbose: bool, files_read: &mut FilesRead) -> Result<Outcome, Box<dyn Error>> {
+    let src = read_file(files_read, &conf.input_path)?;
+    let (value, seq_type) = match &conf.source {
+        ImportSource::Image(layout) => {
+            let image = formats::pnm::read_pnm(&src)?;
+            (image.to_seq_value(*layout), image.seq_type(*layout))
+        }
+        ImportSource::Video(chroma_layout, layout) => {
+            let video = formats::y4m::read_y4m(&src)?;
+            let frames = video.frame_seq_values(*chroma_layout, *layout)?;
+            let frames_type = SeqType::Seq {n: frames.len() as u32,
+                                            elem_type: Box::new(video.frame_seq_type(*chroma_layout, *layout)?)};
+            (Box::new(frames) as Box<dyn SerializableSeqValue>, frames_type)
+        }
+        ImportSource::Audio => {
+            let audio = formats::wav::read_wav(&src)?;
+            (audio.to_seq_value(), audio.seq_type())
+        }
+        ImportSource::Raw { descriptor_path, layout } => {
+            let raw_layout = load_raw_layout(files_read, descriptor_path)?;
+            (formats::raw::read_raw(&src, &raw_layout, *layout)?, raw_layout.seq_type(*layout)?)
+        }
+    };
+    let mut output_file = create_file(&conf.output_path)?;
+    if conf.container {
+        write_container(&AnyType::Seq(seq_type.clone()), &value, &mut output_file)?;
+    } else {
+        output_file.write_all(&serialize_values::save_value(&value))?;
+        output_file.flush()?;
+    }
+    if let Some(path) = &conf.type_output_path {
+        let mut type_file = create_file(path)?;
+        writeln!(type_file, "{}", seq_type)?;
+        type_file.flush()?;
+    }
+    if verbose {
+        eprintln!("wrote a {} value to {}", seq_type, conf.output_path);
+    }
+    Ok(Outcome::Pass)
+}
+
+fn load_raw_layout(files_read: &mut FilesRead, path: &str) -> Result<RawLayout, Box<dyn Error>> {
+    let text = String::from_utf8(read_file(files_read, path)?).map_err(|_| format!("{} isn't UTF-8", path))?;
+    formats::raw::parse_raw_layout(&text, path)
+}
+
+fn run_export(conf: ExportConfig, verbose: bool, files_read: &mut FilesRead) -> Result<Outcome, Box<dyn Error>> {
+    let values_src = read_file(files_read, &conf.values_path)?;
+    let any_type = match &conf.type_path {
+        Some(path) => Some(load_any_type(&read_file(files_read, path)?, TypeLanguage::SpaceTime)?),
+        None => None
+    };
+    let seq_type = any_type.as_ref().map(AnyType::to_seq_type);
+    let seq_file = load_any_value(&values_src, seq_type.as_ref())?;
+    let seq_type = seq_type.or_else(|| seq_file.header_type.as_ref().map(AnyType::to_seq_type));
+    match &conf.sink {
+        ExportSink::Image(image_conf) => {
+            let mut render = image_conf.render.clone();
+            if render.width == 0 || render.height == 0 {
+                match seq_type.as_ref().map(|t| t.dims()).as_deref() {
+                    Some([height, width]) => {
+                        render.height = *height;
+                        render.width = *width;
+                    }
+                    _ => return Err("give the image's width and height, or a Seq height (Seq width pixel) type".into())
+                }
+            }
+            let image = formats::pnm::seq_value_to_image(seq_file.value, &render)?;
+            let mut output_file = create_file(&conf.output_path)?;
+            formats::pnm::write_pnm(&image, !image_conf.ascii, &mut output_file)?;
+            if let (Some(reference_path), Some(diff_path)) = (&image_conf.reference_path, &image_conf.diff_output_path) {
+                let reference_src = read_file(files_read, reference_path)?;
+                // re-read the value, since rendering it consumed it
+                let value = load_any_value(&values_src, seq_type.as_ref())?.value;
+                let reference = match formats::pnm::read_pnm(&reference_src) {
+                    Ok(reference_image) => {
+                        if (reference_image.width, reference_image.height) != (render.width, render.height) {
+                            return Err(format!("the reference is {}x{}, but the image is {}x{}",
+                                               reference_image.width, reference_image.height,
+                                               render.width, render.height).into());
+                        }
+                        reference_image.to_seq_value(ImageLayout::Flat)
+                    }
+                    Err(_) => load_any_value(&reference_src, seq_type.as_ref())?.value
+                };
+                let diff = formats::pnm::seq_values_diff_image(value, reference, &render)?;
+                let mut diff_file = create_file(diff_path)?;
+                formats::pnm::write_pnm(&diff, !image_conf.ascii, &mut diff_file)?;
+                if verbose {
+                    let differing = diff.samples.iter().filter(|s| **s != 0).count();
+                    eprintln!("{} samples differ from {}", differing, reference_path);
+                }
+            }
+            if verbose {
+                eprintln!("wrote a {}x{} image to {}", render.width, render.height, conf.output_path);
+            }
+        }
+        ExportSink::Video(video_conf) => {
+            let mut video_conf = video_conf.clone();
+            if video_conf.width == 0 || video_conf.height == 0 {
+                match (video_conf.chroma_layout, seq_type.as_ref().map(|t| t.dims()).as_deref()) {
+                    (ChromaLayout::Luma, Some([_, height, width])) | (ChromaLayout::Interleaved, Some([_, height, width])) => {
+                        video_conf.height = *height;
+                        video_conf.width = *width;
+                    }
+                    _ => return Err("give the video's width and height, or a \
+                                     Seq frames (Seq height (Seq width pixel)) type of luma or interleaved pixels".into())
+                }
+            }
+            let frames = split_seq_value(&seq_file.value).ok_or("the values aren't a Seq of frames")?;
+            let video = Video::from_seq_values(frames, &video_conf)?;
+            let mut output_file = create_file(&conf.output_path)?;
+            formats::y4m::write_y4m(&video, &mut output_file)?;
+            if verbose {
+                eprintln!("wrote {} {}x{} frames to {}", video.frames.len(), video.width, video.height, conf.output_path);
+            }
+        }
+        ExportSink::Audio(audio_conf) => {
+            let audio = Audio::from_seq_value(&seq_file.value, audio_conf)?;
+            let mut output_file = create_file(&conf.output_path)?;
+            formats::wav::write_wav(&audio, &mut output_file)?;
+            if verbose {
+                eprintln!("wrote {} samples in {} channels to {}", audio.len(), audio.channels, conf.output_path);
+            }
+        }
+        ExportSink::Raw { descriptor_path } => {
+            let raw_layout = load_raw_layout(files_read, descriptor_path)?;
+            let mut output_file = create_file(&conf.output_path)?;
+            formats::raw::write_raw(&seq_file.value, &raw_layout, &mut output_file)?;
+            if verbose {
+                eprintln!("wrote a {}x{} raw buffer to {}", raw_layout.width, raw_layout.height, conf.output_path);
+            }
+        }
+    }
+    Ok(Outcome::Pass)
+}
+
+fn report_roundtrip(what: &str, path: &str, same: bool, verbose: bool, outcome: &mut Outcome) {
+    if !same {
+        println!("{} in {} changed when loaded and saved", what, path);
+        *outcome = Outcome::Fail;
+    } else if verbose {
+        eprintln!("{} in {} is unchanged when loaded and saved", what, path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use languages::space_time::serialize::save_type;
+
+    /// Write `files` to a fresh directory named for `name`, run `f` in it, then remove the directory
+    pub(crate) fn in_temp_dir<T>(name: &str, files: &[(&str, &[u8])], f: impl FnOnce(&Path) -> T) -> T {
+        let dir = std::env::temp_dir().join(format!("aetherling_{}_{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (file_name, contents) in files {
+            fs::write(dir.join(file_name), contents).unwrap();
+        }
+        let result = f(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+        result
+    }
+
+    #[test]
+    fn test_roundtrip_nested_type_and_value() {
+        let st_type = save_type(&Type::TSeq {n: 2, i: 1, elem_type: Box::from(
+            Type::ATuple {left: Box::from(Type::Int8), right: Box::from(Type::Bit)})});
+        let values = serialize_values::save_value(&vec!((1, true), (-2, false)));
+        let outcome = in_temp_dir("roundtrip", &[("type.pb", &st_type), ("values.pb", &values)], |dir| {
+            let path = |name: &str| dir.join(name).to_string_lossy().to_string();
+            run(Config {
+                command: Command::Roundtrip(RoundtripConfig {
+                    sequence_values_proto_path: Some(path("values.pb")),
+                    space_time_type_proto_path: Some(path("type.pb"))
+                }),
+                verbose: false
+            }).unwrap()
+        });
+        assert_eq!(outcome, Outcome::Pass);
+    }
+
+    #[test]
+    fn test_convert_split_tuples_to_readmem() {
+        let st_type = save_type(&Type::TSeq {n: 2, i: 0, elem_type: Box::from(
+            Type::ATuple {left: Box::from(Type::UInt8), right: Box::from(Type::Bit)})});
+        let values = serialize_values::save_value(&vec!((0xab, true), (0x12, false)));
+        let (outcome, left, right, valids, combined_exists) =
+            in_temp_dir("split", &[("type.pb", &st_type), ("values.pb", &values)], |dir| {
+                let path = |name: &str| dir.join(name).to_string_lossy().to_string();
+                let (values, st_type, out, valids) = (path("values.pb"), path("type.pb"), path("out.hex"), path("valids.hex"));
+                let conf = cli::parse_args(vec!("aetherling", "convert", "-f", "readmem", "--split-tuples",
+                                                "--values", &values, "--type", &st_type, "-o", &out,
+                                                "--valids-out", &valids)).unwrap();
+                (run(conf).unwrap(),
+                 fs::read_to_string(path("out_left.hex")).unwrap(),
+                 fs::read_to_string(path("out_right.hex")).unwrap(),
+                 fs::read_to_string(path("valids.hex")).unwrap(),
+                 Path::new(&path("out.hex")).exists())
+            });
+        assert_eq!(outcome, Outcome::Pass);
+        assert_eq!((left.as_str(), right.as_str(), valids.as_str()), ("ab\n12\n", "1\n0\n", "1\n1\n"));
+        assert!(!combined_exists);
+    }
+
+    #[test]
+    fn test_export_image_reference_with_other_dims() {
+        let files: [(&str, &[u8]); 3] = [("values.json", b"[[1, 2]]"), ("type.txt", b"Seq 1 (Seq 2 UInt8)"),
+                                         ("ref.pgm", b"P2 1 2 255 1 2")];
+        let err = in_temp_dir("reference", &files, |dir| {
+            let path = |name: &str| dir.join(name).to_string_lossy().to_string();
+            let (values, seq_type, out) = (path("values.json"), path("type.txt"), path("out.pgm"));
+            let (reference, diff) = (path("ref.pgm"), path("diff.pgm"));
+            let conf = cli::parse_args(vec!("aetherling", "export", "image", "--values", &values, "--type", &seq_type,
+                                            "-o", &out, "--reference", &reference, "--diff-out", &diff)).unwrap();
+            run(conf).unwrap_err().to_string()
+        });
+        assert_eq!(err, "the reference is 1x2, but the image is 2x1");
+    }
+
+    #[test]
+    fn test_only_one_input_can_be_stdin() {
+        let mut files_read = FilesRead { stdin_read: true };
+        assert_eq!(read_file(&mut files_read, "-").unwrap_err().to_string(), "only one input can be stdin");
+        // a new run starts with stdin unread
+        assert!(!FilesRead::default().stdin_read);
+    }
 }
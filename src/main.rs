@@ -1,20 +1,25 @@
-use aetherling::{run, Config};
+use aetherling::{run, Outcome};
+use aetherling::cli::parse_args;
 use std::env;
+use std::process;
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-
-    // 5 as first argument is path to executable
-    if args.len() != 5 {
-        panic!("must have 4 arguments");
-    }
-
-    let conf = Config {
-        sequence_values_proto_path: args[1].clone(),
-        space_time_type_proto_path: args[2].clone(),
-        output_values_csv_path: args[3].clone(),
-        output_valids_csv_path: args[4].clone()
+    let conf = match parse_args(env::args_os()) {
+        Ok(conf) => conf,
+        // help and version messages aren't errors
+        Err(e) if !e.use_stderr() => e.exit(),
+        Err(e) => {
+            eprintln!("{}", e.message);
+            process::exit(2);
+        }
     };
 
-    run(conf);
+    match run(conf) {
+        Ok(Outcome::Pass) => (),
+        Ok(Outcome::Fail) => process::exit(1),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            process::exit(2);
+        }
+    }
 }